@@ -0,0 +1,248 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+
+use crate::timeslot::Timeslot;
+
+/// A set of times, kept as sorted intervals that neither overlap nor touch. Intervals that
+/// overlap or where one ends when the next starts are merged into one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct IntervalSet {
+    intervals: Vec<(DateTime<Tz>, DateTime<Tz>)>,
+}
+
+impl IntervalSet {
+    /// The set of times from `start` to `end`, empty unless `start` is before `end`.
+    pub(crate) fn between(start: DateTime<Tz>, end: DateTime<Tz>) -> IntervalSet {
+        IntervalSet::merged(vec![(start, end)])
+    }
+
+    pub(crate) fn from_timeslots(times: &[Timeslot]) -> IntervalSet {
+        IntervalSet::merged(times.iter().map(|time| (time.start_time(), time.end_time())).collect())
+    }
+
+    /// The intervals as timeslots, in order.
+    pub(crate) fn to_timeslots(&self) -> Vec<Timeslot> {
+        self.intervals
+            .iter()
+            .map(|(start, end)| Timeslot {
+                start: start.to_rfc3339(),
+                end: end.to_rfc3339(),
+            })
+            .collect()
+    }
+
+    /// Times in either set.
+    pub(crate) fn union(&self, other: &IntervalSet) -> IntervalSet {
+        IntervalSet::merged(self.intervals.iter().chain(&other.intervals).copied().collect())
+    }
+
+    /// Times in both sets.
+    pub(crate) fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while let (Some(a), Some(b)) = (self.intervals.get(i), other.intervals.get(j)) {
+            let start = a.0.max(b.0);
+            let end = a.1.min(b.1);
+            if start < end {
+                intervals.push((start, end));
+            }
+            // Move past whichever interval ends first; the other may overlap the next one.
+            if a.1 < b.1 {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        IntervalSet { intervals }
+    }
+
+    /// Times in this set but not in `other`.
+    pub(crate) fn subtract(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = Vec::new();
+        for &(interval_start, end) in &self.intervals {
+            let mut start = interval_start;
+            for &(cut_start, cut_end) in other.intervals.iter().filter(|(cut_start, cut_end)| *cut_start < end && interval_start < *cut_end) {
+                if start < cut_start {
+                    intervals.push((start, cut_start));
+                }
+                start = start.max(cut_end);
+            }
+            if start < end {
+                intervals.push((start, end));
+            }
+        }
+        IntervalSet { intervals }
+    }
+
+    /// Sort the intervals, drop empty ones and merge the ones that overlap or touch.
+    fn merged(mut intervals: Vec<(DateTime<Tz>, DateTime<Tz>)>) -> IntervalSet {
+        intervals.retain(|(start, end)| start < end);
+        intervals.sort();
+        let mut merged: Vec<(DateTime<Tz>, DateTime<Tz>)> = Vec::with_capacity(intervals.len());
+        for (start, end) in intervals {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        IntervalSet { intervals: merged }
+    }
+}
+
+/// Get all remaining available times from opening times and reservations.
+/// Returns a vector of Timeslot structs.
+///
+/// # Example
+/// ```
+/// let opening_times = vec![
+///     Timeslot {
+///         start: "2021-09-01T08:00:00+03:00".to_owned(),
+///         end: "2021-09-01T16:00:00+03:00".to_owned(),
+///     },
+/// ];
+///
+/// let reservations = vec![
+///     Timeslot {
+///         start: "2021-09-01T10:00:00+03:00".to_owned(),
+///         end: "2021-09-01T11:00:00+03:00".to_owned(),
+///     }
+/// ];
+///
+/// let available_times = get_available_times(&opening_times, &reservations);
+///
+/// // Expected output:
+/// assert_eq!(available_times.len(), 2);
+/// assert_eq!(available_times[0].start, "2021-09-01T08:00:00+03:00");
+/// assert_eq!(available_times[0].end, "2021-09-01T10:00:00+03:00");
+/// assert_eq!(available_times[1].start, "2021-09-01T11:00:00+03:00");
+/// assert_eq!(available_times[1].end, "2021-09-01T16:00:00+03:00");
+/// ```
+pub(crate) fn get_available_times(opening_times: &[Timeslot], reservations: &[Timeslot]) -> Vec<Timeslot> {
+    let reserved = IntervalSet::from_timeslots(reservations);
+    opening_times
+        .iter()
+        .map(|opening_time| IntervalSet::between(opening_time.start_time(), opening_time.end_time()).subtract(&reserved))
+        .fold(IntervalSet::default(), |available, open| available.union(&open))
+        .to_timeslots()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timezone;
+    use chrono::TimeZone;
+    use proptest::prelude::*;
+
+    /// A time on 2023-12-01, `minutes` after midnight.
+    fn at(minutes: u32) -> DateTime<Tz> {
+        timezone::zone().with_ymd_and_hms(2023, 12, 1, 0, 0, 0).unwrap() + chrono::Duration::minutes(minutes as i64)
+    }
+
+    fn slot(start: &str, end: &str) -> Timeslot {
+        let parse = |time: &str| {
+            let (hour, minute) = time.split_once(':').unwrap();
+            at(hour.parse::<u32>().unwrap() * 60 + minute.parse::<u32>().unwrap()).to_rfc3339()
+        };
+        Timeslot { start: parse(start), end: parse(end) }
+    }
+
+    /// Times starting and ending at any minute of the day.
+    fn slots(max: usize) -> impl Strategy<Value = Vec<Timeslot>> {
+        prop::collection::vec((0..24 * 60u32, 1..8 * 60u32), 0..max).prop_map(|slots| {
+            slots
+                .into_iter()
+                .map(|(start, length)| Timeslot {
+                    start: at(start).to_rfc3339(),
+                    end: at(start + length).to_rfc3339(),
+                })
+                .collect()
+        })
+    }
+
+    fn overlaps(a: &Timeslot, b: &Timeslot) -> bool {
+        a.start_time() < b.end_time() && b.start_time() < a.end_time()
+    }
+
+    proptest! {
+        #[test]
+        fn available_times_are_not_reserved(opening_times in slots(3), reservations in slots(6)) {
+            for available in get_available_times(&opening_times, &reservations) {
+                prop_assert!(!reservations.iter().any(|reservation| overlaps(&available, reservation)));
+            }
+        }
+
+        #[test]
+        fn available_times_are_within_opening_hours(opening_times in slots(3), reservations in slots(6)) {
+            for available in get_available_times(&opening_times, &reservations) {
+                // Any part of the time is within some opening time.
+                let mut time = available.start_time();
+                while time < available.end_time() {
+                    prop_assert!(opening_times.iter().any(|open| open.start_time() <= time && time < open.end_time()));
+                    time += chrono::Duration::minutes(1);
+                }
+            }
+        }
+
+        #[test]
+        fn available_times_do_not_overlap(opening_times in slots(3), reservations in slots(6)) {
+            let available_times = get_available_times(&opening_times, &reservations);
+            for (i, a) in available_times.iter().enumerate() {
+                for b in &available_times[i + 1..] {
+                    prop_assert!(!overlaps(a, b), "{} overlaps {}", a, b);
+                }
+            }
+        }
+    }
+
+    fn set(times: &[(&str, &str)]) -> IntervalSet {
+        IntervalSet::from_timeslots(&times.iter().map(|(start, end)| slot(start, end)).collect::<Vec<Timeslot>>())
+    }
+
+    #[test]
+    fn union_merges_overlapping_and_touching_times() {
+        let union = set(&[("10:00", "12:00"), ("15:00", "16:00")]).union(&set(&[("11:00", "13:00"), ("16:00", "17:00")]));
+        assert_eq!(union.to_timeslots(), vec![slot("10:00", "13:00"), slot("15:00", "17:00")]);
+        assert_eq!(set(&[("10:00", "12:00")]).union(&IntervalSet::default()), set(&[("10:00", "12:00")]));
+    }
+
+    #[test]
+    fn intersection_keeps_only_shared_times() {
+        let a = set(&[("10:00", "12:00"), ("14:00", "18:00")]);
+        let b = set(&[("11:00", "15:00"), ("16:00", "17:00")]);
+        assert_eq!(
+            a.intersection(&b).to_timeslots(),
+            vec![slot("11:00", "12:00"), slot("14:00", "15:00"), slot("16:00", "17:00")]
+        );
+        // Times that only touch share nothing.
+        assert_eq!(set(&[("10:00", "12:00")]).intersection(&set(&[("12:00", "13:00")])), IntervalSet::default());
+    }
+
+    #[test]
+    fn subtract_cuts_holes_and_ends() {
+        let open = set(&[("10:00", "18:00")]);
+        let reserved = set(&[("09:00", "10:30"), ("12:00", "13:00"), ("17:00", "19:00")]);
+        assert_eq!(open.subtract(&reserved).to_timeslots(), vec![slot("10:30", "12:00"), slot("13:00", "17:00")]);
+        assert_eq!(open.subtract(&set(&[("08:00", "20:00")])), IntervalSet::default());
+        assert_eq!(open.subtract(&IntervalSet::default()), open);
+    }
+
+    #[test]
+    fn reservation_between_whole_hours_blocks_only_its_own_time() {
+        let available = get_available_times(&[slot("10:00", "18:00")], &[slot("10:30", "11:30")]);
+        assert_eq!(available, vec![slot("10:00", "10:30"), slot("11:30", "18:00")]);
+    }
+
+    #[test]
+    fn reservations_past_opening_hours_are_cut_at_closing_and_opening() {
+        let reservations = [slot("08:00", "10:15"), slot("17:45", "20:00")];
+        let available = get_available_times(&[slot("10:00", "18:00")], &reservations);
+        assert_eq!(available, vec![slot("10:15", "17:45")]);
+    }
+
+    #[test]
+    fn back_to_back_reservations_leave_no_gap() {
+        let reservations = [slot("10:00", "11:20"), slot("11:20", "12:40"), slot("12:40", "14:00")];
+        let available = get_available_times(&[slot("10:00", "15:00")], &reservations);
+        assert_eq!(available, vec![slot("14:00", "15:00")]);
+    }
+}
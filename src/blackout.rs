@@ -0,0 +1,86 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::Language;
+use crate::timeslot::Timeslot;
+
+/// Dates whose times are neither notified about nor matched against the wishlist, e.g. a vacation:
+///
+/// ```toml
+/// [[blackout]]
+/// from = "2024-07-01"
+/// to = "2024-07-14"
+/// ```
+///
+/// Both dates are included. Chats can set their own with /blackout.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct Blackout {
+    pub(crate) from: NaiveDate,
+    pub(crate) to: NaiveDate,
+}
+
+impl Blackout {
+    /// Parse the arguments of "/blackout <from> [<to>]", e.g. "2024-07-01 2024-07-14".
+    /// Without `to`, only the one day is blacked out.
+    pub(crate) fn parse(arguments: &[&str]) -> Option<Blackout> {
+        let date = |text: &str| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok();
+        let (from, to) = match arguments {
+            [from] => (date(from)?, date(from)?),
+            [from, to] => (date(from)?, date(to)?),
+            _ => return None,
+        };
+        (from <= to).then_some(Blackout { from, to })
+    }
+
+    /// Whether the time starts on one of the dates.
+    pub(crate) fn covers(&self, time: &Timeslot) -> bool {
+        let date = time.start_time().date_naive();
+        self.from <= date && date <= self.to
+    }
+
+    /// Whether the last date has passed.
+    pub(crate) fn has_passed(&self, today: NaiveDate) -> bool {
+        self.to < today
+    }
+
+    /// The dates in the language's format, e.g. "2024-07-01–2024-07-14".
+    pub(crate) fn localized(&self, language: Language) -> String {
+        let format = language.date_format();
+        if self.from == self.to {
+            return self.from.format(format).to_string();
+        }
+        format!("{}–{}", self.from.format(format), self.to.format(format))
+    }
+}
+
+/// The times not on any of the blacked out dates.
+pub(crate) fn outside(blackouts: &[Blackout], times: &[Timeslot]) -> Vec<Timeslot> {
+    times
+        .iter()
+        .filter(|time| !blackouts.iter().any(|blackout| blackout.covers(time)))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_times_starting_on_blacked_out_dates() {
+        let blackouts = [Blackout::parse(&["2024-07-01", "2024-07-14"]).unwrap(), Blackout::parse(&["2024-07-20"]).unwrap()];
+        let time = |start: &str, end: &str| Timeslot {
+            start: start.to_owned(),
+            end: end.to_owned(),
+        };
+        let times = [
+            time("2024-06-30T10:00:00+03:00", "2024-06-30T12:00:00+03:00"),
+            time("2024-07-14T20:00:00+03:00", "2024-07-14T22:00:00+03:00"),
+            time("2024-07-15T10:00:00+03:00", "2024-07-15T12:00:00+03:00"),
+            time("2024-07-20T10:00:00+03:00", "2024-07-20T12:00:00+03:00"),
+        ];
+        let kept = outside(&blackouts, &times).into_iter().map(|time| time.start).collect::<Vec<_>>();
+        assert_eq!(kept, ["2024-06-30T10:00:00+03:00", "2024-07-15T10:00:00+03:00"]);
+        assert!(Blackout::parse(&["2024-07-14", "2024-07-01"]).is_none());
+    }
+}
@@ -0,0 +1,432 @@
+use chrono::{DateTime, Datelike, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::availability;
+use crate::holidays;
+use crate::i18n::Language;
+use crate::own_reservations;
+use crate::respa::{self, FetchError, Reservation};
+use crate::scheduler::deserialize_time;
+use crate::timeslot::Timeslot;
+use crate::timezone;
+use crate::CONFIG;
+
+/// Credentials for making reservations through the respa API.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct AuthConfig {
+    /// API token of the respa user reservations are made for. RESPA_API_TOKEN overrides this.
+    pub(crate) api_token: String,
+}
+
+/// Limits on what may be booked on the user's behalf, checked before every reservation:
+///
+/// ```toml
+/// [booking]
+/// max_per_week = 2
+/// max_hours = 2
+/// confirm_timeout_secs = 300
+///
+/// [[booking.windows]]
+/// weekdays = ["mon", "tue", "wed", "thu", "fri"]
+/// from = "16:00"
+/// to = "21:00"
+/// ```
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct BookingLimits {
+    /// Most own reservations of a resource starting in the same week, Monday to Sunday.
+    /// 0 means no limit.
+    pub(crate) max_per_week: usize,
+    /// Longest time that may be booked, in hours. 0 means no limit.
+    pub(crate) max_hours: i64,
+    /// Times must be entirely within one of these. Without windows any time may be booked.
+    pub(crate) windows: Vec<BookingWindow>,
+    /// How long the "Confirm" button of a booking prompt works, in seconds. 0 means no timeout.
+    pub(crate) confirm_timeout_secs: i64,
+}
+
+impl Default for BookingLimits {
+    fn default() -> Self {
+        BookingLimits {
+            max_per_week: 0,
+            max_hours: 0,
+            windows: Vec::new(),
+            confirm_timeout_secs: 300,
+        }
+    }
+}
+
+/// Hours of the day times may be booked in. Without weekdays every day matches.
+#[derive(Deserialize)]
+pub(crate) struct BookingWindow {
+    #[serde(default)]
+    pub(crate) weekdays: Vec<Weekday>,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub(crate) from: NaiveTime,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub(crate) to: NaiveTime,
+}
+
+impl BookingWindow {
+    /// Whether all of `time` is within the window.
+    fn contains(&self, time: &Timeslot) -> bool {
+        let date = time.start_time().date_naive();
+        holidays::matches(&self.weekdays, date)
+            && timezone::at(date, self.from) <= time.start_time()
+            && time.end_time() <= timezone::at(date, self.to)
+    }
+}
+
+impl BookingLimits {
+    /// Check the length of a time and that it is within the allowed windows.
+    pub(crate) fn permits(&self, time: &Timeslot) -> Result<(), BookingError> {
+        let minutes = (time.end_time() - time.start_time()).num_minutes();
+        if self.max_hours > 0 && minutes > self.max_hours * 60 {
+            return Err(BookingError::TooLong(self.max_hours));
+        }
+        if !self.windows.is_empty() && !self.windows.iter().any(|window| window.contains(time)) {
+            return Err(BookingError::OutsideWindows);
+        }
+        Ok(())
+    }
+
+    /// Check that booking a time wouldn't exceed the weekly limit, given the own reservations.
+    pub(crate) fn permits_another(&self, time: &Timeslot, booked: &[Timeslot]) -> Result<(), BookingError> {
+        let week = time.start_time().iso_week();
+        let same_week = booked.iter().filter(|booked| booked.start_time().iso_week() == week).count();
+        if self.max_per_week > 0 && same_week >= self.max_per_week {
+            return Err(BookingError::WeeklyLimit(self.max_per_week));
+        }
+        Ok(())
+    }
+
+    /// Whether a booking prompt sent at `asked_at` can no longer be confirmed at `now`.
+    pub(crate) fn has_expired(&self, asked_at: DateTime<Tz>, now: DateTime<Tz>) -> bool {
+        self.confirm_timeout_secs > 0 && now - asked_at > chrono::Duration::seconds(self.confirm_timeout_secs)
+    }
+}
+
+/// Reasons a time could not be booked.
+#[derive(Debug)]
+pub(crate) enum BookingError {
+    /// No API token is configured.
+    NoApiToken,
+    /// The time has already started.
+    Started,
+    /// The time is not free anymore, or never was.
+    NotAvailable,
+    /// Someone else reserved the time between checking it and booking it.
+    Conflict,
+    /// The time is longer than `max_hours` in `[booking]`.
+    TooLong(i64),
+    /// The time is not within the `windows` in `[booking]`.
+    OutsideWindows,
+    /// As many times as `max_per_week` in `[booking]` are already booked for the week.
+    WeeklyLimit(usize),
+    /// Checking the availability or making the reservation failed.
+    Api(FetchError),
+}
+
+impl std::fmt::Display for BookingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BookingError::NoApiToken => write!(f, "no respa API token configured, set api_token in [auth] or RESPA_API_TOKEN"),
+            BookingError::Started => write!(f, "the time has already started"),
+            BookingError::NotAvailable => write!(f, "the time is not available"),
+            BookingError::Conflict => write!(f, "someone else booked the time just now"),
+            BookingError::TooLong(max_hours) => write!(f, "times longer than {} h may not be booked", max_hours),
+            BookingError::OutsideWindows => write!(f, "the time is outside the hours times may be booked in"),
+            BookingError::WeeklyLimit(max) => write!(f, "{} times are already booked that week, the most allowed", max),
+            BookingError::Api(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl BookingError {
+    /// Whether the time was taken by someone else, possibly only moments before booking it.
+    pub(crate) fn is_taken(&self) -> bool {
+        matches!(self, BookingError::NotAvailable | BookingError::Conflict)
+    }
+}
+
+/// Check that a time of a resource is free, by fetching its reservations again.
+pub(crate) fn check(resource_id: &str, time: &Timeslot) -> Result<(), BookingError> {
+    if time.start_time() <= timezone::now() {
+        return Err(BookingError::Started);
+    }
+    let api_data = respa::fetch_resource_data(resource_id).map_err(BookingError::Api)?;
+    let opening_times = respa::parse_opening_times(&api_data);
+    let reservations = respa::parse_reservations(&api_data);
+    let available_times = availability::get_available_times(&opening_times, &reservations);
+    if !available_times.iter().any(|available| available.covers(time)) {
+        return Err(BookingError::NotAvailable);
+    }
+    Ok(())
+}
+
+/// Varaamo page listing the user's reservations, where they can be cancelled.
+pub(crate) const MY_RESERVATIONS_URL: &str = "https://varaamo.hel.fi/my-reservations";
+
+/// Reserve a time of a resource, after checking the limits in `[booking]` and checking again that
+/// the time is still free.
+pub(crate) fn book(resource_id: &str, time: &Timeslot) -> Result<Reservation, BookingError> {
+    if CONFIG.auth.api_token.is_empty() {
+        return Err(BookingError::NoApiToken);
+    }
+    CONFIG.booking.permits(time)?;
+    if CONFIG.booking.max_per_week > 0 {
+        let week_start = time.start_time().date_naive().week(Weekday::Mon).first_day();
+        let booked = own_reservations::fetch_since(resource_id, timezone::at(week_start, NaiveTime::MIN))?;
+        let booked = booked.into_iter().map(|reservation| reservation.time).collect::<Vec<Timeslot>>();
+        CONFIG.booking.permits_another(time, &booked)?;
+    }
+    check(resource_id, time)?;
+    respa::create_reservation(resource_id, time, &CONFIG.auth.api_token).map_err(reservation_error)
+}
+
+/// The API answers 409 Conflict when the time was reserved after it was checked.
+fn reservation_error(error: FetchError) -> BookingError {
+    match error {
+        FetchError::Api { status: 409, .. } => BookingError::Conflict,
+        error => BookingError::Api(error),
+    }
+}
+
+/// Most times in a notification that get booking buttons, to keep the keyboard small.
+const MAX_BUTTON_ROWS: usize = 5;
+
+/// Lengths of the times offered by booking buttons, in hours.
+const BUTTON_HOURS: [i64; 2] = [1, 2];
+
+/// A time to book, as carried in the callback data of a booking button.
+pub(crate) struct Request {
+    pub(crate) resource_id: String,
+    pub(crate) time: Timeslot,
+}
+
+impl Request {
+    /// Callback data of a button for the request, e.g. "book axwzr3i57yba 1710514800 2" with
+    /// `action` "book". Telegram allows 64 bytes.
+    fn callback_data(&self, action: &str) -> String {
+        let hours = (self.time.end_time() - self.time.start_time()).num_hours();
+        format!("{} {} {} {}", action, self.resource_id, self.time.start_time().timestamp(), hours)
+    }
+
+    /// Parse the arguments of callback data made by callback_data, after the action.
+    pub(crate) fn parse(arguments: &[&str]) -> Option<Request> {
+        let [resource_id, start, hours] = arguments else {
+            return None;
+        };
+        let start = timezone::zoned(&DateTime::from_timestamp(start.parse().ok()?, 0)?);
+        let end = start + chrono::Duration::hours(hours.parse().ok()?);
+        Some(Request {
+            resource_id: (*resource_id).to_owned(),
+            time: Timeslot {
+                start: start.to_rfc3339(),
+                end: end.to_rfc3339(),
+            },
+        })
+    }
+}
+
+/// Inline keyboard with "Book 1 h" and "Book 2 h" buttons for the start of each time, as JSON.
+/// Times the limits in `[booking]` don't permit get no button.
+/// Returns None if booking isn't configured.
+pub(crate) fn keyboard(resource_id: &str, times: &[Timeslot], language: Language) -> Option<String> {
+    if CONFIG.auth.api_token.is_empty() {
+        return None;
+    }
+    let rows = times
+        .iter()
+        .take(MAX_BUTTON_ROWS)
+        .map(|time| {
+            BUTTON_HOURS
+                .iter()
+                .filter(|hours| time.start_time() + chrono::Duration::hours(**hours) <= time.end_time())
+                .map(|hours| Request {
+                    resource_id: resource_id.to_owned(),
+                    time: Timeslot {
+                        start: time.start.clone(),
+                        end: (time.start_time() + chrono::Duration::hours(*hours)).to_rfc3339(),
+                    },
+                })
+                .filter(|request| CONFIG.booking.permits(&request.time).is_ok())
+                .map(|request| {
+                    let hours = (request.time.end_time() - request.time.start_time()).num_hours();
+                    button(&language.book_button(time.start_time(), hours), &request.callback_data("book"))
+                })
+                .collect::<Vec<serde_json::Value>>()
+        })
+        .filter(|row| !row.is_empty())
+        .collect::<Vec<Vec<serde_json::Value>>>();
+    (!rows.is_empty()).then(|| serde_json::json!({ "inline_keyboard": rows }).to_string())
+}
+
+/// Receipt of a booking: the time, the reservation's ID and state, its access code if it has
+/// one, and where to cancel it.
+pub(crate) fn receipt(time: &Timeslot, reservation: &Reservation, language: Language) -> String {
+    let mut lines = vec![
+        language.booked(&time.localized(language)),
+        language.reservation_details(&reservation.id, &reservation.state),
+    ];
+    if let Some(access_code) = &reservation.access_code {
+        lines.push(language.access_code(access_code));
+    }
+    lines.push(language.cancel_reservation_at(MY_RESERVATIONS_URL));
+    lines.join("\n")
+}
+
+/// Keyboard asking to confirm a booking, as JSON. The "Confirm" button carries the time it was
+/// asked at for the timeout, e.g. "confirm axwzr3i57yba 1710514800 2 1710510000".
+pub(crate) fn confirmation_keyboard(request: &Request, asked_at: DateTime<Tz>, language: Language) -> String {
+    let confirm = format!("{} {}", request.callback_data("confirm"), asked_at.timestamp());
+    let row = [
+        button(language.confirm_booking_button(), &confirm),
+        button(language.cancel_booking_button(), "cancel"),
+    ];
+    serde_json::json!({ "inline_keyboard": [row] }).to_string()
+}
+
+fn button(text: &str, callback_data: &str) -> serde_json::Value {
+    serde_json::json!({ "text": text, "callback_data": callback_data })
+}
+
+/// Handle `laser-rust book`. Without `confirm`, only checks that the time is free.
+/// Returns true if the time was booked, or would be.
+pub(crate) fn run(resource_id: &str, start: &str, end: &str, confirm: bool) -> bool {
+    let (Some(start), Some(end)) = (parse_time(start), parse_time(end)) else {
+        eprintln!("Times must be given as YYYY-MM-DDTHH:MM or in RFC 3339 format");
+        return false;
+    };
+    if end <= start {
+        eprintln!("The end must be after the start");
+        return false;
+    }
+    let time = Timeslot {
+        start: start.to_rfc3339(),
+        end: end.to_rfc3339(),
+    };
+
+    let result = if confirm {
+        book(resource_id, &time).map(|reservation| println!("{}", receipt(&time, &reservation, Language::En)))
+    } else {
+        check(resource_id, &time).map(|()| println!("{} of {} is free. Book it with --confirm.", time, resource_id))
+    };
+    match result {
+        Ok(()) => true,
+        Err(error) => {
+            eprintln!("Can't book {} of {}: {}", time, resource_id, error);
+            false
+        }
+    }
+}
+
+/// Parse a time in RFC 3339 format, or a time in the configured time zone like
+/// "2024-03-15T17:00" or "2024-03-15 17:00".
+fn parse_time(value: &str) -> Option<DateTime<Tz>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(timezone::zoned(&time));
+    }
+    let time = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M"))
+        .ok()?;
+    timezone::zone().from_local_datetime(&time).earliest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_a_conflict_as_a_taken_time() {
+        let conflict = reservation_error(FetchError::Api {
+            status: 409,
+            detail: "The resource is already reserved for some of the period".to_owned(),
+        });
+        assert!(matches!(conflict, BookingError::Conflict));
+        assert!(conflict.is_taken());
+        let rejected = reservation_error(FetchError::Api {
+            status: 400,
+            detail: "Invalid begin".to_owned(),
+        });
+        assert!(matches!(rejected, BookingError::Api(_)));
+        assert!(!rejected.is_taken());
+    }
+
+    fn time(start: &str, end: &str) -> Timeslot {
+        Timeslot {
+            start: start.to_owned(),
+            end: end.to_owned(),
+        }
+    }
+
+    #[test]
+    fn refuses_times_outside_the_limits() {
+        let limits: BookingLimits = toml::from_str(
+            r#"
+            max_per_week = 2
+            max_hours = 2
+
+            [[windows]]
+            weekdays = ["mon", "tue", "wed", "thu", "fri"]
+            from = "16:00"
+            to = "21:00"
+            "#,
+        )
+        .unwrap();
+        // 2024-05-07 is a Tuesday and 2024-05-11 a Saturday.
+        let evening = time("2024-05-07T17:00:00+03:00", "2024-05-07T19:00:00+03:00");
+        assert!(limits.permits(&evening).is_ok());
+        let long = time("2024-05-07T16:00:00+03:00", "2024-05-07T19:00:00+03:00");
+        assert!(matches!(limits.permits(&long), Err(BookingError::TooLong(2))));
+        let late = time("2024-05-07T20:00:00+03:00", "2024-05-07T22:00:00+03:00");
+        assert!(matches!(limits.permits(&late), Err(BookingError::OutsideWindows)));
+        let saturday = time("2024-05-11T17:00:00+03:00", "2024-05-11T19:00:00+03:00");
+        assert!(matches!(limits.permits(&saturday), Err(BookingError::OutsideWindows)));
+
+        // The week of 2024-05-06 has one booking, and the next week one more.
+        let booked = [
+            time("2024-05-06T17:00:00+03:00", "2024-05-06T18:00:00+03:00"),
+            time("2024-05-13T17:00:00+03:00", "2024-05-13T18:00:00+03:00"),
+        ];
+        assert!(limits.permits_another(&evening, &booked).is_ok());
+        let full = [booked[0].clone(), time("2024-05-12T10:00:00+03:00", "2024-05-12T11:00:00+03:00")];
+        assert!(matches!(limits.permits_another(&evening, &full), Err(BookingError::WeeklyLimit(2))));
+        assert!(BookingLimits::default().permits_another(&evening, &full).is_ok());
+    }
+
+    #[test]
+    fn expires_confirmations_after_the_timeout() {
+        let limits = BookingLimits::default();
+        let asked_at = time("2024-05-07T17:00:00+03:00", "2024-05-07T17:00:00+03:00").start_time();
+        assert!(!limits.has_expired(asked_at, asked_at + chrono::Duration::minutes(5)));
+        assert!(limits.has_expired(asked_at, asked_at + chrono::Duration::minutes(6)));
+        let no_timeout = BookingLimits {
+            confirm_timeout_secs: 0,
+            ..BookingLimits::default()
+        };
+        assert!(!no_timeout.has_expired(asked_at, asked_at + chrono::Duration::days(1)));
+    }
+
+    #[test]
+    fn lists_access_code_and_cancellation_link_in_receipt() {
+        let time = time("2024-03-15T17:00:00+02:00", "2024-03-15T19:00:00+02:00");
+        let reservation = Reservation {
+            id: "123456".to_owned(),
+            state: "confirmed".to_owned(),
+            access_code: Some("4321".to_owned()),
+        };
+        let receipt = receipt(&time, &reservation, Language::En);
+        let lines = receipt.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("✅ Booked "));
+        assert_eq!(lines[1], "Reservation 123456, confirmed");
+        assert_eq!(lines[2], "Access code: 4321");
+        assert_eq!(lines[3], format!("Cancel it at {}", MY_RESERVATIONS_URL));
+
+        let without_code = Reservation { access_code: None, ..reservation };
+        assert!(!super::receipt(&time, &without_code, Language::En).contains("Access code"));
+    }
+}
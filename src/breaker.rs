@@ -0,0 +1,195 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Notifier;
+use crate::respa::FetchError;
+use crate::state::State;
+use crate::{desktop, telegram, CONFIG};
+
+/// Longest allowed retry interval, a day.
+const MAX_RETRY_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// Back off when the respa API keeps failing, e.g.
+///
+/// ```toml
+/// [circuit_breaker]
+/// failures = 5
+/// retry_interval_secs = 1800
+/// ```
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct CircuitBreakerConfig {
+    /// Failed cycles in a row after which the API is considered down. 0, the default, disables
+    /// the breaker.
+    pub(crate) failures: u32,
+    /// Seconds between fetches while the API is considered down. At most a day.
+    pub(crate) retry_interval_secs: u64,
+}
+
+impl CircuitBreakerConfig {
+    /// Check that the retry interval is at most MAX_RETRY_INTERVAL_SECS.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.retry_interval_secs > MAX_RETRY_INTERVAL_SECS {
+            return Err(format!(
+                "circuit_breaker.retry_interval_secs of {} s is longer than the maximum of {} s",
+                self.retry_interval_secs, MAX_RETRY_INTERVAL_SECS
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failures: 0,
+            retry_interval_secs: 30 * 60,
+        }
+    }
+}
+
+/// Failures of the respa API, kept in the state so a restart doesn't announce an outage again.
+///
+/// The breaker is closed while fetches succeed, and opens after `failures` failed cycles in a
+/// row. While open, a fetch is only tried every `retry_interval_secs`; a success closes it again.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct Breaker {
+    /// Cycles in a row in which no resource could be fetched.
+    pub(crate) failures: u32,
+    /// Time the API was considered down, while it still is.
+    pub(crate) open_since: Option<DateTime<Local>>,
+    /// Time of the latest failed cycle.
+    pub(crate) failed_at: Option<DateTime<Local>>,
+}
+
+impl Breaker {
+    /// Whether to fetch at `now`: always while closed, and once `retry_interval` has passed since
+    /// the latest failure while open. A retry time past the end of time is tried right away.
+    fn allows_fetch_at(&self, retry_interval: chrono::Duration, now: DateTime<Local>) -> bool {
+        match (self.open_since, self.failed_at) {
+            (Some(_), Some(failed_at)) => failed_at.checked_add_signed(retry_interval).is_none_or(|retry_at| now >= retry_at),
+            _ => true,
+        }
+    }
+
+    /// Record a failed cycle at `now`. Returns true if this failure opened the breaker, which
+    /// it does after `threshold` failures in a row unless `threshold` is 0.
+    fn fail(&mut self, threshold: u32, now: DateTime<Local>) -> bool {
+        self.failures += 1;
+        self.failed_at = Some(now);
+        if threshold == 0 || self.open_since.is_some() || self.failures < threshold {
+            return false;
+        }
+        self.open_since = Some(now);
+        true
+    }
+
+    /// Record a successful cycle, closing the breaker. Returns the time it had been open since,
+    /// if it was.
+    fn succeed(&mut self) -> Option<DateTime<Local>> {
+        std::mem::take(self).open_since
+    }
+}
+
+/// Whether to fetch on this cycle. While the API is considered down, fetches are only made
+/// every `retry_interval_secs`.
+pub(crate) fn allows_fetch(state: &State) -> bool {
+    let retry_interval = i64::try_from(CONFIG.circuit_breaker.retry_interval_secs)
+        .ok()
+        .and_then(chrono::Duration::try_seconds)
+        .unwrap_or(chrono::Duration::MAX);
+    if state.breaker.allows_fetch_at(retry_interval, Local::now()) {
+        return true;
+    }
+    tracing::info!("respa API seems to be down, not fetching until the next retry");
+    false
+}
+
+/// Record a cycle in which no resource could be fetched. After `failures` of them in a row,
+/// the API is considered down and the operator is told so, once.
+pub(crate) fn record_failure(state: &mut State, error: &FetchError) {
+    if state.breaker.fail(CONFIG.circuit_breaker.failures, Local::now()) {
+        tracing::warn!(failures = state.breaker.failures, "respa API seems to be down, backing off");
+        announce(&CONFIG.language.api_down(&error.to_string()));
+    }
+}
+
+/// Record a cycle in which a resource could be fetched, telling the operator if the API was down.
+pub(crate) fn record_success(state: &mut State) {
+    if let Some(since) = state.breaker.succeed() {
+        tracing::info!(%since, "respa API is back");
+        announce(&CONFIG.language.api_recovered(since));
+    }
+}
+
+/// Send a message about the API through each configured notifier, over Telegram to the operator
+/// chat only.
+fn announce(message: &str) {
+    for notifier in &CONFIG.notifiers {
+        match notifier {
+            Notifier::Telegram => {
+                if let Err(error) = telegram::send_message(CONFIG.operator_chat(), message) {
+                    tracing::warn!(%error, "Failed to send message to operator chat");
+                }
+            }
+            Notifier::Desktop => desktop::notify("laser-rust", message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_failures_and_retries_until_a_fetch_succeeds() {
+        let retry_interval = chrono::Duration::minutes(30);
+        let start = Local::now();
+        let minutes = |minutes| start + chrono::Duration::minutes(minutes);
+        let mut breaker = Breaker::default();
+
+        // Closed: failures below the threshold don't stop fetching.
+        assert!(!breaker.fail(3, minutes(0)));
+        assert!(!breaker.fail(3, minutes(5)));
+        assert!(breaker.allows_fetch_at(retry_interval, minutes(10)));
+
+        // Open: the third failure in a row stops fetching until the retry interval has passed.
+        assert!(breaker.fail(3, minutes(10)));
+        assert!(!breaker.allows_fetch_at(retry_interval, minutes(20)));
+
+        // Half-open: a retry is allowed, and if it fails the breaker stays open without opening again.
+        assert!(breaker.allows_fetch_at(retry_interval, minutes(40)));
+        assert!(!breaker.fail(3, minutes(40)));
+        assert!(!breaker.allows_fetch_at(retry_interval, minutes(50)));
+
+        // A successful retry closes it.
+        assert_eq!(breaker.succeed(), Some(minutes(10)));
+        assert!(breaker.allows_fetch_at(retry_interval, minutes(50)));
+        assert_eq!(breaker.failures, 0);
+        assert_eq!(breaker.succeed(), None);
+    }
+
+    #[test]
+    fn never_opens_when_disabled() {
+        let mut breaker = Breaker::default();
+        for _ in 0..10 {
+            assert!(!breaker.fail(0, Local::now()));
+        }
+        assert!(breaker.allows_fetch_at(chrono::Duration::minutes(30), Local::now()));
+    }
+
+    #[test]
+    fn refuses_retry_intervals_over_a_day() {
+        let config = |retry_interval_secs| CircuitBreakerConfig { failures: 5, retry_interval_secs };
+        assert!(config(24 * 60 * 60).validate().is_ok());
+        assert!(config(u64::MAX).validate().is_err());
+
+        let breaker = Breaker {
+            failures: 5,
+            open_since: Some(Local::now()),
+            failed_at: Some(Local::now()),
+        };
+        assert!(breaker.allows_fetch_at(chrono::Duration::MAX, Local::now()));
+    }
+}
@@ -0,0 +1,103 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use crate::diff::Diff;
+use crate::timeslot::Timeslot;
+
+/// Number of changes kept in the state.
+const MAX_CHANGES: usize = 1000;
+/// File in the state directory all changes are appended to, unless `change_log` is set.
+pub(crate) const LOG_FILE: &str = "changes.jsonl";
+
+/// A time becoming available or no longer available.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Change {
+    /// When the change was detected.
+    pub(crate) detected_at: DateTime<Local>,
+    pub(crate) resource: String,
+    pub(crate) kind: ChangeKind,
+    pub(crate) start: String,
+    pub(crate) end: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ChangeKind {
+    /// The time became available.
+    Added,
+    /// The time is no longer available, e.g. because it was reserved.
+    Removed,
+}
+
+/// Turn the differences between the available times of a resource and the previously seen ones
+/// into changes.
+pub(crate) fn diff(resource: &str, diff: &Diff, detected_at: DateTime<Local>) -> Vec<Change> {
+    let change = |kind: ChangeKind, time: &Timeslot| Change {
+        detected_at,
+        resource: resource.to_owned(),
+        kind,
+        start: time.start.clone(),
+        end: time.end.clone(),
+    };
+
+    let added = diff.added.iter().map(|time| change(ChangeKind::Added, time));
+    let removed = diff.removed.iter().map(|time| change(ChangeKind::Removed, time));
+    added.chain(removed).collect()
+}
+
+/// Append changes to a change log, dropping the oldest ones beyond MAX_CHANGES.
+pub(crate) fn record(log: &mut Vec<Change>, changes: Vec<Change>) {
+    log.extend(changes);
+    if log.len() > MAX_CHANGES {
+        log.drain(..log.len() - MAX_CHANGES);
+    }
+}
+
+/// Append changes to a file as one JSON object per line. Unlike the changes in the state, they
+/// are never dropped.
+pub(crate) fn append(path: &Path, changes: &[Change]) -> std::io::Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for change in changes {
+        lines.push_str(&serde_json::to_string(change)?);
+        lines.push('\n');
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(lines.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_changes_as_json_lines() {
+        let dir = std::env::temp_dir().join(format!("laser-rust-changes-{}", std::process::id()));
+        let path = dir.join(LOG_FILE);
+        let time = |start: &str, end: &str| Timeslot {
+            start: start.to_owned(),
+            end: end.to_owned(),
+        };
+        let slots = Diff {
+            added: vec![time("2024-05-01T10:00:00+03:00", "2024-05-01T12:00:00+03:00")],
+            removed: vec![time("2024-05-02T10:00:00+03:00", "2024-05-02T11:00:00+03:00")],
+        };
+        let changes = diff("axwzr3i57yba", &slots, Local::now());
+        append(&path, &changes).unwrap();
+        append(&path, &changes[..1]).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let logged = contents.lines().map(|line| serde_json::from_str::<Change>(line).unwrap()).collect::<Vec<_>>();
+        std::fs::remove_dir_all(&dir).ok();
+        let kinds = logged.iter().map(|change| change.kind).collect::<Vec<_>>();
+        assert!(kinds == [ChangeKind::Added, ChangeKind::Removed, ChangeKind::Added]);
+        assert_eq!(logged[1].start, "2024-05-02T10:00:00+03:00");
+    }
+}
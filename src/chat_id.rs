@@ -0,0 +1,33 @@
+use crate::config;
+use crate::telegram;
+
+/// Print the chats that have recently messaged the bot, to help find the chat ID to configure.
+/// With `write`, the chat of the latest message is saved as telegram_chat_id in the config file.
+///
+/// Updates are only read, not acknowledged, so the bot will still see the same messages.
+pub(crate) fn run(write: bool) {
+    let (messages, _) = telegram::get_updates(0);
+    if messages.is_empty() {
+        println!("No recent messages. Send a message to the bot (or add it to a group) and try again.");
+        return;
+    }
+
+    // List each chat once, in the order they last wrote.
+    let mut chats: Vec<(&str, &str)> = Vec::new();
+    for message in messages.iter().rev() {
+        if !chats.iter().any(|(chat_id, _)| *chat_id == message.chat_id) {
+            chats.push((&message.chat_id, &message.chat_title));
+        }
+    }
+    for (chat_id, title) in &chats {
+        println!("{}\t{}", chat_id, title);
+    }
+
+    if write {
+        let (chat_id, title) = chats[0];
+        match config::write_value("telegram_chat_id", chat_id) {
+            Ok(()) => println!("Saved chat {} ({}) to {}", chat_id, title, config::path()),
+            Err(error) => println!("Failed to write config file {}: {}", config::path(), error),
+        }
+    }
+}
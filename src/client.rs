@@ -0,0 +1,68 @@
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::CONFIG;
+
+/// User-Agent sent when none is configured, identifying the bot to the API operators.
+const DEFAULT_USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/lopossumi/laser-rust)"
+);
+
+/// Options for the HTTP requests made to respa, Telegram and the Pushgateway, e.g.
+///
+/// ```toml
+/// [client]
+/// connect_timeout_secs = 10
+/// timeout_secs = 30
+/// proxy = "socks5h://127.0.0.1:1080"
+/// ```
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct ClientConfig {
+    /// Seconds to wait for a connection to be established.
+    pub(crate) connect_timeout_secs: u64,
+    /// Seconds to wait for a whole request, from connecting until the response has been read.
+    pub(crate) timeout_secs: u64,
+    /// HTTP, HTTPS or SOCKS proxy for all requests, e.g. "http://proxy:3128". Without it,
+    /// the HTTP_PROXY, HTTPS_PROXY and ALL_PROXY environment variables are used.
+    pub(crate) proxy: Option<String>,
+    pub(crate) user_agent: String,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            connect_timeout_secs: 10,
+            timeout_secs: 30,
+            proxy: None,
+            user_agent: DEFAULT_USER_AGENT.to_owned(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Build an HTTP client with these options. Fails if the proxy URL is invalid.
+    pub(crate) fn build(&self) -> reqwest::Result<reqwest::blocking::Client> {
+        let mut builder = reqwest::blocking::Client::builder()
+            .connect_timeout(Duration::from_secs(self.connect_timeout_secs))
+            .timeout(Duration::from_secs(self.timeout_secs))
+            .user_agent(&self.user_agent);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        builder.build()
+    }
+}
+
+static CLIENT: Lazy<reqwest::blocking::Client> =
+    Lazy::new(|| CONFIG.client.build().expect("Failed to create HTTP client"));
+
+/// The HTTP client for all outgoing requests, configured by `[client]`.
+/// Sharing one client also reuses connections between requests.
+pub(crate) fn client() -> &'static reqwest::blocking::Client {
+    &CLIENT
+}
@@ -0,0 +1,470 @@
+use chrono::{DateTime, Local, NaiveDate};
+
+use crate::blackout::Blackout;
+use crate::booking;
+use crate::config::MIN_POLL_INTERVAL_SECS;
+use crate::digest::UnchangedDigest;
+use crate::output;
+use crate::overrides::{self, Overrides};
+use crate::own_reservations::{self, OwnReservation};
+use crate::priority::PriorityWindow;
+use crate::respa;
+use crate::stats;
+use crate::state::State;
+use crate::telegram;
+use crate::timezone;
+use crate::watch::Watch;
+use crate::CONFIG;
+
+/// Most resources listed in a reply to /search.
+const SEARCH_LIMIT: usize = 5;
+
+/// Longest duration accepted by parse_duration, so that adding it to the current time can't overflow.
+const MAX_DURATION_DAYS: i64 = 366;
+
+/// Longest throttle /throttle sets.
+const MAX_THROTTLE_DAYS: i64 = 7;
+
+/// Fetch new messages sent to the bot and act on any commands in them.
+/// Only messages from subscribed chats are handled.
+pub(crate) fn handle_commands(state: &mut State) {
+    let (messages, next_offset) = telegram::get_updates(state.update_offset);
+    state.update_offset = next_offset;
+
+    for message in messages {
+        let Some(subscriber) = state.subscriber_mut(&message.chat_id) else {
+            continue;
+        };
+
+        // A chat that writes to the bot has evidently unblocked it.
+        if subscriber.inactive {
+            tracing::info!(chat_id = %subscriber.chat_id, "Chat is active again");
+            subscriber.inactive = false;
+            subscriber.failures = 0;
+        }
+
+        if let Some(callback) = &message.callback {
+            if let Err(error) = telegram::answer_callback(&callback.id) {
+                tracing::warn!(chat_id = %message.chat_id, %error, "Failed to answer callback");
+            }
+            handle_button(state, &message.chat_id, message.user_id, callback.message_id, &message.text);
+            continue;
+        }
+
+        // Commands may be addressed to the bot in groups, e.g. "/pause@laser_bot until 2024-08-01"
+        let mut words = message.text.split_whitespace();
+        let command = words.next().unwrap_or("").split('@').next().unwrap_or("");
+        let arguments = words.collect::<Vec<&str>>();
+
+        // Commands that affect every chat are for admins only.
+        // So are the operator's own reservations.
+        let operational = matches!(
+            (command, arguments.as_slice()),
+            ("/pause", ["until", ..]) | ("/broadcast" | "/set" | "/myreservations", _)
+        );
+        if operational && !CONFIG.is_admin(message.user_id) {
+            tracing::info!(chat_id = %message.chat_id, user_id = ?message.user_id, command, "Command refused, user is not an admin");
+            if let Err(error) = telegram::send_message(&message.chat_id, CONFIG.language.admin_only()) {
+                tracing::warn!(chat_id = %message.chat_id, %error, "Failed to reply");
+            }
+            continue;
+        }
+
+        let reply = match command {
+            "/pause" => pause(state, &message.chat_id, &arguments),
+            "/broadcast" => match message.text.split_once(char::is_whitespace).map(|(_, text)| text.trim()) {
+                Some(text) if !text.is_empty() => broadcast(state, text),
+                _ => CONFIG.language.broadcast_usage().to_owned(),
+            },
+            "/set" => set(state, &arguments),
+            "/resume" => {
+                subscriber.alerts_paused_until = None;
+                CONFIG.language.alerts_resumed().to_owned()
+            }
+            "/search" if arguments.is_empty() => CONFIG.language.search_usage().to_owned(),
+            "/search" => match respa::search(&arguments.join(" "), SEARCH_LIMIT) {
+                Ok(resources) => output::search_text(&resources),
+                Err(error) => error.to_string(),
+            },
+            "/status" => status(state),
+            "/stats" => output::lead_times_text(&stats::lead_times(&state.changes), CONFIG.language),
+            "/digest" => match arguments.as_slice() {
+                [setting] => match UnchangedDigest::parse(setting) {
+                    Some(setting) => {
+                        subscriber.unchanged_digest = setting;
+                        CONFIG.language.unchanged_digest_set(setting).to_owned()
+                    }
+                    None => CONFIG.language.digest_usage().to_owned(),
+                },
+                _ => CONFIG.language.digest_usage().to_owned(),
+            },
+            "/watch" => match Watch::parse(&arguments) {
+                Some(watch) => {
+                    let reply = CONFIG.language.watching(&watch.localized(CONFIG.language));
+                    subscriber.watches.push(watch);
+                    reply
+                }
+                None => CONFIG.language.watch_usage().to_owned(),
+            },
+            "/watches" => watches(&subscriber.watches),
+            "/blackout" => blackout(&mut subscriber.blackouts, &arguments),
+            "/priority" => priority(&mut subscriber.priority_windows, &arguments),
+            "/throttle" => match arguments.as_slice() {
+                ["off"] => {
+                    subscriber.throttle_minutes = None;
+                    CONFIG.language.throttle_off().to_owned()
+                }
+                [duration] => match parse_duration(duration)
+                    .filter(|duration| *duration > chrono::Duration::zero() && *duration <= chrono::Duration::days(MAX_THROTTLE_DAYS))
+                {
+                    Some(duration) => {
+                        subscriber.throttle_minutes = Some(duration.num_minutes());
+                        CONFIG.language.throttle_set(duration.num_minutes())
+                    }
+                    None => CONFIG.language.throttle_usage().to_owned(),
+                },
+                _ => CONFIG.language.throttle_usage().to_owned(),
+            },
+            "/myreservations" => my_reservations(),
+            "/unwatch" => {
+                let index = arguments.first().and_then(|number| number.parse::<usize>().ok());
+                match index.filter(|index| (1..=subscriber.watches.len()).contains(index)) {
+                    Some(index) => {
+                        let watch = subscriber.watches.remove(index - 1);
+                        CONFIG.language.unwatched(&watch.localized(CONFIG.language))
+                    }
+                    None => CONFIG.language.unwatch_usage().to_owned(),
+                }
+            }
+            "/where" => {
+                let sent = match arguments.as_slice() {
+                    [] => where_is(&message.chat_id, &CONFIG.resource_id),
+                    [resource_id] => where_is(&message.chat_id, resource_id),
+                    _ => Err(CONFIG.language.where_usage().to_owned()),
+                };
+                match sent {
+                    Ok(()) => continue,
+                    Err(reply) => reply,
+                }
+            }
+            _ => continue,
+        };
+        if let Err(error) = telegram::send_message(&message.chat_id, &reply) {
+            tracing::warn!(chat_id = %message.chat_id, %error, "Failed to reply");
+        }
+    }
+}
+
+/// Handle "/pause until <date>", which pauses monitoring, and "/pause <duration>", which only
+/// stops sending to the chat. Returns the reply to send.
+fn pause(state: &mut State, chat_id: &str, arguments: &[&str]) -> String {
+    match arguments {
+        ["until", date] => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(until) => {
+                state.paused_until = Some(until);
+                CONFIG.language.paused_until(until)
+            }
+            Err(_) => CONFIG.language.pause_usage().to_owned(),
+        },
+        [duration] => match (parse_duration(duration), state.subscriber_mut(chat_id)) {
+            (Some(duration), Some(subscriber)) => {
+                let until = Local::now() + duration;
+                tracing::info!(%chat_id, %until, "Notifications paused");
+                subscriber.alerts_paused_until = Some(until);
+                CONFIG.language.alerts_paused(until)
+            }
+            _ => CONFIG.language.pause_usage().to_owned(),
+        },
+        _ => CONFIG.language.pause_usage().to_owned(),
+    }
+}
+
+/// Handle "/set <setting> <value>", or "/set <setting> default" to go back to the value in the
+/// config file. Returns the reply to send.
+fn set(state: &mut State, arguments: &[&str]) -> String {
+    let (Some(overrides), [name, value]) = (changed_overrides(state.overrides, arguments), arguments) else {
+        return CONFIG.language.set_usage().to_owned();
+    };
+    tracing::info!(setting = name, value, "Setting changed");
+    state.overrides = overrides;
+    overrides::apply(overrides);
+    CONFIG.language.setting_changed(name, value)
+}
+
+/// The overrides with the setting in the arguments of /set changed, or None if they're invalid.
+fn changed_overrides(mut overrides: Overrides, arguments: &[&str]) -> Option<Overrides> {
+    match arguments {
+        ["interval", value] => {
+            overrides.poll_interval_secs = setting(value, |value| value.parse().ok().filter(|secs| *secs >= MIN_POLL_INTERVAL_SECS))?
+        }
+        ["lookahead", value] => overrides.lookahead_days = setting(value, |value| value.parse().ok().filter(|days| *days > 0))?,
+        ["min_duration", value] => {
+            overrides.min_duration_minutes = setting(value, |value| parse_duration(value).map(|duration| duration.num_minutes()))?
+        }
+        _ => return None,
+    }
+    Some(overrides)
+}
+
+/// Parse the value of a setting, or "default" as None.
+fn setting<T>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Option<T>> {
+    match value {
+        "default" => Some(None),
+        _ => parse(value).map(Some),
+    }
+}
+
+/// Parse a duration like "30m", "12h", "3d" or "2w", of at most MAX_DURATION_DAYS.
+pub(crate) fn parse_duration(value: &str) -> Option<chrono::Duration> {
+    let split = value.find(|character: char| !character.is_ascii_digit())?;
+    let (number, unit) = value.split_at(split);
+    let number = number.parse::<i64>().ok()?;
+    let duration = match unit {
+        "m" => chrono::Duration::try_minutes(number),
+        "h" => chrono::Duration::try_hours(number),
+        "d" => chrono::Duration::try_days(number),
+        "w" => chrono::Duration::try_weeks(number),
+        _ => None,
+    }?;
+    (duration <= chrono::Duration::days(MAX_DURATION_DAYS)).then_some(duration)
+}
+
+/// Handle a tap on a booking button. "book" asks for confirmation, "confirm" books the time
+/// and "cancel" drops the prompt. Results replace the prompt.
+/// Only admins may book, since times are booked with the operator's account.
+fn handle_button(state: &mut State, chat_id: &str, user_id: Option<i64>, message_id: i64, data: &str) {
+    if !CONFIG.is_admin(user_id) {
+        tracing::info!(%chat_id, ?user_id, "Button refused, user is not an admin");
+        if let Err(error) = telegram::send_message(chat_id, CONFIG.language.admin_only()) {
+            tracing::warn!(%chat_id, %error, "Failed to reply to button");
+        }
+        return;
+    }
+    let words = data.split_whitespace().collect::<Vec<&str>>();
+    let result = match words.as_slice() {
+        ["book", arguments @ ..] => {
+            let Some(request) = booking::Request::parse(arguments) else {
+                return;
+            };
+            let prompt = CONFIG.language.confirm_booking(&request.time.localized(CONFIG.language));
+            let keyboard = booking::confirmation_keyboard(&request, timezone::now(), CONFIG.language);
+            telegram::send_with_keyboard(chat_id, &prompt, &keyboard)
+        }
+        ["confirm", resource_id, start, hours, asked_at @ ..] => {
+            let Some(request) = booking::Request::parse(&[resource_id, start, hours]) else {
+                return;
+            };
+            let time = request.time.localized(CONFIG.language);
+            // Prompts sent before the timeout existed don't tell when they were asked, and expire.
+            let asked_at = asked_at.first().and_then(|asked_at| DateTime::from_timestamp(asked_at.parse().ok()?, 0));
+            let expired = asked_at.is_none_or(|asked_at| CONFIG.booking.has_expired(timezone::zoned(&asked_at), timezone::now()));
+            let reply = if expired {
+                tracing::info!(%chat_id, %time, "Booking confirmed too late");
+                CONFIG.language.confirmation_expired().to_owned()
+            } else {
+                match booking::book(&request.resource_id, &request.time) {
+                    Ok(reservation) => {
+                        tracing::info!(%chat_id, %time, reservation_id = %reservation.id, state = %reservation.state, "Booked from chat");
+                        // Tracked right away, so reminders and cancellation alerts don't wait for the
+                        // next refresh of the own reservations.
+                        if !state.own_reservations.iter().any(|own| own.id == reservation.id) {
+                            state.own_reservations.push(OwnReservation {
+                                id: reservation.id.clone(),
+                                time: request.time.clone(),
+                                reminded: Vec::new(),
+                            });
+                        }
+                        booking::receipt(&request.time, &reservation, CONFIG.language)
+                    }
+                    Err(error) if error.is_taken() => {
+                        tracing::info!(%chat_id, %time, %error, "Time was taken before it could be booked");
+                        CONFIG.language.booking_missed(&time)
+                    }
+                    Err(error) => {
+                        tracing::warn!(%chat_id, %time, %error, "Booking from chat failed");
+                        CONFIG.language.booking_failed(&time, &error.to_string())
+                    }
+                }
+            };
+            telegram::edit_message(chat_id, message_id, &reply)
+        }
+        ["cancel"] => telegram::edit_message(chat_id, message_id, CONFIG.language.booking_cancelled()),
+        _ => return,
+    };
+    if let Err(error) = result {
+        tracing::warn!(%chat_id, %error, "Failed to reply to button");
+    }
+}
+
+/// Handle "/broadcast <text>" by sending the text to every subscribed chat. Returns the reply to send.
+fn broadcast(state: &mut State, text: &str) -> String {
+    let delivered = telegram::broadcast_each(state, false, |_| Some(text.to_owned()));
+    tracing::info!(chats = delivered.len(), "Sent announcement");
+    CONFIG.language.broadcast_sent(delivered.len())
+}
+
+/// Handle "/status" with the bot's uptime, polling interval, latest notification and the latest
+/// fetch of each monitored resource, so chats can tell a quiet bot from a broken one.
+fn status(state: &State) -> String {
+    let now = Local::now();
+    let mut lines = vec![CONFIG.language.status_heading().to_owned()];
+    if let Some(run) = state.counters.uptime.last() {
+        lines.push(CONFIG.language.status_running(run.started_at, (now - run.started_at).num_minutes()));
+    }
+    lines.push(match &CONFIG.schedule {
+        Some(schedule) => CONFIG.language.status_schedule(&schedule.to_string()),
+        None => CONFIG.language.status_poll_interval((CONFIG.poll_interval_of_at(&CONFIG.resource_id, now).as_secs() / 60).max(1) as i64),
+    });
+    lines.push(CONFIG.language.status_last_notified(state.last_notified.values().max().copied()));
+    let unit_resources = state.unit_resources.iter().filter(|resource_id| **resource_id != CONFIG.resource_id);
+    for resource_id in std::iter::once(&CONFIG.resource_id).chain(unit_resources) {
+        let count = state.available_times_of(resource_id).map_or(0, Vec::len);
+        lines.push(CONFIG.language.status_resource(resource_id, state.fetched_at.get(resource_id).copied(), count));
+    }
+    lines.join("\n")
+}
+
+/// Handle "/myreservations" by listing the upcoming reservations of the API token's user.
+fn my_reservations() -> String {
+    match own_reservations::fetch(&CONFIG.resource_id) {
+        Ok(reservations) if reservations.is_empty() => CONFIG.language.no_own_reservations().to_owned(),
+        Ok(reservations) => {
+            let mut reply = CONFIG.language.own_reservations_heading().to_owned();
+            for reservation in reservations {
+                reply.push('\n');
+                reply.push_str(&reservation.time.localized(CONFIG.language));
+            }
+            reply
+        }
+        Err(error) => CONFIG.language.own_reservations_unavailable(&error.to_string()),
+    }
+}
+
+/// Handle "/watches" by listing the watched windows, numbered for /unwatch.
+fn watches(watches: &[Watch]) -> String {
+    if watches.is_empty() {
+        return CONFIG.language.no_watches().to_owned();
+    }
+    let mut reply = CONFIG.language.watches_heading().to_owned();
+    for (index, watch) in watches.iter().enumerate() {
+        reply.push_str(&format!("\n{}. {}", index + 1, watch.localized(CONFIG.language)));
+    }
+    reply
+}
+
+/// Handle "/blackout <from> [<to>]", which adds dates the chat isn't notified about, "/blackout"
+/// which lists them and "/blackout clear" which removes them. Past dates are forgotten.
+/// Returns the reply to send.
+fn blackout(blackouts: &mut Vec<Blackout>, arguments: &[&str]) -> String {
+    let today = timezone::now().date_naive();
+    blackouts.retain(|blackout| !blackout.has_passed(today));
+    match arguments {
+        [] if blackouts.is_empty() => CONFIG.language.no_blackouts().to_owned(),
+        [] => {
+            let mut reply = CONFIG.language.blackouts_heading().to_owned();
+            for blackout in blackouts.iter() {
+                reply.push_str(&format!("\n{}", blackout.localized(CONFIG.language)));
+            }
+            reply
+        }
+        ["clear"] => {
+            blackouts.clear();
+            CONFIG.language.blackouts_cleared().to_owned()
+        }
+        _ => match Blackout::parse(arguments) {
+            Some(blackout) => {
+                let reply = CONFIG.language.blackout_added(&blackout.localized(CONFIG.language));
+                blackouts.push(blackout);
+                reply
+            }
+            None => CONFIG.language.blackout_usage().to_owned(),
+        },
+    }
+}
+
+/// Handle "/priority [<weekdays>] <from>-<to>", which adds a window of times the chat is notified
+/// about with sound, "/priority" which lists them and "/priority clear" which removes them.
+/// Returns the reply to send.
+fn priority(windows: &mut Vec<PriorityWindow>, arguments: &[&str]) -> String {
+    match arguments {
+        [] if windows.is_empty() => CONFIG.language.no_priority_windows().to_owned(),
+        [] => {
+            let mut reply = CONFIG.language.priority_windows_heading().to_owned();
+            for window in windows.iter() {
+                reply.push_str(&format!("\n{}", window.localized(CONFIG.language)));
+            }
+            reply
+        }
+        ["clear"] => {
+            windows.clear();
+            CONFIG.language.priority_windows_cleared().to_owned()
+        }
+        _ => match PriorityWindow::parse(arguments) {
+            Some(window) => {
+                let reply = CONFIG.language.priority_window_added(&window.localized(CONFIG.language));
+                windows.push(window);
+                reply
+            }
+            None => CONFIG.language.priority_usage().to_owned(),
+        },
+    }
+}
+
+/// Handle "/where [resource ID]" by sending the location of the venue of the resource, by default
+/// the monitored one. Returns the reply to send if the location isn't available.
+fn where_is(chat_id: &str, resource_id: &str) -> Result<(), String> {
+    let unit = respa::fetch_resource_data(resource_id)
+        .ok()
+        .and_then(|api_data| respa::parse_resource(resource_id, &api_data).unit)
+        .and_then(|unit_id| respa::fetch_unit(&unit_id))
+        .ok_or_else(|| CONFIG.language.venue_unknown().to_owned())?;
+
+    if let Err(error) = telegram::send_venue(chat_id, &unit, None) {
+        tracing::warn!(%chat_id, %error, "Failed to send venue");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_one_setting_with_set() {
+        let overrides = Overrides {
+            lookahead_days: Some(7),
+            ..Overrides::default()
+        };
+        let changed = changed_overrides(overrides, &["min_duration", "1h"]).unwrap();
+        assert_eq!((changed.min_duration_minutes, changed.lookahead_days), (Some(60), Some(7)));
+        let changed = changed_overrides(overrides, &["interval", "600"]).unwrap();
+        assert_eq!(changed.poll_interval_secs, Some(600));
+        let changed = changed_overrides(overrides, &["lookahead", "default"]).unwrap();
+        assert_eq!(changed.lookahead_days, None);
+
+        let below_minimum = MIN_POLL_INTERVAL_SECS - 1;
+        for invalid in [
+            &["interval", &below_minimum.to_string()][..],
+            &["lookahead", "0"],
+            &["min_duration", "60"],
+            &["colour", "blue"],
+            &["lookahead"],
+            &["lookahead", "7", "days"],
+        ] {
+            assert!(changed_overrides(overrides, invalid).is_none(), "{:?} should be refused", invalid);
+        }
+    }
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("30m"), Some(chrono::Duration::minutes(30)));
+        assert_eq!(parse_duration("12h"), Some(chrono::Duration::hours(12)));
+        assert_eq!(parse_duration("3d"), Some(chrono::Duration::days(3)));
+        assert_eq!(parse_duration("2w"), Some(chrono::Duration::weeks(2)));
+        assert_eq!(parse_duration("0m"), Some(chrono::Duration::zero()));
+        assert_eq!(parse_duration("52w"), Some(chrono::Duration::weeks(52)));
+        for invalid in ["", "30", "m", "-3d", "3 d", "3y", "1.5h", "53w", "99999999w", "9999999999999999w"] {
+            assert_eq!(parse_duration(invalid), None, "{:?} should not parse", invalid);
+        }
+    }
+}
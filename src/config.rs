@@ -0,0 +1,647 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+use chrono_tz::Tz;
+use croner::Cron;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::blackout::Blackout;
+use crate::booking::{AuthConfig, BookingLimits};
+use crate::breaker::CircuitBreakerConfig;
+use crate::client::ClientConfig;
+use crate::diff::{DiffConfig, DiffStrategy};
+use crate::holidays::HolidayMode;
+use crate::i18n::Language;
+use crate::interest::InterestConfig;
+use crate::logging::{LogFormat, OtlpConfig};
+use crate::maintenance::MaintenanceBlock;
+use crate::message::FormatProfile;
+use crate::metrics::PushgatewayConfig;
+use crate::mqtt::MqttConfig;
+use crate::overrides;
+use crate::paths;
+use crate::scheduler::{self, AdaptivePollingConfig};
+use crate::server::HttpConfig;
+use crate::telegram::Subscriber;
+use crate::timezone;
+use crate::unit::UnitConfig;
+use crate::wishlist::WishlistEntry;
+
+/// Shortest allowed polling interval, to avoid hammering the respa API.
+pub(crate) const MIN_POLL_INTERVAL_SECS: u64 = 60;
+
+/// Runtime configuration.
+///
+/// Settings are read from a TOML file (laser.toml, or the path in LASER_CONFIG).
+/// The Telegram bot token is always taken from the environment.
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    #[serde(skip)]
+    pub(crate) telegram_bot_token: String,
+    /// Chat that is subscribed to notifications. TELEGRAM_CHAT_ID overrides this.
+    pub(crate) telegram_chat_id: String,
+    /// Chat that receives operational reports, such as subscribers being dropped.
+    pub(crate) operator_chat_id: Option<String>,
+    /// Telegram user IDs allowed to use the commands that affect every chat, such as pausing
+    /// monitoring. If empty, only the user whose private chat is the operator chat, or
+    /// telegram_chat_id without one, may use them.
+    pub(crate) admins: Vec<i64>,
+    /// Number of consecutive refused deliveries after which a subscriber is made inactive.
+    pub(crate) max_send_failures: u32,
+    /// Pause between messages to different chats, to stay within Telegram's rate limits.
+    pub(crate) send_interval_millis: u64,
+    /// Longest rate limit wait to sit out. Messages with longer waits are queued for the next cycle.
+    pub(crate) max_retry_after_secs: u64,
+    /// Respa ID of the monitored resource.
+    pub(crate) resource_id: String,
+    /// Seconds between fetches when running continuously.
+    pub(crate) poll_interval_secs: u64,
+    /// Monitor all reservable resources of a unit too. See unit::UnitConfig.
+    pub(crate) unit: Option<UnitConfig>,
+    /// Polling intervals in seconds for specific resources, by resource ID.
+    pub(crate) resource_poll_interval_secs: HashMap<String, u64>,
+    /// Cron expression for fetch times, e.g. "*/5 7-22 * * *". Replaces the polling intervals when set.
+    #[serde(deserialize_with = "scheduler::deserialize_schedule")]
+    pub(crate) schedule: Option<Cron>,
+    /// Delay each fetch by a random number of seconds up to this.
+    pub(crate) poll_jitter_secs: u64,
+    /// Number of days ahead to fetch availability for.
+    #[serde(alias = "days")]
+    pub(crate) lookahead_days: u64,
+    /// Don't notify about times starting more than this many days from now.
+    pub(crate) notify_max_days_ahead: Option<u64>,
+    /// Don't notify about new times shorter than this many minutes.
+    pub(crate) min_duration_minutes: i64,
+    /// Travel times to venues. Times that can't be reached in time are not notified about.
+    pub(crate) travel: TravelConfig,
+    /// Language of messages, dates and resource names, "en" or "fi".
+    pub(crate) language: Language,
+    /// Time zone of displayed times, dates and daily schedules, e.g. "Europe/Helsinki".
+    pub(crate) timezone: Tz,
+    /// Log output format, "text" or "json". The level is set with RUST_LOG.
+    pub(crate) log_format: LogFormat,
+    /// File logs are appended to with --daemon. Defaults to laser.log in the state directory.
+    pub(crate) log_file: Option<PathBuf>,
+    /// Export traces of the checks to an OpenTelemetry collector. See logging::OtlpConfig.
+    pub(crate) otlp: Option<OtlpConfig>,
+    /// File every new and no longer available time is appended to as a line of JSON. Defaults to
+    /// changes.jsonl in the state directory.
+    pub(crate) change_log: Option<PathBuf>,
+    /// Where new available times are announced.
+    pub(crate) notifiers: Vec<Notifier>,
+    /// Notifiers and chats for specific resources, by resource ID. See Route.
+    pub(crate) routes: HashMap<String, Route>,
+    /// Follow the first Telegram notification of the day about a venue with its location.
+    pub(crate) send_venue: bool,
+    /// Handlebars template for notification messages. See message::render.
+    pub(crate) template: Option<String>,
+    /// Announce free times separated by reservations of at most this many minutes as one block.
+    pub(crate) bridge_gap_minutes: i64,
+    /// How new and no longer available times are found. See diff::DiffConfig.
+    pub(crate) diff: DiffConfig,
+    /// Diff strategies for specific resources, by resource ID.
+    pub(crate) resource_diff: HashMap<String, DiffConfig>,
+    /// Send a digest of all available times to the subscribers daily at this time, e.g. "08:00".
+    #[serde(deserialize_with = "scheduler::deserialize_optional_time")]
+    pub(crate) digest_time: Option<NaiveTime>,
+    /// Follow the daily digest with a heatmap image of the coming week. Requires the "heatmap" feature.
+    pub(crate) digest_heatmap: bool,
+    /// Formatting of notifications per notifier. See message::FormatProfile.
+    pub(crate) formats: HashMap<Notifier, FormatProfile>,
+    /// Note new times as urgent if similar times have usually been booked within this many minutes. 0, the default, disables.
+    pub(crate) urgent_minutes: i64,
+    /// Kinds of times that get their own alert when they become available.
+    pub(crate) wishlist: Vec<WishlistEntry>,
+    /// Weights for sorting notified times by how interesting they are. See interest::InterestConfig.
+    pub(crate) interest: InterestConfig,
+    /// How Finnish public holidays match the weekdays of the wishlist and interest weights.
+    pub(crate) holidays: HolidayMode,
+    /// Minimum number of minutes between notifications about the same resource.
+    pub(crate) cooldown_minutes: i64,
+    /// Don't announce a time again within this many minutes of announcing it, e.g. when it
+    /// disappears for a fetch and comes back. 0, the default, announces it every time it reappears.
+    pub(crate) dedup_minutes: i64,
+    /// Announce a new time only once it's still available in the next fetch, to ignore times
+    /// that show up for a single fetch because of an API glitch.
+    pub(crate) confirm_new_times: bool,
+    /// Saved availability older than this is replaced without notifying about new times.
+    pub(crate) stale_state_hours: i64,
+    pub(crate) pause: Vec<PauseWindow>,
+    /// Dates whose times aren't notified about or matched against the wishlist.
+    pub(crate) blackout: Vec<Blackout>,
+    /// Recurring times the resource is unavailable for internal reasons, subtracted from availability.
+    pub(crate) maintenance: Vec<MaintenanceBlock>,
+    pub(crate) mqtt: Option<MqttConfig>,
+    pub(crate) pushgateway: Option<PushgatewayConfig>,
+    pub(crate) adaptive_polling: Option<AdaptivePollingConfig>,
+    /// Backing off when the respa API keeps failing.
+    pub(crate) circuit_breaker: CircuitBreakerConfig,
+    /// Timeouts, proxy and User-Agent of outgoing HTTP requests.
+    pub(crate) client: ClientConfig,
+    /// Base URL of the respa API, e.g. of a mock server in tests.
+    pub(crate) respa_url: String,
+    /// Base URL of the Telegram Bot API.
+    pub(crate) telegram_api_url: String,
+    /// Replay responses recorded with `laser-rust record` from this directory instead of fetching
+    /// from the respa API. See source::FileSource.
+    pub(crate) replay_dir: Option<String>,
+    /// Results per page when listing reservations and resources from the respa API.
+    pub(crate) page_size: u32,
+    /// Credentials for booking times with `laser-rust book`.
+    pub(crate) auth: AuthConfig,
+    /// Limits on what may be booked.
+    pub(crate) booking: BookingLimits,
+    /// Remind about own reservations this many minutes before they start. Requires an API token.
+    pub(crate) reminder_minutes: Vec<i64>,
+    /// Serve availability over HTTP. When set, the program keeps running and polls periodically.
+    pub(crate) http: Option<HttpConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            telegram_bot_token: String::new(),
+            telegram_chat_id: String::new(),
+            operator_chat_id: None,
+            admins: Vec::new(),
+            max_send_failures: 3,
+            send_interval_millis: 100,
+            max_retry_after_secs: 60,
+            resource_id: "axwzr3i57yba".to_owned(),
+            poll_interval_secs: 5 * 60,
+            unit: None,
+            resource_poll_interval_secs: HashMap::new(),
+            schedule: None,
+            poll_jitter_secs: 0,
+            lookahead_days: 14,
+            notify_max_days_ahead: None,
+            min_duration_minutes: 0,
+            travel: TravelConfig::default(),
+            language: Language::En,
+            timezone: timezone::DEFAULT,
+            log_format: LogFormat::Text,
+            log_file: None,
+            otlp: None,
+            change_log: None,
+            notifiers: vec![Notifier::Telegram],
+            routes: HashMap::new(),
+            send_venue: false,
+            template: None,
+            bridge_gap_minutes: 0,
+            diff: DiffConfig::default(),
+            resource_diff: HashMap::new(),
+            urgent_minutes: 0,
+            digest_time: None,
+            digest_heatmap: false,
+            formats: HashMap::new(),
+            wishlist: Vec::new(),
+            interest: InterestConfig::default(),
+            holidays: HolidayMode::Weekday,
+            cooldown_minutes: 0,
+            dedup_minutes: 0,
+            confirm_new_times: false,
+            stale_state_hours: 24,
+            pause: Vec::new(),
+            blackout: Vec::new(),
+            maintenance: Vec::new(),
+            mqtt: None,
+            pushgateway: None,
+            adaptive_polling: None,
+            circuit_breaker: CircuitBreakerConfig::default(),
+            client: ClientConfig::default(),
+            respa_url: "https://api.hel.fi/respa/v1".to_owned(),
+            telegram_api_url: "https://api.telegram.org".to_owned(),
+            replay_dir: None,
+            page_size: 100,
+            auth: AuthConfig::default(),
+            booking: BookingLimits::default(),
+            reminder_minutes: vec![120],
+            http: None,
+        }
+    }
+}
+
+/// Notification channels for new available times.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Notifier {
+    /// Message all subscribed Telegram chats.
+    Telegram,
+    /// Show a native desktop notification. Requires the "desktop" feature.
+    Desktop,
+}
+
+impl Notifier {
+    /// Name of the notifier in the config, e.g. "telegram".
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Notifier::Telegram => "telegram",
+            Notifier::Desktop => "desktop",
+        }
+    }
+}
+
+/// Where notifications about a resource go, e.g. the laser cutter to a private chat only:
+///
+/// ```toml
+/// [routes.axwzr3i57yba]
+/// notifiers = ["telegram", "desktop"]
+/// chats = ["123456789"]
+/// ```
+///
+/// In forum groups, `topics` posts the messages in a topic of the group, e.g.
+/// `topics = { "-1001234567890" = 42 }`.
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub(crate) struct Route {
+    /// Notifiers for the resource, instead of `notifiers`.
+    pub(crate) notifiers: Option<Vec<Notifier>>,
+    /// Telegram chats that get the resource's notifications. All subscribers if empty.
+    pub(crate) chats: Vec<String>,
+    /// Forum topics the resource's Telegram messages are posted in, by chat ID. Other chats get
+    /// the messages without a topic.
+    pub(crate) topics: HashMap<String, i64>,
+}
+
+/// Travel times from home to the venues of the resources, e.g.
+///
+/// ```toml
+/// [travel]
+/// default_minutes = 20
+/// venues = { "tprek:51342" = 45 }
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct TravelConfig {
+    /// Travel time to venues not listed in `venues`.
+    pub(crate) default_minutes: i64,
+    /// Travel times in minutes by respa unit ID.
+    pub(crate) venues: HashMap<String, i64>,
+}
+
+impl TravelConfig {
+    /// Travel time to the given venue.
+    pub(crate) fn to_venue(&self, unit: Option<&str>) -> chrono::Duration {
+        let minutes = unit
+            .and_then(|unit| self.venues.get(unit))
+            .copied()
+            .unwrap_or(self.default_minutes);
+        chrono::Duration::minutes(minutes)
+    }
+}
+
+/// A scheduled break in monitoring, e.g.
+///
+/// ```toml
+/// [[pause]]
+/// from = "2024-07-01"
+/// to = "2024-08-01"
+/// ```
+///
+/// Monitoring is paused from the start of `from` until the start of `to`.
+#[derive(Deserialize)]
+pub(crate) struct PauseWindow {
+    pub(crate) from: NaiveDate,
+    pub(crate) to: NaiveDate,
+}
+
+impl Config {
+    /// Formatting of notifications sent through `notifier`. Only Telegram supports Markdown.
+    pub(crate) fn format(&self, notifier: Notifier) -> FormatProfile {
+        let mut profile = self.formats.get(&notifier).cloned().unwrap_or_default();
+        profile.markdown &= notifier == Notifier::Telegram;
+        profile
+    }
+
+    /// Notifiers that announce the times of a resource.
+    pub(crate) fn notifiers_for(&self, resource_id: &str) -> &[Notifier] {
+        self.routes
+            .get(resource_id)
+            .and_then(|route| route.notifiers.as_deref())
+            .unwrap_or(&self.notifiers)
+    }
+
+    /// Whether a Telegram chat gets the notifications about a resource.
+    pub(crate) fn routes_to(&self, resource_id: &str, subscriber: &Subscriber) -> bool {
+        let Some(route) = self.routes.get(resource_id).filter(|route| !route.chats.is_empty()) else {
+            return true;
+        };
+        route.chats.iter().any(|chat_id| {
+            subscriber.chat_id == *chat_id || subscriber.migrated_from.as_deref() == Some(chat_id.as_str())
+        })
+    }
+
+    /// Forum topic the Telegram messages about a resource are posted in in a chat, if its route
+    /// has one for the chat.
+    pub(crate) fn message_thread_id(&self, resource_id: &str, chat_id: &str) -> Option<i64> {
+        self.routes.get(resource_id).and_then(|route| route.topics.get(chat_id)).copied()
+    }
+
+    /// Return the date monitoring resumes if `today` falls inside a configured pause window.
+    pub(crate) fn paused_until(&self, today: NaiveDate) -> Option<NaiveDate> {
+        self.pause
+            .iter()
+            .filter(|window| window.from <= today && today < window.to)
+            .map(|window| window.to)
+            .max()
+    }
+
+    /// Return true if the Telegram user may use the commands that affect every chat. Without
+    /// `admins`, that's the user of the operator chat, or of telegram_chat_id without one, since
+    /// the ID of a private chat is the ID of the user.
+    pub(crate) fn is_admin(&self, user_id: Option<i64>) -> bool {
+        let Some(user_id) = user_id else {
+            return false;
+        };
+        if !self.admins.is_empty() {
+            return self.admins.contains(&user_id);
+        }
+        self.operator_chat().parse::<i64>() == Ok(user_id)
+    }
+
+    /// Chat of the operator: operator_chat_id, or telegram_chat_id without one.
+    pub(crate) fn operator_chat(&self) -> &str {
+        self.operator_chat_id.as_deref().unwrap_or(&self.telegram_chat_id)
+    }
+
+    /// Time between cycles: the shortest polling interval of any resource, unless changed with /set.
+    pub(crate) fn poll_interval(&self) -> Duration {
+        let secs = overrides::current().poll_interval_secs.unwrap_or_else(|| {
+            self.resource_poll_interval_secs.values().copied().chain([self.poll_interval_secs]).min().unwrap_or(self.poll_interval_secs)
+        });
+        Duration::from_secs(secs)
+    }
+
+    /// Time between fetches of a resource: its interval in resource_poll_interval_secs, or
+    /// poll_interval_secs. /set changes both.
+    pub(crate) fn poll_interval_of(&self, resource_id: &str) -> Duration {
+        let secs = overrides::current()
+            .poll_interval_secs
+            .or_else(|| self.resource_poll_interval_secs.get(resource_id).copied())
+            .unwrap_or(self.poll_interval_secs);
+        Duration::from_secs(secs)
+    }
+
+    /// Number of days ahead to fetch availability for, unless changed with /set.
+    pub(crate) fn lookahead_days(&self) -> u64 {
+        overrides::current().lookahead_days.unwrap_or(self.lookahead_days)
+    }
+
+    /// Shortest new time to notify about in minutes, unless changed with /set.
+    pub(crate) fn min_duration_minutes(&self) -> i64 {
+        overrides::current().min_duration_minutes.unwrap_or(self.min_duration_minutes)
+    }
+
+    /// Strategy for finding new and no longer available times of a resource.
+    pub(crate) fn diff_strategy(&self, resource_id: &str) -> Box<dyn DiffStrategy> {
+        self.resource_diff.get(resource_id).unwrap_or(&self.diff).strategy()
+    }
+
+    /// Time between cycles at the given time, shorter around `adaptive_polling` windows.
+    pub(crate) fn poll_interval_at(&self, time: DateTime<Local>) -> Duration {
+        self.adaptive_interval_at(time, self.poll_interval())
+    }
+
+    /// Time between fetches of a resource at the given time, shorter around `adaptive_polling` windows.
+    pub(crate) fn poll_interval_of_at(&self, resource_id: &str, time: DateTime<Local>) -> Duration {
+        self.adaptive_interval_at(time, self.poll_interval_of(resource_id))
+    }
+
+    fn adaptive_interval_at(&self, time: DateTime<Local>, interval: Duration) -> Duration {
+        match &self.adaptive_polling {
+            Some(adaptive) if adaptive.is_active(timezone::zoned(&time).time()) => Duration::from_secs(adaptive.interval_secs).min(interval),
+            _ => interval,
+        }
+    }
+
+    /// Time of the next scheduled fetch of a resource after a fetch at `time`.
+    pub(crate) fn next_fetch_after(&self, resource_id: &str, time: DateTime<Local>) -> DateTime<Local> {
+        self.schedule
+            .as_ref()
+            .and_then(|schedule| scheduler::next_occurrence(schedule, time))
+            .unwrap_or_else(|| time + self.poll_interval_of_at(resource_id, time))
+    }
+
+    /// Time of the `count`th scheduled fetch of a resource before `time`.
+    pub(crate) fn scheduled_fetch_before(&self, resource_id: &str, time: DateTime<Local>, count: u32) -> DateTime<Local> {
+        match &self.schedule {
+            Some(schedule) => (0..count).fold(time, |time, _| scheduler::previous_occurrence(schedule, time)),
+            None => time - self.poll_interval_of(resource_id) * count,
+        }
+    }
+
+    /// Check that the polling intervals are not shorter than MIN_POLL_INTERVAL_SECS.
+    fn validate_poll_intervals(&self) -> Result<(), String> {
+        let too_short = std::iter::once(("poll_interval_secs", &self.poll_interval_secs))
+            .chain(self.resource_poll_interval_secs.iter().map(|(id, secs)| (id.as_str(), secs)))
+            .chain(self.adaptive_polling.iter().map(|adaptive| ("adaptive_polling", &adaptive.interval_secs)))
+            .find(|(_, secs)| **secs < MIN_POLL_INTERVAL_SECS);
+        match too_short {
+            Some((name, secs)) => Err(format!(
+                "Polling interval {} of {} s is shorter than the minimum of {} s",
+                name, secs, MIN_POLL_INTERVAL_SECS
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The configuration in effect, replaced when the config file is reloaded.
+///
+/// Replaced configurations are leaked rather than dropped, since code may still hold references
+/// to them. Each reload leaks one, a few kilobytes, which is fine for reloads done by hand or on
+/// file changes, but not for reloading in a loop.
+pub(crate) struct Current(RwLock<&'static Config>);
+
+impl Current {
+    pub(crate) fn new(config: Config) -> Current {
+        Current(RwLock::new(Box::leak(Box::new(config))))
+    }
+
+    pub(crate) fn replace(&self, config: Config) {
+        *self.0.write().unwrap() = Box::leak(Box::new(config));
+    }
+}
+
+impl std::ops::Deref for Current {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        *self.0.read().unwrap()
+    }
+}
+
+/// Time the config file was last modified, or None if it can't be read.
+pub(crate) fn modified() -> Option<std::time::SystemTime> {
+    std::fs::metadata(path()).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Path of the config file: LASER_CONFIG, or the default from paths::default_config_file.
+pub(crate) fn path() -> String {
+    std::env::var("LASER_CONFIG").unwrap_or_else(|_| paths::default_config_file().display().to_string())
+}
+
+/// Load configuration from the config file and the environment.
+/// A missing config file is not an error; defaults are used instead.
+///
+/// # Panics
+///
+/// Panics if the configuration is invalid. See read.
+pub(crate) fn load() -> Config {
+    read().unwrap_or_else(|error| panic!("{}", error))
+}
+
+/// Read configuration from the config file and the environment, like load.
+/// Secrets can also be read from files, see secret.
+/// Fails if the config file cannot be read or parsed, if a polling interval is too short, if
+/// page_size is 0, if the proxy is invalid, or if TELEGRAM_BOT_TOKEN is not set.
+pub(crate) fn read() -> Result<Config, String> {
+    let path = path();
+    let mut config: Config = match std::fs::read_to_string(&path) {
+        Ok(contents) => toml::from_str(&contents).map_err(|error| format!("Failed to parse config file {}: {}", path, error))?,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Config::default(),
+        Err(error) => return Err(format!("Failed to read config file {}: {}", path, error)),
+    };
+    config.validate_poll_intervals()?;
+    config.circuit_breaker.validate()?;
+    if let Some(schedule) = &config.schedule {
+        if scheduler::next_occurrence(schedule, Local::now()).is_none() {
+            return Err(format!("Cron schedule {} never matches an upcoming time", schedule));
+        }
+    }
+    if config.page_size == 0 {
+        return Err("page_size must be at least 1".to_owned());
+    }
+    config.client.build().map_err(|error| format!("Invalid [client] config: {}", error))?;
+
+    // Unit tests never send anything, so they don't need a token.
+    config.telegram_bot_token = match secret("TELEGRAM_BOT_TOKEN")? {
+        Some(token) => token,
+        None if cfg!(test) => String::new(),
+        None => return Err("TELEGRAM_BOT_TOKEN is not set".to_owned()),
+    };
+    if let Some(chat_id) = secret("TELEGRAM_CHAT_ID")? {
+        config.telegram_chat_id = chat_id;
+    }
+    if let Some(api_token) = secret("RESPA_API_TOKEN")? {
+        config.auth.api_token = api_token;
+    }
+    Ok(config)
+}
+
+/// Value of the environment variable `name`, or the contents of the file named in `<name>_FILE`
+/// without the trailing newline, as with Docker and Kubernetes secrets. None if neither is set.
+fn secret(name: &str) -> Result<Option<String>, String> {
+    secret_from(name, |name| std::env::var(name).ok())
+}
+
+/// Like secret, but with the environment variables looked up with `var`.
+fn secret_from(name: &str, var: impl Fn(&str) -> Option<String>) -> Result<Option<String>, String> {
+    if let Some(value) = var(name) {
+        return Ok(Some(value));
+    }
+    let Some(file) = var(&format!("{}_FILE", name)) else {
+        return Ok(None);
+    };
+    let value = std::fs::read_to_string(&file).map_err(|error| format!("Failed to read {}_FILE {}: {}", name, file, error))?;
+    Ok(Some(value.trim_end_matches(['\r', '\n']).to_owned()))
+}
+
+/// Set a top-level value in the config file, keeping the rest of the file and its comments intact.
+/// The file is created if it does not exist.
+pub(crate) fn write_value(key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path();
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut document = contents.parse::<toml_edit::DocumentMut>()?;
+    document[key] = toml_edit::value(value);
+    if let Some(dir) = std::path::Path::new(&path).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, document.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_secrets_from_files() {
+        let path = std::env::temp_dir().join(format!("laser-rust-secret-{}", std::process::id()));
+        std::fs::write(&path, "123456:ABC\r\n").unwrap();
+        let file = path.display().to_string();
+        let secret = |variables: &[(&str, &str)]| {
+            let variables = variables.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect::<HashMap<_, _>>();
+            secret_from("TELEGRAM_BOT_TOKEN", |name| variables.get(name).cloned())
+        };
+
+        assert_eq!(secret(&[("TELEGRAM_BOT_TOKEN_FILE", &file)]), Ok(Some("123456:ABC".to_owned())));
+        let both = [("TELEGRAM_BOT_TOKEN", "654321:XYZ"), ("TELEGRAM_BOT_TOKEN_FILE", &file)];
+        assert_eq!(secret(&both), Ok(Some("654321:XYZ".to_owned())));
+        assert_eq!(secret(&[]), Ok(None));
+        std::fs::remove_file(&path).unwrap();
+        assert!(secret(&[("TELEGRAM_BOT_TOKEN_FILE", &file)]).unwrap_err().starts_with("Failed to read TELEGRAM_BOT_TOKEN_FILE"));
+    }
+
+    #[test]
+    fn polls_each_resource_at_its_own_interval() {
+        let config: Config = toml::from_str(
+            r#"
+            resource_id = "axwzr3i57yba"
+            poll_interval_secs = 300
+
+            [resource_poll_interval_secs]
+            axwzr3i57yba = 120
+            sewing = 3600
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.poll_interval(), Duration::from_secs(120));
+        assert_eq!(config.poll_interval_of("axwzr3i57yba"), Duration::from_secs(120));
+        assert_eq!(config.poll_interval_of("sewing"), Duration::from_secs(3600));
+        // Resources found in the unit without an interval of their own.
+        assert_eq!(config.poll_interval_of("embroidery"), Duration::from_secs(300));
+    }
+
+    #[test]
+    fn allows_only_the_operator_without_admins() {
+        let mut config = Config {
+            telegram_chat_id: "-100200300".to_owned(),
+            operator_chat_id: Some("123456789".to_owned()),
+            ..Config::default()
+        };
+        assert!(config.is_admin(Some(123456789)));
+        assert!(!config.is_admin(Some(987654321)));
+        assert!(!config.is_admin(None));
+
+        config.operator_chat_id = None;
+        assert!(!config.is_admin(Some(123456789)));
+
+        config.admins = vec![987654321];
+        assert!(config.is_admin(Some(987654321)));
+        assert!(!config.is_admin(Some(123456789)));
+    }
+
+    #[test]
+    fn posts_in_forum_topics_by_chat() {
+        let config: Config = toml::from_str(
+            r#"
+            [routes.axwzr3i57yba]
+            topics = { "-1001" = 7, "-1002" = 9 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.message_thread_id("axwzr3i57yba", "-1001"), Some(7));
+        assert_eq!(config.message_thread_id("axwzr3i57yba", "-1002"), Some(9));
+        assert_eq!(config.message_thread_id("axwzr3i57yba", "123456789"), None);
+        assert_eq!(config.message_thread_id("other", "-1001"), None);
+    }
+}
@@ -0,0 +1,164 @@
+use chrono::{DateTime, Days, Local, NaiveDate, NaiveTime, TimeZone};
+use handlebars::html_escape;
+
+use crate::server::{Snapshot, Status};
+use crate::timeslot::Timeslot;
+use crate::timezone;
+
+/// Number of days shown on the dashboard, starting today.
+const DAYS: u64 = 14;
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 1em; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #fff; padding: 0.2em 0.4em; font-size: 0.8em; }
+th { text-align: left; font-weight: normal; }
+.available { background: #4caf50; }
+.reserved { background: #e57373; }
+.closed { background: #eee; }
+.legend span { display: inline-block; padding: 0 0.5em; margin-right: 0.5em; }";
+
+/// State of an hour on the dashboard.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Hour {
+    Available,
+    Reserved,
+    Closed,
+}
+
+impl Hour {
+    fn class(self) -> &'static str {
+        match self {
+            Hour::Available => "available",
+            Hour::Reserved => "reserved",
+            Hour::Closed => "closed",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Hour::Available => "Available",
+            Hour::Reserved => "Reserved",
+            Hour::Closed => "Closed",
+        }
+    }
+}
+
+/// Render an HTML page with a grid of the hours of the next two weeks for each resource.
+/// Each hour is available, reserved, or closed, by its state at half past.
+pub(crate) fn render(snapshots: &[&Snapshot]) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Availability</title>\n<style>\n{}\n</style>\n</head>\n<body>\n",
+        STYLE
+    );
+    html.push_str("<p class=\"legend\">");
+    for state in [Hour::Available, Hour::Reserved, Hour::Closed] {
+        html.push_str(&format!("<span class=\"{}\">{}</span>", state.class(), state.label()));
+    }
+    html.push_str("</p>\n");
+    if snapshots.is_empty() {
+        html.push_str("<p>No data fetched yet.</p>\n");
+    }
+    for snapshot in snapshots {
+        html.push_str(&grid(snapshot));
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Render a small public status page, e.g. "Healthy, last checked 2 min ago, next free time Sat 10:00",
+/// for embedding in other sites. It refreshes itself every minute.
+pub(crate) fn render_status(status: &Status, ready: bool) -> String {
+    let health = match (status.paused, ready) {
+        (true, _) => "Paused",
+        (false, true) => "Healthy",
+        (false, false) => "Not checking",
+    };
+    let mut lines = vec![health.to_owned()];
+    if let Some(snapshot) = status.snapshot() {
+        lines.push(format!("last checked {}", ago(snapshot.fetched_at)));
+        let now = timezone::now();
+        let next = snapshot.available_times.iter().filter(|time| time.end_time() > now).min_by_key(|time| time.start_time());
+        lines.push(match next {
+            Some(time) => format!("next free time {}", time.start_time().max(now).format("%a %-d.%-m. %H:%M")),
+            None => "no free times".to_owned(),
+        });
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta http-equiv=\"refresh\" content=\"60\">\n\
+         <title>Status</title>\n<style>\nbody {{ font-family: sans-serif; margin: 0.5em; }}\n</style>\n</head>\n\
+         <body>\n<p>{}</p>\n</body>\n</html>\n",
+        html_escape(&lines.join(", "))
+    )
+}
+
+/// Time since `time` in words, e.g. "2 min ago".
+fn ago(time: DateTime<Local>) -> String {
+    let minutes = (Local::now() - time).num_minutes().max(0);
+    match minutes {
+        0 => "just now".to_owned(),
+        1..=59 => format!("{} min ago", minutes),
+        60..=1439 => format!("{} h ago", minutes / 60),
+        _ => format!("{} days ago", minutes / 1440),
+    }
+}
+
+fn grid(snapshot: &Snapshot) -> String {
+    let today = timezone::now().date_naive();
+    let days = (0..DAYS).filter_map(|day| today.checked_add_days(Days::new(day))).collect::<Vec<NaiveDate>>();
+    let hours = opening_hours(&snapshot.opening_times);
+
+    let mut html = format!(
+        "<h2><a href=\"{}\">{}</a></h2>\n<p>Checked {}</p>\n<table>\n<tr><th></th>",
+        html_escape(&snapshot.resource.booking_url()),
+        html_escape(&snapshot.resource.name),
+        timezone::zoned(&snapshot.fetched_at).format("%Y-%m-%d %H:%M")
+    );
+    for hour in hours.clone() {
+        html.push_str(&format!("<th>{:02}</th>", hour));
+    }
+    html.push_str("</tr>\n");
+
+    for day in days {
+        html.push_str(&format!("<tr><th>{}</th>", day.format("%a %-d.%-m.")));
+        for hour in hours.clone() {
+            let state = hour_state(&snapshot.opening_times, &snapshot.available_times, day, hour);
+            html.push_str(&format!("<td class=\"{}\"></td>", state.class()));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// Hours from the earliest opening to the latest closing, or office hours if nothing is open.
+pub(crate) fn opening_hours(opening_times: &[Timeslot]) -> std::ops::Range<u32> {
+    use chrono::Timelike;
+    let opens = opening_times.iter().map(|time| time.start_time().hour()).min();
+    let closes = opening_times
+        .iter()
+        .map(|time| time.end_time())
+        .map(|end| if end.minute() > 0 { end.hour() + 1 } else { end.hour() })
+        .max();
+    match (opens, closes) {
+        (Some(opens), Some(closes)) if opens < closes => opens..closes,
+        _ => 8..20,
+    }
+}
+
+/// State of an hour of a day, by its state at half past. An hour skipped when clocks are turned
+/// forward is closed.
+pub(crate) fn hour_state(opening_times: &[Timeslot], available_times: &[Timeslot], day: NaiveDate, hour: u32) -> Hour {
+    let Some(time) = NaiveTime::from_hms_opt(hour, 30, 0).and_then(|time| timezone::zone().from_local_datetime(&day.and_time(time)).earliest())
+    else {
+        return Hour::Closed;
+    };
+    let contains = |slot: &Timeslot| slot.start_time() <= time && time < slot.end_time();
+    if available_times.iter().any(contains) {
+        Hour::Available
+    } else if opening_times.iter().any(contains) {
+        Hour::Reserved
+    } else {
+        Hour::Closed
+    }
+}
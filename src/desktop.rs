@@ -0,0 +1,19 @@
+/// Show a native desktop notification.
+/// Failures are logged, since a missing notification daemon shouldn't stop monitoring.
+#[cfg(feature = "desktop")]
+pub(crate) fn notify(summary: &str, body: &str) {
+    let result = notify_rust::Notification::new()
+        .appname("laser-rust")
+        .summary(summary)
+        .body(body)
+        .show();
+    if let Err(error) = result {
+        tracing::warn!(%error, "Failed to show desktop notification");
+    }
+}
+
+/// Desktop notifications are only available when built with the "desktop" feature.
+#[cfg(not(feature = "desktop"))]
+pub(crate) fn notify(summary: &str, _body: &str) {
+    tracing::warn!(summary, "Desktop notifications are not available in this build");
+}
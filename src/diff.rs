@@ -0,0 +1,142 @@
+use serde::Deserialize;
+
+use crate::availability::IntervalSet;
+use crate::timeslot::Timeslot;
+
+/// Times that became available and are no longer available since the previous fetch.
+#[derive(Default)]
+pub(crate) struct Diff {
+    pub(crate) added: Vec<Timeslot>,
+    pub(crate) removed: Vec<Timeslot>,
+}
+
+/// A way of comparing the available times of a resource to the previously seen ones.
+pub(crate) trait DiffStrategy {
+    fn diff(&self, previous: &[Timeslot], current: &[Timeslot]) -> Diff;
+}
+
+/// Times are new or gone unless the exact same time was seen before and after.
+/// A block of free time that grows by an hour is announced again in full.
+pub(crate) struct ExactMatch;
+
+impl DiffStrategy for ExactMatch {
+    fn diff(&self, previous: &[Timeslot], current: &[Timeslot]) -> Diff {
+        Diff {
+            added: current.iter().filter(|time| !previous.contains(time)).cloned().collect(),
+            removed: previous.iter().filter(|time| !current.contains(time)).cloned().collect(),
+        }
+    }
+}
+
+/// Only the minutes not covered before are new, and only the minutes not covered now are gone.
+/// A block that grows is announced by the part it grew by, and a block that shrinks adds nothing.
+pub(crate) struct IntervalAware;
+
+impl DiffStrategy for IntervalAware {
+    fn diff(&self, previous: &[Timeslot], current: &[Timeslot]) -> Diff {
+        let diff = Diff {
+            added: subtract(current, previous),
+            removed: subtract(previous, current),
+        };
+        for time in &diff.added {
+            if previous.iter().any(|other| touches(time, other)) {
+                tracing::debug!(%time, "Available time grew");
+            }
+        }
+        for time in &diff.removed {
+            if current.iter().any(|other| touches(time, other)) {
+                tracing::debug!(%time, "Available time shrank");
+            }
+        }
+        diff
+    }
+}
+
+/// Like IntervalAware, but changes shorter than `minutes` are ignored, e.g. a block growing
+/// by a few minutes when a reservation is shortened.
+pub(crate) struct Threshold {
+    pub(crate) minutes: i64,
+}
+
+impl DiffStrategy for Threshold {
+    fn diff(&self, previous: &[Timeslot], current: &[Timeslot]) -> Diff {
+        let long_enough = |time: &Timeslot| (time.end_time() - time.start_time()).num_minutes() >= self.minutes;
+        let diff = IntervalAware.diff(previous, current);
+        Diff {
+            added: diff.added.into_iter().filter(long_enough).collect(),
+            removed: diff.removed.into_iter().filter(long_enough).collect(),
+        }
+    }
+}
+
+/// Diff strategy of a resource in the config, e.g. `{ strategy = "threshold", minutes = 15 }`.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(tag = "strategy", rename_all = "lowercase")]
+pub(crate) enum DiffConfig {
+    #[default]
+    Exact,
+    Interval,
+    Threshold {
+        minutes: i64,
+    },
+}
+
+impl DiffConfig {
+    pub(crate) fn strategy(&self) -> Box<dyn DiffStrategy> {
+        match *self {
+            DiffConfig::Exact => Box::new(ExactMatch),
+            DiffConfig::Interval => Box::new(IntervalAware),
+            DiffConfig::Threshold { minutes } => Box::new(Threshold { minutes }),
+        }
+    }
+}
+
+/// The parts of `times` not covered by any of `covered`.
+fn subtract(times: &[Timeslot], covered: &[Timeslot]) -> Vec<Timeslot> {
+    IntervalSet::from_timeslots(times).subtract(&IntervalSet::from_timeslots(covered)).to_timeslots()
+}
+
+/// Return true if the times overlap or one ends when the other starts.
+fn touches(a: &Timeslot, b: &Timeslot) -> bool {
+    a.start_time() <= b.end_time() && b.start_time() <= a.end_time()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(start: &str, end: &str) -> Timeslot {
+        Timeslot {
+            start: format!("2023-12-01T{}:00+02:00", start),
+            end: format!("2023-12-01T{}:00+02:00", end),
+        }
+    }
+
+    fn hours(times: &[Timeslot]) -> Vec<String> {
+        times
+            .iter()
+            .map(|time| format!("{}-{}", time.start_time().format("%H:%M"), time.end_time().format("%H:%M")))
+            .collect()
+    }
+
+    #[test]
+    fn announces_only_the_minutes_a_time_grew_by() {
+        let previous = [time("10:00", "12:00"), time("14:00", "16:00")];
+        let current = [time("09:00", "12:00"), time("15:00", "16:00"), time("18:00", "19:00")];
+        let diff = IntervalAware.diff(&previous, &current);
+        assert_eq!(hours(&diff.added), ["09:00-10:00", "18:00-19:00"]);
+        assert_eq!(hours(&diff.removed), ["14:00-15:00"]);
+
+        let exact = ExactMatch.diff(&previous, &current);
+        assert_eq!(hours(&exact.added), ["09:00-12:00", "15:00-16:00", "18:00-19:00"]);
+    }
+
+    #[test]
+    fn ignores_changes_shorter_than_the_threshold() {
+        let previous = [time("10:00", "12:00"), time("14:00", "16:00")];
+        let current = [time("10:00", "12:10"), time("14:00", "15:00"), time("18:00", "18:15")];
+        let diff = Threshold { minutes: 15 }.diff(&previous, &current);
+        assert_eq!(hours(&diff.added), ["18:00-18:15"]);
+        assert_eq!(hours(&diff.removed), ["15:00-16:00"]);
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::Notifier;
+use crate::heatmap;
+use crate::message::Resource;
+use crate::state::State;
+use crate::telegram;
+use crate::timeslot::Timeslot;
+use crate::timezone;
+use crate::CONFIG;
+
+/// What to send a chat when the digest is the same as the one it got the previous time.
+/// Chats choose with "/digest send|note|skip".
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum UnchangedDigest {
+    /// Send the digest again.
+    #[default]
+    Send,
+    /// Send a one-line "no changes" note instead.
+    Note,
+    /// Send nothing.
+    Skip,
+}
+
+impl UnchangedDigest {
+    pub(crate) fn parse(value: &str) -> Option<UnchangedDigest> {
+        match value {
+            "send" => Some(UnchangedDigest::Send),
+            "note" => Some(UnchangedDigest::Note),
+            "skip" => Some(UnchangedDigest::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// Send the daily digest of all available times to the subscribers, once `digest_time` has passed today.
+/// With `digest_heatmap`, chats that got the digest also get a heatmap of the coming week.
+pub(crate) fn send_if_due(state: &mut State, resource: &Resource, opening_times: &[Timeslot], available_times: &[Timeslot]) {
+    let Some(digest_time) = CONFIG.digest_time else {
+        return;
+    };
+    if !CONFIG.notifiers_for(&resource.id).contains(&Notifier::Telegram) {
+        return;
+    }
+    let now = timezone::now();
+    if now.time() < digest_time || state.digest_sent_on == Some(now.date_naive()) {
+        return;
+    }
+
+    let mut digest = format!("{}, {}:\n", CONFIG.language.digest_heading(), resource.name);
+    for time in available_times {
+        digest.push_str(&time.localized(CONFIG.language));
+        digest.push('\n');
+    }
+    tracing::info!(count = available_times.len(), "Sending daily digest");
+
+    let delivered = telegram::broadcast_each_about(state, &resource.id, false, |subscriber| {
+        if !CONFIG.routes_to(&resource.id, subscriber) {
+            return None;
+        }
+        if subscriber.last_digest.as_deref() != Some(digest.as_str()) {
+            return Some(digest.clone());
+        }
+        match subscriber.unchanged_digest {
+            UnchangedDigest::Send => Some(digest.clone()),
+            UnchangedDigest::Note => Some(CONFIG.language.digest_unchanged().to_owned()),
+            UnchangedDigest::Skip => None,
+        }
+    });
+    if CONFIG.digest_heatmap {
+        heatmap::send(&delivered, resource, opening_times, available_times);
+    }
+    // Chats the digest didn't reach compare the next one to the last digest they actually got.
+    for subscriber in state.subscribers.iter_mut().filter(|subscriber| delivered.contains(&subscriber.chat_id)) {
+        subscriber.last_digest = Some(digest.clone());
+    }
+    state.digest_sent_on = Some(now.date_naive());
+}
@@ -0,0 +1,91 @@
+use crate::config::{self, Notifier};
+use crate::state::{self, State};
+use crate::telegram::SendError;
+use crate::{desktop, own_reservations, respa, telegram, unit, CONFIG};
+
+/// Check the configuration, the respa API for each monitored resource, the state file and the
+/// credentials of each notifier, printing the result of each check. With `send_test`, a test
+/// message is sent through each notifier too. Returns true if all checks passed.
+///
+/// The configuration is checked first, since nothing else can be checked without it.
+pub(crate) fn run(send_test: bool) -> bool {
+    let mut passed = true;
+    let mut report = |name: &str, result: Result<String, String>| match result {
+        Ok(detail) if detail.is_empty() => println!("ok      {}", name),
+        Ok(detail) => println!("ok      {}: {}", name, detail),
+        Err(error) => {
+            println!("FAILED  {}: {}", name, error);
+            passed = false;
+        }
+    };
+
+    let config = config::read();
+    report("config", config.as_ref().map(|_| config::path()).map_err(String::clone));
+    if config.is_err() {
+        println!("Doctor found problems.");
+        return false;
+    }
+
+    if let Some(unit) = &CONFIG.unit {
+        let resources = respa::unit_resources(&unit.id).map(|resources| format!("{} reservable resources", resources.len()));
+        report(&format!("unit {}", unit.id), resources.map_err(|error| error.to_string()));
+    }
+    for resource_id in unit::monitored_resources(&mut State::default()) {
+        let name = respa::fetch_resource_data(&resource_id)
+            .map(|api_data| respa::parse_resource(&resource_id, &api_data).name)
+            .map_err(|error| error.to_string());
+        report(&format!("respa API for {}", resource_id), name);
+    }
+    if !CONFIG.auth.api_token.is_empty() {
+        let reservations = own_reservations::fetch(&CONFIG.resource_id)
+            .map(|reservations| format!("{} upcoming own reservations", reservations.len()))
+            .map_err(|error| error.to_string());
+        report("respa API token", reservations);
+    }
+
+    report("state file", state::check());
+
+    for notifier in &CONFIG.notifiers {
+        let result = match notifier {
+            Notifier::Telegram => check_telegram(send_test),
+            Notifier::Desktop => check_desktop(send_test),
+        };
+        report(notifier.name(), result);
+    }
+
+    println!("{}", if passed { "All checks passed." } else { "Doctor found problems." });
+    passed
+}
+
+/// Check the bot token and that a chat is configured, and send the test message to the chat.
+fn check_telegram(send_test: bool) -> Result<String, String> {
+    let username = telegram::get_me().map_err(|error| format!("getMe failed: {}", without_token(error)))?;
+    if CONFIG.telegram_chat_id.is_empty() {
+        return Err(format!("bot @{} works, but no chat is configured", username));
+    }
+    if send_test {
+        telegram::send_message(&CONFIG.telegram_chat_id, CONFIG.language.test_message())
+            .map_err(|error| format!("test message to {} failed: {}", CONFIG.telegram_chat_id, without_token(error)))?;
+        return Ok(format!("bot @{}, test message sent to {}", username, CONFIG.telegram_chat_id));
+    }
+    Ok(format!("bot @{}, chat {}", username, CONFIG.telegram_chat_id))
+}
+
+/// Describe a Telegram error without the request URL, which contains the bot token.
+fn without_token(error: SendError) -> String {
+    match error {
+        SendError::Network(error) => error.without_url().to_string(),
+        error => error.to_string(),
+    }
+}
+
+fn check_desktop(send_test: bool) -> Result<String, String> {
+    if !cfg!(feature = "desktop") {
+        return Err("not available in this build, build with --features desktop".to_owned());
+    }
+    if send_test {
+        desktop::notify("laser-rust", CONFIG.language.test_message());
+        return Ok("test notification sent".to_owned());
+    }
+    Ok(String::new())
+}
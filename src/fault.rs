@@ -0,0 +1,178 @@
+use reqwest::StatusCode;
+use serde_json::json;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use crate::respa::FetchError;
+use crate::timezone;
+
+/// A simulated respa API response, for testing how failures are handled.
+///
+/// Faults are injected into respa requests in the order they are scheduled. With the
+/// "fault-injection" feature, the schedule is read from LASER_FAULTS, e.g.
+/// `LASER_FAULTS=timeout,429,ok`. Requests are made normally once the schedule runs out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Fault {
+    /// The request times out.
+    Timeout,
+    /// The API responds 429 Too Many Requests.
+    RateLimited,
+    /// The API responds 503 Service Unavailable.
+    ServerError,
+    /// The response is not JSON, e.g. an error page from a proxy.
+    MalformedJson,
+    /// The response is cut off in the middle.
+    Truncated,
+    /// The response is valid JSON, but is missing the opening hours and reservations.
+    Partial,
+    /// A valid response with the resource open from 10 to 18 tomorrow, reserved from 12 to 14.
+    Ok,
+    /// The API responds 304 Not Modified, as to a conditional request for unchanged data.
+    NotModified,
+}
+
+thread_local! {
+    // Per thread, so tests running in parallel don't take each other's faults. Tests schedule
+    // their own, so only the program reads LASER_FAULTS.
+    static SCHEDULE: RefCell<VecDeque<Fault>> = RefCell::new(if cfg!(test) {
+        VecDeque::new()
+    } else {
+        std::env::var("LASER_FAULTS").map(|faults| parse(&faults)).unwrap_or_default()
+    });
+}
+
+/// Replace the faults to inject into the following requests made on this thread.
+#[cfg(test)]
+pub(crate) fn schedule(faults: &[Fault]) {
+    SCHEDULE.with(|schedule| *schedule.borrow_mut() = faults.iter().copied().collect());
+}
+
+/// Take the fault to inject into the next request, if any.
+pub(crate) fn next() -> Option<Fault> {
+    SCHEDULE.with(|schedule| schedule.borrow_mut().pop_front())
+}
+
+/// Number of scheduled faults not injected yet.
+#[cfg(test)]
+pub(crate) fn remaining() -> usize {
+    SCHEDULE.with(|schedule| schedule.borrow().len())
+}
+
+/// Parse a comma-separated list of faults like "timeout,429,ok".
+fn parse(faults: &str) -> VecDeque<Fault> {
+    faults
+        .split(',')
+        .map(|fault| match fault.trim() {
+            "timeout" => Fault::Timeout,
+            "429" => Fault::RateLimited,
+            "503" => Fault::ServerError,
+            "malformed" => Fault::MalformedJson,
+            "truncated" => Fault::Truncated,
+            "partial" => Fault::Partial,
+            "ok" => Fault::Ok,
+            "304" => Fault::NotModified,
+            other => panic!("Unknown fault in LASER_FAULTS: {}", other),
+        })
+        .collect()
+}
+
+impl Fault {
+    /// The response status and body of the fault, or the error it causes.
+    pub(crate) fn response(self) -> Result<(StatusCode, String), FetchError> {
+        let tomorrow = (timezone::now() + chrono::Days::new(1)).format("%Y-%m-%d").to_string();
+        let at = |hour: u32| format!("{}T{:02}:00:00{}", tomorrow, hour, timezone::now().format("%:z"));
+        let valid = json!({
+            "id": crate::CONFIG.resource_id,
+            "name": { "fi": "Laserleikkuri", "en": "Laser cutter" },
+            "opening_hours": [{ "date": tomorrow, "opens": at(10), "closes": at(18) }],
+            "reservations": [{ "begin": at(12), "end": at(14) }],
+        })
+        .to_string();
+
+        match self {
+            Fault::Timeout => Err(FetchError::Timeout),
+            Fault::RateLimited => Ok((
+                StatusCode::TOO_MANY_REQUESTS,
+                r#"{"detail": "Request was throttled."}"#.to_owned(),
+            )),
+            Fault::ServerError => Ok((StatusCode::SERVICE_UNAVAILABLE, "Service Unavailable".to_owned())),
+            Fault::MalformedJson => Ok((StatusCode::OK, "<html><body>Bad Gateway</body></html>".to_owned())),
+            Fault::Truncated => Ok((StatusCode::OK, valid[..valid.len() / 2].to_owned())),
+            Fault::Partial => Ok((StatusCode::OK, json!({ "id": crate::CONFIG.resource_id }).to_string())),
+            Fault::Ok => Ok((StatusCode::OK, valid)),
+            Fault::NotModified => Ok((StatusCode::NOT_MODIFIED, String::new())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::respa;
+    use crate::state::State;
+
+    fn fetch() -> Result<serde_json::Value, FetchError> {
+        respa::fetch_resource_data(&crate::CONFIG.resource_id)
+    }
+
+    #[test]
+    fn parses_faults_in_order() {
+        assert_eq!(parse("timeout, 429,ok"), [Fault::Timeout, Fault::RateLimited, Fault::Ok]);
+    }
+
+    #[test]
+    fn retries_after_timeout() {
+        schedule(&[Fault::Timeout, Fault::Ok]);
+        assert!(fetch().is_ok());
+        assert_eq!(remaining(), 0);
+    }
+
+    #[test]
+    fn retries_after_rate_limit_and_server_error() {
+        schedule(&[Fault::RateLimited, Fault::ServerError, Fault::Ok]);
+        assert!(fetch().is_ok());
+    }
+
+    #[test]
+    fn gives_up_after_repeated_rate_limits() {
+        schedule(&[Fault::RateLimited, Fault::RateLimited, Fault::RateLimited, Fault::Ok]);
+        let error = fetch().unwrap_err();
+        assert!(matches!(error, FetchError::Api { status: 429, .. }), "{}", error);
+        assert_eq!(remaining(), 1);
+    }
+
+    #[test]
+    fn does_not_retry_malformed_json() {
+        schedule(&[Fault::MalformedJson, Fault::Ok]);
+        assert!(matches!(fetch(), Err(FetchError::InvalidResponse(_))));
+        assert_eq!(remaining(), 1);
+    }
+
+    #[test]
+    fn rejects_truncated_response() {
+        schedule(&[Fault::Truncated]);
+        assert!(matches!(fetch(), Err(FetchError::InvalidResponse(_))));
+    }
+
+    #[test]
+    fn uses_cached_response_when_not_modified() {
+        schedule(&[Fault::Ok, Fault::NotModified]);
+        let fetched = fetch().unwrap();
+        assert_eq!(fetch().unwrap(), fetched);
+    }
+
+    #[test]
+    fn rejects_partial_response_instead_of_reporting_no_availability() {
+        schedule(&[Fault::Partial]);
+        let error = crate::fetch_availability(&mut State::default(), &crate::CONFIG.resource_id).err().unwrap();
+        assert!(matches!(error, FetchError::IncompleteResponse("opening_hours")), "{}", error);
+    }
+
+    #[test]
+    fn computes_availability_from_valid_response() {
+        schedule(&[Fault::Ok]);
+        let snapshot = crate::fetch_availability(&mut State::default(), &crate::CONFIG.resource_id).ok().unwrap();
+        let hours = snapshot.available_times.iter().map(|time| time.duration()).collect::<Vec<i64>>();
+        assert_eq!(hours, vec![2, 4]);
+    }
+}
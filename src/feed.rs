@@ -0,0 +1,52 @@
+use chrono::{DateTime, Local};
+use handlebars::html_escape;
+
+use crate::changes::{Change, ChangeKind};
+use crate::message::Resource;
+use crate::timeslot::Timeslot;
+use crate::CONFIG;
+
+/// Number of entries in the feed.
+const MAX_ENTRIES: usize = 50;
+
+/// Write an Atom feed with an entry for each batch of times that became available, newest first.
+/// A batch is the times of a resource found on the same fetch.
+pub(crate) fn write_feed(resource: &Resource, changes: &[Change]) -> String {
+    let mut batches: Vec<(DateTime<Local>, Vec<Timeslot>)> = Vec::new();
+    for change in changes.iter().filter(|change| change.kind == ChangeKind::Added && change.resource == resource.id) {
+        let time = Timeslot {
+            start: change.start.clone(),
+            end: change.end.clone(),
+        };
+        match batches.last_mut() {
+            Some((detected_at, times)) if *detected_at == change.detected_at => times.push(time),
+            _ => batches.push((change.detected_at, vec![time])),
+        }
+    }
+    let updated = batches.last().map(|(detected_at, _)| *detected_at).unwrap_or_else(Local::now);
+
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         <id>urn:laser-rust:{}</id>\n<title>{}</title>\n<updated>{}</updated>\n<link href=\"{}\"/>\n<author><name>laser-rust</name></author>\n",
+        html_escape(&resource.id),
+        html_escape(&resource.name),
+        updated.to_rfc3339(),
+        html_escape(&resource.booking_url())
+    );
+    for (detected_at, times) in batches.iter().rev().take(MAX_ENTRIES) {
+        let text = times.iter().map(|time| time.localized(CONFIG.language)).collect::<Vec<String>>().join("\n");
+        xml.push_str(&format!(
+            "<entry>\n<id>urn:laser-rust:{}:{}</id>\n<title>{}: {} new available times</title>\n\
+             <updated>{}</updated>\n<link href=\"{}\"/>\n<content type=\"text\">{}</content>\n</entry>\n",
+            html_escape(&resource.id),
+            detected_at.timestamp_millis(),
+            html_escape(&resource.name),
+            times.len(),
+            detected_at.to_rfc3339(),
+            html_escape(&resource.booking_url()),
+            html_escape(&text)
+        ));
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
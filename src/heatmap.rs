@@ -0,0 +1,100 @@
+use crate::message::Resource;
+use crate::timeslot::Timeslot;
+
+/// Send a heatmap of the coming week's hours, colored by whether they are available, reserved
+/// or closed, to each of the chats. Failures are logged.
+#[cfg(feature = "heatmap")]
+pub(crate) fn send(chat_ids: &[String], resource: &Resource, opening_times: &[Timeslot], available_times: &[Timeslot]) {
+    if chat_ids.is_empty() {
+        return;
+    }
+    let image = match render(resource, opening_times, available_times) {
+        Ok(image) => image,
+        Err(error) => {
+            tracing::warn!(%error, "Failed to draw heatmap");
+            return;
+        }
+    };
+    for chat_id in chat_ids {
+        let message_thread_id = crate::CONFIG.message_thread_id(&resource.id, chat_id);
+        if let Err(error) = crate::telegram::send_photo(chat_id, image.clone(), &resource.name, message_thread_id) {
+            tracing::warn!(%chat_id, %error, "Failed to send heatmap");
+        }
+    }
+}
+
+/// Heatmaps are only available when built with the "heatmap" feature.
+#[cfg(not(feature = "heatmap"))]
+pub(crate) fn send(_chat_ids: &[String], _resource: &Resource, _opening_times: &[Timeslot], _available_times: &[Timeslot]) {
+    tracing::warn!("Heatmaps are not available in this build");
+}
+
+/// Draw the heatmap as a PNG image, with a row for each day and a column for each opening hour.
+#[cfg(feature = "heatmap")]
+fn render(
+    resource: &Resource,
+    opening_times: &[Timeslot],
+    available_times: &[Timeslot],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    use chrono::{Days, NaiveDate};
+    use plotters::prelude::*;
+
+    use crate::dashboard::{self, Hour};
+    use crate::timezone;
+
+    let today = timezone::now().date_naive();
+    let days = (0..7).filter_map(|day| today.checked_add_days(Days::new(day))).collect::<Vec<NaiveDate>>();
+    let hours = dashboard::opening_hours(opening_times);
+
+    // plotters writes bitmaps to files, so draw to a temporary file and read it back.
+    let path = std::env::temp_dir().join(format!("laser-rust-heatmap-{}.png", std::process::id()));
+    {
+        let root = BitMapBackend::new(&path, (800, 360)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(&resource.name, ("sans-serif", 24))
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(70)
+            .build_cartesian_2d(hours.clone(), (0..days.len() as u32 - 1).into_segmented())?;
+        chart
+            .configure_mesh()
+            .disable_mesh()
+            .x_labels(hours.len() + 1)
+            .x_label_formatter(&|hour| format!("{:02}", hour))
+            .y_labels(days.len())
+            // Rows are numbered from the bottom, so the first day gets the last row.
+            .y_label_formatter(&|row| match row {
+                SegmentValue::CenterOf(row) => days
+                    .len()
+                    .checked_sub(1 + *row as usize)
+                    .map(|index| days[index].format("%a %-d.%-m.").to_string())
+                    .unwrap_or_default(),
+                _ => String::new(),
+            })
+            .draw()?;
+
+        let color = |state: Hour| match state {
+            Hour::Available => RGBColor(0x4c, 0xaf, 0x50),
+            Hour::Reserved => RGBColor(0xe5, 0x73, 0x73),
+            Hour::Closed => RGBColor(0xee, 0xee, 0xee),
+        };
+        let rows = days.len();
+        let cells = days.iter().enumerate().flat_map(|(index, day)| {
+            let row = (rows - 1 - index) as u32;
+            hours.clone().map(move |hour| {
+                let state = dashboard::hour_state(opening_times, available_times, *day, hour);
+                Rectangle::new(
+                    [(hour, SegmentValue::Exact(row)), (hour + 1, SegmentValue::Exact(row + 1))],
+                    color(state).filled(),
+                )
+            })
+        });
+        chart.draw_series(cells)?;
+        root.present()?;
+    }
+
+    let image = std::fs::read(&path)?;
+    std::fs::remove_file(&path).ok();
+    Ok(image)
+}
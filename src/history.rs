@@ -0,0 +1,78 @@
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::timeslot::Timeslot;
+
+/// Number of observations kept in the state, about a week at the default polling interval.
+const MAX_OBSERVATIONS: usize = 2000;
+
+/// Days observations are kept, so checking more often than the default doesn't replace the week
+/// with a day or two.
+const MAX_OBSERVATION_DAYS: i64 = 7;
+
+/// The availability of a resource seen on a fetch, summarized per day.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Observation {
+    pub(crate) observed_at: DateTime<Local>,
+    pub(crate) resource: String,
+    pub(crate) days: BTreeMap<NaiveDate, Day>,
+}
+
+/// Opening and available minutes of a day.
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub(crate) struct Day {
+    pub(crate) open_minutes: i64,
+    pub(crate) free_minutes: i64,
+}
+
+impl Observation {
+    pub(crate) fn new(
+        resource: &str,
+        opening_times: &[Timeslot],
+        available_times: &[Timeslot],
+        observed_at: DateTime<Local>,
+    ) -> Observation {
+        let mut days: BTreeMap<NaiveDate, Day> = BTreeMap::new();
+        let minutes = |time: &Timeslot| (time.end_time() - time.start_time()).num_minutes();
+        for time in opening_times {
+            days.entry(time.start_time().date_naive()).or_default().open_minutes += minutes(time);
+        }
+        for time in available_times {
+            days.entry(time.start_time().date_naive()).or_default().free_minutes += minutes(time);
+        }
+        Observation {
+            observed_at,
+            resource: resource.to_owned(),
+            days,
+        }
+    }
+}
+
+/// Append an observation to the history, dropping those older than MAX_OBSERVATION_DAYS and the
+/// oldest ones beyond MAX_OBSERVATIONS.
+pub(crate) fn record(history: &mut Vec<Observation>, observation: Observation) {
+    let oldest = observation.observed_at - chrono::Duration::days(MAX_OBSERVATION_DAYS);
+    history.retain(|kept| kept.observed_at >= oldest);
+    history.push(observation);
+    if history.len() > MAX_OBSERVATIONS {
+        history.drain(..history.len() - MAX_OBSERVATIONS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn keeps_a_week_of_observations() {
+        let observation = |day| Observation::new("axwzr3i57yba", &[], &[], Local.with_ymd_and_hms(2024, 5, day, 12, 0, 0).unwrap());
+        let mut history = Vec::new();
+        for day in 1..=10 {
+            record(&mut history, observation(day));
+        }
+        let days = history.iter().map(|kept| kept.observed_at.date_naive().format("%d").to_string()).collect::<Vec<_>>();
+        assert_eq!(days, ["03", "04", "05", "06", "07", "08", "09", "10"]);
+    }
+}
@@ -0,0 +1,129 @@
+use chrono::{Datelike, Days, NaiveDate, Weekday};
+use serde::Deserialize;
+
+use crate::CONFIG;
+
+/// How Finnish public holidays are treated by the weekdays of the wishlist and interest weights.
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum HolidayMode {
+    /// A holiday is the weekday it falls on.
+    #[default]
+    Weekday,
+    /// A holiday is like a Sunday, e.g. Vappu matches "sun" but not "wed".
+    Weekend,
+    /// Nothing matches on holidays.
+    Exclude,
+}
+
+/// The configured holiday mode. Unit tests use the default, so they don't need a configuration.
+fn mode() -> HolidayMode {
+    if cfg!(test) {
+        HolidayMode::Weekday
+    } else {
+        CONFIG.holidays
+    }
+}
+
+/// Whether a date matches a list of weekdays, taking holidays into account. An empty list
+/// matches every day.
+pub(crate) fn matches(weekdays: &[Weekday], date: NaiveDate) -> bool {
+    matches_in(mode(), weekdays, date)
+}
+
+fn matches_in(mode: HolidayMode, weekdays: &[Weekday], date: NaiveDate) -> bool {
+    let weekday = match mode {
+        HolidayMode::Weekday => date.weekday(),
+        HolidayMode::Weekend if is_holiday(date) => Weekday::Sun,
+        HolidayMode::Weekend => date.weekday(),
+        HolidayMode::Exclude if is_holiday(date) => return false,
+        HolidayMode::Exclude => date.weekday(),
+    };
+    weekdays.is_empty() || weekdays.contains(&weekday)
+}
+
+/// Whether the date is a Finnish public holiday, or Midsummer Eve or Christmas Eve, which are
+/// days off for most.
+pub(crate) fn is_holiday(date: NaiveDate) -> bool {
+    let year = date.year();
+    let fixed = |month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+    let easter = easter_sunday(year);
+    // Midsummer Day and All Saints' Day are on the Saturday of a given week.
+    let saturday_from = |month, day| {
+        let first = fixed(month, day);
+        first + Days::new(u64::from((Weekday::Sat.num_days_from_monday() + 7 - first.weekday().num_days_from_monday()) % 7))
+    };
+    let midsummer_day = saturday_from(6, 20);
+
+    [
+        fixed(1, 1),            // New Year's Day
+        fixed(1, 6),            // Epiphany
+        easter - Days::new(2),  // Good Friday
+        easter,                 // Easter Sunday
+        easter + Days::new(1),  // Easter Monday
+        fixed(5, 1),            // May Day (Vappu)
+        easter + Days::new(39), // Ascension Day
+        easter + Days::new(49), // Pentecost
+        midsummer_day - Days::new(1), // Midsummer Eve
+        midsummer_day,                // Midsummer Day
+        saturday_from(10, 31),  // All Saints' Day
+        fixed(12, 6),           // Independence Day
+        fixed(12, 24),          // Christmas Eve
+        fixed(12, 25),          // Christmas Day
+        fixed(12, 26),          // St. Stephen's Day
+    ]
+    .contains(&date)
+}
+
+/// Easter Sunday of a year in the Gregorian calendar.
+fn easter_sunday(year: i32) -> NaiveDate {
+    // The anonymous Gregorian algorithm.
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn finds_moving_holidays() {
+        assert_eq!(easter_sunday(2024), date(2024, 3, 31));
+        assert_eq!(easter_sunday(2025), date(2025, 4, 20));
+        // Good Friday, Ascension Day, Midsummer Eve and All Saints' Day in 2025.
+        for holiday in [date(2025, 4, 18), date(2025, 5, 29), date(2025, 6, 20), date(2025, 11, 1)] {
+            assert!(is_holiday(holiday), "{} should be a holiday", holiday);
+        }
+        assert!(!is_holiday(date(2025, 6, 19)));
+        assert!(!is_holiday(date(2025, 10, 31)));
+    }
+
+    #[test]
+    fn treats_holidays_by_mode() {
+        // Vappu 2024 is a Wednesday.
+        let vappu = date(2024, 5, 1);
+        let weekdays = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+        assert!(matches_in(HolidayMode::Weekday, &weekdays, vappu));
+        assert!(!matches_in(HolidayMode::Weekend, &weekdays, vappu));
+        assert!(matches_in(HolidayMode::Weekend, &[Weekday::Sat, Weekday::Sun], vappu));
+        assert!(!matches_in(HolidayMode::Exclude, &[], vappu));
+        assert!(matches_in(HolidayMode::Exclude, &weekdays, date(2024, 5, 2)));
+    }
+}
@@ -0,0 +1,728 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::digest::UnchangedDigest;
+use crate::stats::LeadTime;
+use crate::timezone;
+
+/// Language of notification texts and slot formatting.
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Language {
+    #[default]
+    En,
+    Fi,
+}
+
+impl Language {
+    /// Language code used by the respa API, e.g. "fi".
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::Fi => "fi",
+        }
+    }
+
+    /// Pick the text in this language from a respa translated field like
+    /// `{"fi": "Laserleikkuri", "en": "Laser cutter", "sv": "Laserskärare"}`.
+    /// Falls back to Finnish, English and Swedish, in that order.
+    pub(crate) fn pick(self, translations: &serde_json::Value) -> Option<String> {
+        [self.code(), "fi", "en", "sv"]
+            .iter()
+            .filter_map(|code| translations[*code].as_str())
+            .find(|text| !text.is_empty())
+            .map(str::to_owned)
+    }
+
+    /// chrono format string for dates, e.g. "2023-12-01" or "1.12.2023".
+    pub(crate) fn date_format(self) -> &'static str {
+        match self {
+            Language::En => "%Y-%m-%d",
+            Language::Fi => "%-d.%-m.%Y",
+        }
+    }
+
+    /// Date and time in the configured time zone, e.g. "2024-03-15 17:00".
+    fn date_time(self, time: DateTime<Local>) -> String {
+        timezone::zoned(&time).format(&format!("{} %H:%M", self.date_format())).to_string()
+    }
+
+    /// Length of a time, e.g. "2 h", "1 h 30 min" or "45 min", or in Finnish "1 t 30 min".
+    pub(crate) fn duration(self, minutes: i64) -> String {
+        let hours_unit = match self {
+            Language::En => "h",
+            Language::Fi => "t",
+        };
+        match (minutes / 60, minutes % 60) {
+            (0, minutes) => format!("{} min", minutes),
+            (hours, 0) => format!("{} {}", hours, hours_unit),
+            (hours, minutes) => format!("{} {} {} min", hours, hours_unit, minutes),
+        }
+    }
+
+    /// Heading for a list of new available times.
+    pub(crate) fn new_available_times(self) -> &'static str {
+        match self {
+            Language::En => "New available times",
+            Language::Fi => "Uusia vapaita aikoja",
+        }
+    }
+
+    /// Heading for the times of a day, e.g. "Friday 1.12." or "perjantai 1.12.".
+    pub(crate) fn day_heading(self, date: NaiveDate) -> String {
+        let weekday = match self {
+            Language::En => date.format("%A").to_string(),
+            Language::Fi => {
+                const WEEKDAYS: [&str; 7] = ["maanantai", "tiistai", "keskiviikko", "torstai", "perjantai", "lauantai", "sunnuntai"];
+                WEEKDAYS[date.weekday().num_days_from_monday() as usize].to_owned()
+            }
+        };
+        format!("{} {}", weekday, date.format("%-d.%-m."))
+    }
+
+    /// Short name of a weekday, e.g. "Sat" or "la".
+    pub(crate) fn weekday_short(self, weekday: Weekday) -> &'static str {
+        const EN: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+        const FI: [&str; 7] = ["ma", "ti", "ke", "to", "pe", "la", "su"];
+        let index = weekday.num_days_from_monday() as usize;
+        match self {
+            Language::En => EN[index],
+            Language::Fi => FI[index],
+        }
+    }
+
+    /// Summary of the new times, e.g. "3 new slots, total 7 h".
+    pub(crate) fn new_times_summary(self, count: usize, minutes: i64) -> String {
+        let total = self.duration(minutes);
+        match (self, count) {
+            (Language::En, 1) => format!("1 new slot, total {}", total),
+            (Language::En, _) => format!("{} new slots, total {}", count, total),
+            (Language::Fi, 1) => format!("1 uusi aika, yhteensä {}", total),
+            (Language::Fi, _) => format!("{} uutta aikaa, yhteensä {}", count, total),
+        }
+    }
+
+    /// Label for a short reservation inside a block of available time.
+    pub(crate) fn reserved(self) -> &'static str {
+        match self {
+            Language::En => "reserved",
+            Language::Fi => "varattu",
+        }
+    }
+
+    /// Note on a new time that similar times are usually booked quickly.
+    pub(crate) fn usually_booked_within(self, minutes: i64) -> String {
+        match self {
+            Language::En => format!("slots like this usually go within {} minutes", minutes),
+            Language::Fi => format!("tällaiset ajat varataan yleensä {} minuutissa", minutes),
+        }
+    }
+
+    /// Line of /stats about times at a weekday and hour, e.g.
+    /// "Sat 10:00 available 40 min, booked 52 h ahead (5 times)".
+    pub(crate) fn lead_time(self, lead_time: &LeadTime) -> String {
+        let weekday = self.weekday_short(lead_time.weekday);
+        let LeadTime { hour, available_minutes, booked_hours_ahead, samples, .. } = lead_time;
+        match self {
+            Language::En => format!("{} {:02}:00 available {} min, booked {} h ahead ({} times)", weekday, hour, available_minutes, booked_hours_ahead, samples),
+            Language::Fi => format!("{} {:02}:00 vapaana {} min, varattu {} h etukäteen ({} aikaa)", weekday, hour, available_minutes, booked_hours_ahead, samples),
+        }
+    }
+
+    /// Reply to /stats before enough times have been booked for medians.
+    pub(crate) fn not_enough_bookings(self) -> &'static str {
+        match self {
+            Language::En => "Not enough times booked yet to tell.",
+            Language::Fi => "Varattuja aikoja ei ole vielä tarpeeksi arvioon.",
+        }
+    }
+
+    pub(crate) fn interest_score(self, score: f64) -> String {
+        match self {
+            Language::En => format!("score {}", score),
+            Language::Fi => format!("pisteet {}", score.to_string().replace('.', ",")),
+        }
+    }
+
+    pub(crate) fn paused_until(self, until: NaiveDate) -> String {
+        let until = until.format(self.date_format());
+        match self {
+            Language::En => format!("Monitoring paused until {}.", until),
+            Language::Fi => format!("Seuranta keskeytetty {} asti.", until),
+        }
+    }
+
+    /// Reply to /pause with a duration.
+    pub(crate) fn alerts_paused(self, until: DateTime<Local>) -> String {
+        let until = self.date_time(until);
+        match self {
+            Language::En => format!("Notifications to this chat paused until {}. Send /resume to get them again.", until),
+            Language::Fi => format!("Ilmoitukset tähän keskusteluun keskeytetty {} asti. Jatka niitä komennolla /resume.", until),
+        }
+    }
+
+    /// Reply to /resume.
+    pub(crate) fn alerts_resumed(self) -> &'static str {
+        match self {
+            Language::En => "Notifications to this chat resumed.",
+            Language::Fi => "Ilmoitukset tähän keskusteluun jatkuvat.",
+        }
+    }
+
+    /// Reply to /priority with a window.
+    pub(crate) fn priority_window_added(self, window: &str) -> String {
+        match self {
+            Language::En => format!(
+                "Notifications about times within {} ring. Others in this chat arrive silently.",
+                window
+            ),
+            Language::Fi => format!(
+                "Ilmoitukset ajoista {} hälyttävät. Muut ilmoitukset tähän keskusteluun tulevat äänettä.",
+                window
+            ),
+        }
+    }
+
+    /// Heading of the reply to /priority, followed by the windows.
+    pub(crate) fn priority_windows_heading(self) -> &'static str {
+        match self {
+            Language::En => "Notifications ring for times within:",
+            Language::Fi => "Ilmoitukset hälyttävät ajoista:",
+        }
+    }
+
+    /// Reply to /priority when the chat has no priority windows.
+    pub(crate) fn no_priority_windows(self) -> &'static str {
+        match self {
+            Language::En => "All notifications ring. Make others silent with e.g. /priority sat,sun 10:00-18:00.",
+            Language::Fi => "Kaikki ilmoitukset hälyttävät. Hiljennä muut esim. komennolla /priority sat,sun 10:00-18:00.",
+        }
+    }
+
+    /// Reply to /priority clear.
+    pub(crate) fn priority_windows_cleared(self) -> &'static str {
+        match self {
+            Language::En => "All notifications ring again.",
+            Language::Fi => "Kaikki ilmoitukset hälyttävät taas.",
+        }
+    }
+
+    /// Reply to /throttle with a duration.
+    pub(crate) fn throttle_set(self, minutes: i64) -> String {
+        match self {
+            Language::En => format!(
+                "This chat gets at most one notification every {} minutes. The rest are sent together after that.",
+                minutes
+            ),
+            Language::Fi => format!(
+                "Tähän keskusteluun lähetetään enintään yksi ilmoitus {} minuutin välein. Loput lähetetään sen jälkeen yhdessä.",
+                minutes
+            ),
+        }
+    }
+
+    /// Reply to /throttle off.
+    pub(crate) fn throttle_off(self) -> &'static str {
+        match self {
+            Language::En => "Notifications to this chat are sent as soon as they're found.",
+            Language::Fi => "Ilmoitukset tähän keskusteluun lähetetään heti.",
+        }
+    }
+
+    /// Summary sent instead of individual notifications after a long downtime.
+    pub(crate) fn resynced(self, since: NaiveDate, count: usize, minutes: i64) -> String {
+        let since = since.format(self.date_format());
+        let total = self.duration(minutes);
+        match self {
+            Language::En => format!(
+                "Resynced after downtime since {}. Currently {} available times, {} in total.",
+                since, count, total
+            ),
+            Language::Fi => format!(
+                "Seuranta jatkuu katkon jälkeen ({} alkaen). Nyt {} vapaata aikaa, yhteensä {}.",
+                since, count, total
+            ),
+        }
+    }
+
+    /// Reply to /where when the location of the venue can't be found.
+    pub(crate) fn venue_unknown(self) -> &'static str {
+        match self {
+            Language::En => "The location of the venue is not available.",
+            Language::Fi => "Toimipisteen sijainti ei ole saatavilla.",
+        }
+    }
+
+    /// Sent by `laser-rust doctor --send-test`.
+    pub(crate) fn test_message(self) -> &'static str {
+        match self {
+            Language::En => "Test message from laser-rust. Notifications will arrive like this.",
+            Language::Fi => "Testiviesti laser-rustilta. Ilmoitukset tulevat näin.",
+        }
+    }
+
+    /// Sent once when the respa API has failed several times in a row.
+    pub(crate) fn api_down(self, error: &str) -> String {
+        match self {
+            Language::En => format!(
+                "The reservation system seems to be down ({}). Checking less often until it is back.",
+                error
+            ),
+            Language::Fi => format!(
+                "Varausjärjestelmä näyttää olevan poissa käytöstä ({}). Tarkistan harvemmin, kunnes se palaa.",
+                error
+            ),
+        }
+    }
+
+    /// Sent when the respa API works again after api_down.
+    pub(crate) fn api_recovered(self, since: DateTime<Local>) -> String {
+        let since = timezone::zoned(&since).format(&format!("{} %H:%M", self.date_format())).to_string();
+        match self {
+            Language::En => format!("The reservation system is back after being down since {}.", since),
+            Language::Fi => format!("Varausjärjestelmä toimii taas. Katko alkoi {}.", since),
+        }
+    }
+
+    /// Start of a message sent instead of the notifications held back from a throttled chat,
+    /// followed by their texts.
+    pub(crate) fn throttled_catch_up(self, since: DateTime<Local>, count: usize) -> String {
+        let since = timezone::zoned(&since).format(&format!("{} %H:%M", self.date_format())).to_string();
+        match self {
+            Language::En => format!("{} notifications held back since {}:", count, since),
+            Language::Fi => format!("{} ilmoitusta odotti {} alkaen:", count, since),
+        }
+    }
+
+    /// Start of a message sent instead of the messages queued while Telegram was unreachable,
+    /// followed by their texts.
+    pub(crate) fn catch_up(self, since: DateTime<Local>, missed: usize) -> String {
+        let since = timezone::zoned(&since).format(&format!("{} %H:%M", self.date_format())).to_string();
+        match self {
+            Language::En => format!(
+                "Telegram was unreachable since {}, and {} messages weren't delivered until now:",
+                since, missed
+            ),
+            Language::Fi => format!(
+                "Telegram ei ollut tavoitettavissa {} alkaen, eikä {} viestiä toimitettu tähän asti:",
+                since, missed
+            ),
+        }
+    }
+
+    /// Heading of the daily digest, followed by the resource name.
+    pub(crate) fn digest_heading(self) -> &'static str {
+        match self {
+            Language::En => "Available times",
+            Language::Fi => "Vapaat ajat",
+        }
+    }
+
+    /// Sent instead of a daily digest identical to the previous one, if the chat chose so.
+    pub(crate) fn digest_unchanged(self) -> &'static str {
+        match self {
+            Language::En => "No changes since the last digest.",
+            Language::Fi => "Ei muutoksia edellisen koosteen jälkeen.",
+        }
+    }
+
+    /// Reply to /digest.
+    pub(crate) fn unchanged_digest_set(self, setting: UnchangedDigest) -> &'static str {
+        match (self, setting) {
+            (Language::En, UnchangedDigest::Send) => "Unchanged digests will be sent in full.",
+            (Language::En, UnchangedDigest::Note) => "Unchanged digests will be replaced with a short note.",
+            (Language::En, UnchangedDigest::Skip) => "Unchanged digests will be skipped.",
+            (Language::Fi, UnchangedDigest::Send) => "Muuttumattomat koosteet lähetetään kokonaan.",
+            (Language::Fi, UnchangedDigest::Note) => "Muuttumattomien koosteiden sijaan lähetetään lyhyt ilmoitus.",
+            (Language::Fi, UnchangedDigest::Skip) => "Muuttumattomia koosteita ei lähetetä.",
+        }
+    }
+
+    pub(crate) fn digest_usage(self) -> &'static str {
+        match self {
+            Language::En => "Usage: /digest send|note|skip",
+            Language::Fi => "Käyttö: /digest send|note|skip",
+        }
+    }
+
+    /// Label of a button booking `hours` from `start`.
+    pub(crate) fn book_button(self, start: DateTime<Tz>, hours: i64) -> String {
+        let start = start.format(&format!("{} %H:%M", self.date_format())).to_string();
+        match self {
+            Language::En => format!("Book {}, {} h", start, hours),
+            Language::Fi => format!("Varaa {}, {} t", start, hours),
+        }
+    }
+
+    /// Prompt sent when a booking button is tapped.
+    pub(crate) fn confirm_booking(self, time: &str) -> String {
+        match self {
+            Language::En => format!("Book {}?", time),
+            Language::Fi => format!("Varataanko {}?", time),
+        }
+    }
+
+    pub(crate) fn confirm_booking_button(self) -> &'static str {
+        match self {
+            Language::En => "Confirm",
+            Language::Fi => "Vahvista",
+        }
+    }
+
+    pub(crate) fn cancel_booking_button(self) -> &'static str {
+        match self {
+            Language::En => "Cancel",
+            Language::Fi => "Peruuta",
+        }
+    }
+
+    /// First line of the receipt that replaces the booking prompt when the booking succeeded.
+    pub(crate) fn booked(self, time: &str) -> String {
+        match self {
+            Language::En => format!("✅ Booked {}.", time),
+            Language::Fi => format!("✅ Varattu {}.", time),
+        }
+    }
+
+    /// Line of a booking receipt with the reservation's ID and state as given by the API.
+    pub(crate) fn reservation_details(self, id: &str, state: &str) -> String {
+        match self {
+            Language::En => format!("Reservation {}, {}", id, state),
+            Language::Fi => format!("Varaus {}, {}", id, state),
+        }
+    }
+
+    pub(crate) fn access_code(self, code: &str) -> String {
+        match self {
+            Language::En => format!("Access code: {}", code),
+            Language::Fi => format!("Kulkukoodi: {}", code),
+        }
+    }
+
+    pub(crate) fn cancel_reservation_at(self, url: &str) -> String {
+        match self {
+            Language::En => format!("Cancel it at {}", url),
+            Language::Fi => format!("Peru varaus: {}", url),
+        }
+    }
+
+    /// Replaces the booking prompt when the booking failed.
+    pub(crate) fn booking_failed(self, time: &str, error: &str) -> String {
+        match self {
+            Language::En => format!("Couldn't book {}: {}", time, error),
+            Language::Fi => format!("Ajan {} varaus epäonnistui: {}", time, error),
+        }
+    }
+
+    /// Replaces the booking prompt when someone else reserved the time first.
+    pub(crate) fn booking_missed(self, time: &str) -> String {
+        match self {
+            Language::En => format!("😩 Missed it by seconds: someone else booked {} just now.", time),
+            Language::Fi => format!("😩 Hups, joku ehti ensin: {} varattiin juuri äsken.", time),
+        }
+    }
+
+    /// Replaces the booking prompt when "Confirm" was tapped after the timeout.
+    pub(crate) fn confirmation_expired(self) -> &'static str {
+        match self {
+            Language::En => "The booking prompt expired. Tap a booking button again to book.",
+            Language::Fi => "Varausvahvistus vanheni. Varaa uudelleen varauspainikkeesta.",
+        }
+    }
+
+    /// Replaces the booking prompt when it was cancelled.
+    pub(crate) fn booking_cancelled(self) -> &'static str {
+        match self {
+            Language::En => "Booking cancelled.",
+            Language::Fi => "Varaus peruttu.",
+        }
+    }
+
+    /// Reminder of an own reservation starting in `minutes`.
+    pub(crate) fn reservation_starts_in(self, time: &str, minutes: i64) -> String {
+        let left = if minutes >= 60 {
+            format!("{} h {} min", minutes / 60, minutes % 60)
+        } else {
+            format!("{} min", minutes)
+        };
+        match self {
+            Language::En => format!("⏰ Your reservation {} starts in {}.", time, left),
+            Language::Fi => format!("⏰ Varauksesi {} alkaa {} kuluttua.", time, left),
+        }
+    }
+
+    /// Alert about an own reservation that is no longer found or was cancelled.
+    pub(crate) fn reservation_cancelled(self, time: &str) -> String {
+        match self {
+            Language::En => format!("⚠️ Your reservation {} was cancelled.", time),
+            Language::Fi => format!("⚠️ Varauksesi {} on peruttu.", time),
+        }
+    }
+
+    /// Alert about an own reservation whose time was changed.
+    pub(crate) fn reservation_modified(self, from: &str, to: &str) -> String {
+        match self {
+            Language::En => format!("⚠️ Your reservation {} was moved to {}.", from, to),
+            Language::Fi => format!("⚠️ Varauksesi {} siirrettiin aikaan {}.", from, to),
+        }
+    }
+
+    /// Heading of the reply to /myreservations, followed by the reservations.
+    pub(crate) fn own_reservations_heading(self) -> &'static str {
+        match self {
+            Language::En => "Your upcoming reservations:",
+            Language::Fi => "Tulevat varauksesi:",
+        }
+    }
+
+    /// Reply to /myreservations when there are none.
+    pub(crate) fn no_own_reservations(self) -> &'static str {
+        match self {
+            Language::En => "You have no upcoming reservations.",
+            Language::Fi => "Sinulla ei ole tulevia varauksia.",
+        }
+    }
+
+    /// Reply to /myreservations when they can't be fetched.
+    pub(crate) fn own_reservations_unavailable(self, error: &str) -> String {
+        match self {
+            Language::En => format!("Couldn't fetch your reservations: {}", error),
+            Language::Fi => format!("Varauksia ei saatu haettua: {}", error),
+        }
+    }
+
+    /// Heading of an alert about times matching a wishlist entry, followed by the times.
+    pub(crate) fn wishlist_matched(self, entry: &str, resource: &str) -> String {
+        match self {
+            Language::En => format!("⭐ Your wishlist matched: {}, {}", entry, resource),
+            Language::Fi => format!("⭐ Toivelistasi osui: {}, {}", entry, resource),
+        }
+    }
+
+    /// Heading of a message about new times within a watched window, followed by the times.
+    pub(crate) fn watched_time_free(self, window: &str, resource: &str) -> String {
+        match self {
+            Language::En => format!("Free within your watched time {}, {}:", window, resource),
+            Language::Fi => format!("Vapaata seuraamallasi ajalla {}, {}:", window, resource),
+        }
+    }
+
+    /// Reply to /watch.
+    pub(crate) fn watching(self, window: &str) -> String {
+        match self {
+            Language::En => format!("Watching {}. You'll be notified when any part of it becomes free.", window),
+            Language::Fi => format!("Seurataan aikaa {}. Saat ilmoituksen, kun siitä vapautuu jotain.", window),
+        }
+    }
+
+    /// Heading of the reply to /watches, followed by the numbered windows.
+    pub(crate) fn watches_heading(self) -> &'static str {
+        match self {
+            Language::En => "Watched times:",
+            Language::Fi => "Seuratut ajat:",
+        }
+    }
+
+    /// Heading of the reply to /status, followed by the status lines.
+    pub(crate) fn status_heading(self) -> &'static str {
+        match self {
+            Language::En => "Bot status",
+            Language::Fi => "Botin tila",
+        }
+    }
+
+    /// Line of /status telling how long the bot has been running.
+    pub(crate) fn status_running(self, since: DateTime<Local>, minutes: i64) -> String {
+        let (since, running) = (self.date_time(since), self.duration(minutes));
+        match self {
+            Language::En => format!("Running since {} ({})", since, running),
+            Language::Fi => format!("Käynnissä {} alkaen ({})", since, running),
+        }
+    }
+
+    /// Line of /status telling how often availability is checked.
+    pub(crate) fn status_poll_interval(self, minutes: i64) -> String {
+        let interval = self.duration(minutes);
+        match self {
+            Language::En => format!("Checking every {}", interval),
+            Language::Fi => format!("Tarkistus {} välein", interval),
+        }
+    }
+
+    /// Line of /status with the cron expression availability is checked on.
+    pub(crate) fn status_schedule(self, schedule: &str) -> String {
+        match self {
+            Language::En => format!("Checking on schedule {}", schedule),
+            Language::Fi => format!("Tarkistus aikataululla {}", schedule),
+        }
+    }
+
+    /// Line of /status with the time of the latest notification about new times.
+    pub(crate) fn status_last_notified(self, time: Option<DateTime<Local>>) -> String {
+        match (self, time) {
+            (Language::En, Some(time)) => format!("Last notification {}", self.date_time(time)),
+            (Language::En, None) => "No notifications yet".to_owned(),
+            (Language::Fi, Some(time)) => format!("Edellinen ilmoitus {}", self.date_time(time)),
+            (Language::Fi, None) => "Ei vielä ilmoituksia".to_owned(),
+        }
+    }
+
+    /// Line of /status for a monitored resource.
+    pub(crate) fn status_resource(self, resource_id: &str, fetched_at: Option<DateTime<Local>>, count: usize) -> String {
+        match (self, fetched_at) {
+            (Language::En, Some(time)) => {
+                format!("{}: fetched {}, {} available times", resource_id, self.date_time(time), count)
+            }
+            (Language::En, None) => format!("{}: not fetched yet", resource_id),
+            (Language::Fi, Some(time)) => format!("{}: haettu {}, {} vapaata aikaa", resource_id, self.date_time(time), count),
+            (Language::Fi, None) => format!("{}: ei vielä haettu", resource_id),
+        }
+    }
+
+    /// Reply to /watches when the chat watches nothing.
+    pub(crate) fn no_watches(self) -> &'static str {
+        match self {
+            Language::En => "No watched times. Add one with /watch YYYY-MM-DD HH:MM-HH:MM.",
+            Language::Fi => "Ei seurattuja aikoja. Lisää seuranta komennolla /watch VVVV-KK-PP HH:MM-HH:MM.",
+        }
+    }
+
+    /// Reply to /blackout with dates.
+    pub(crate) fn blackout_added(self, dates: &str) -> String {
+        match self {
+            Language::En => format!("No notifications about times on {}.", dates),
+            Language::Fi => format!("Ei ilmoituksia ajoista {}.", dates),
+        }
+    }
+
+    /// Heading of the reply to /blackout, followed by the blacked out dates.
+    pub(crate) fn blackouts_heading(self) -> &'static str {
+        match self {
+            Language::En => "No notifications about times on:",
+            Language::Fi => "Ei ilmoituksia ajoista:",
+        }
+    }
+
+    /// Reply to /blackout when the chat has no blacked out dates.
+    pub(crate) fn no_blackouts(self) -> &'static str {
+        match self {
+            Language::En => "No blackout dates. Add some with /blackout YYYY-MM-DD YYYY-MM-DD.",
+            Language::Fi => "Ei estettyjä päiviä. Lisää niitä komennolla /blackout VVVV-KK-PP VVVV-KK-PP.",
+        }
+    }
+
+    /// Reply to /blackout clear.
+    pub(crate) fn blackouts_cleared(self) -> &'static str {
+        match self {
+            Language::En => "Blackout dates removed.",
+            Language::Fi => "Estetyt päivät poistettu.",
+        }
+    }
+
+    /// Reply to /unwatch.
+    pub(crate) fn unwatched(self, window: &str) -> String {
+        match self {
+            Language::En => format!("No longer watching {}.", window),
+            Language::Fi => format!("Aikaa {} ei enää seurata.", window),
+        }
+    }
+
+    /// Reply to a command that only admins may use.
+    pub(crate) fn admin_only(self) -> &'static str {
+        match self {
+            Language::En => "Only admins can use this command.",
+            Language::Fi => "Vain ylläpitäjät voivat käyttää tätä komentoa.",
+        }
+    }
+
+    /// Reply to /broadcast.
+    pub(crate) fn broadcast_sent(self, chats: usize) -> String {
+        match (self, chats) {
+            (Language::En, 1) => "Announcement sent to 1 chat.".to_owned(),
+            (Language::En, _) => format!("Announcement sent to {} chats.", chats),
+            (Language::Fi, 1) => "Tiedote lähetetty 1 keskusteluun.".to_owned(),
+            (Language::Fi, _) => format!("Tiedote lähetetty {} keskusteluun.", chats),
+        }
+    }
+
+    pub(crate) fn broadcast_usage(self) -> &'static str {
+        match self {
+            Language::En => "Usage: /broadcast <text>",
+            Language::Fi => "Käyttö: /broadcast <teksti>",
+        }
+    }
+
+    /// Reply to /set.
+    pub(crate) fn setting_changed(self, name: &str, value: &str) -> String {
+        match (self, value) {
+            (Language::En, "default") => format!("{} is back to the value in the config file.", name),
+            (Language::En, _) => format!("{} set to {}.", name, value),
+            (Language::Fi, "default") => format!("{} palautettu asetustiedoston arvoon.", name),
+            (Language::Fi, _) => format!("{} asetettu arvoon {}.", name, value),
+        }
+    }
+
+    pub(crate) fn set_usage(self) -> &'static str {
+        match self {
+            Language::En => "Usage: /set interval <seconds>, /set lookahead <days>, /set min_duration <e.g. 2h>, or default as the value",
+            Language::Fi => "Käyttö: /set interval <sekunnit>, /set lookahead <päivät>, /set min_duration <esim. 2h>, tai arvona default",
+        }
+    }
+
+    pub(crate) fn search_usage(self) -> &'static str {
+        match self {
+            Language::En => "Usage: /search <words>",
+            Language::Fi => "Käyttö: /search <hakusanat>",
+        }
+    }
+
+    pub(crate) fn watch_usage(self) -> &'static str {
+        match self {
+            Language::En => "Usage: /watch YYYY-MM-DD HH:MM-HH:MM",
+            Language::Fi => "Käyttö: /watch VVVV-KK-PP HH:MM-HH:MM",
+        }
+    }
+
+    pub(crate) fn priority_usage(self) -> &'static str {
+        match self {
+            Language::En => "Usage: /priority [mon,tue,...] HH:MM-HH:MM, or /priority clear",
+            Language::Fi => "Käyttö: /priority [mon,tue,...] HH:MM-HH:MM, tai /priority clear",
+        }
+    }
+
+    pub(crate) fn throttle_usage(self) -> &'static str {
+        match self {
+            Language::En => "Usage: /throttle <duration up to 1w, e.g. 1h>, or /throttle off",
+            Language::Fi => "Käyttö: /throttle <aika enintään 1w, esim. 1h>, tai /throttle off",
+        }
+    }
+
+    pub(crate) fn blackout_usage(self) -> &'static str {
+        match self {
+            Language::En => "Usage: /blackout YYYY-MM-DD [YYYY-MM-DD], or /blackout clear",
+            Language::Fi => "Käyttö: /blackout VVVV-KK-PP [VVVV-KK-PP], tai /blackout clear",
+        }
+    }
+
+    pub(crate) fn unwatch_usage(self) -> &'static str {
+        match self {
+            Language::En => "Usage: /unwatch <number from /watches>",
+            Language::Fi => "Käyttö: /unwatch <numero /watches-listalta>",
+        }
+    }
+
+    pub(crate) fn where_usage(self) -> &'static str {
+        match self {
+            Language::En => "Usage: /where, or /where <resource ID> for another resource",
+            Language::Fi => "Käyttö: /where, tai /where <resurssin tunnus> muulle resurssille",
+        }
+    }
+
+    pub(crate) fn pause_usage(self) -> &'static str {
+        match self {
+            Language::En => "Usage: /pause 3d (also 30m, 12h or 2w, up to a year) or /pause until YYYY-MM-DD",
+            Language::Fi => "Käyttö: /pause 3d (myös 30m, 12h tai 2w, enintään vuosi) tai /pause until VVVV-KK-PP",
+        }
+    }
+}
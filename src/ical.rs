@@ -0,0 +1,107 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::message::Resource;
+use crate::timeslot::Timeslot;
+use crate::timezone;
+
+/// Parse the events of an iCalendar document into timeslots.
+///
+/// Only DTSTART and DTEND are read. Times in UTC ("20240801T100000Z") are converted to the
+/// configured time zone, other times are taken as times in it, and all-day events cover whole days.
+pub(crate) fn parse_events(calendar: &str) -> Result<Vec<Timeslot>, String> {
+    // Long lines are folded by starting the continuation lines with a space or a tab.
+    let unfolded = calendar.replace("\r\n", "\n").replace("\n ", "").replace("\n\t", "");
+
+    let mut events: Vec<Timeslot> = Vec::new();
+    let (mut start, mut end) = (None, None);
+    for line in unfolded.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Properties may have parameters, e.g. "DTSTART;TZID=Europe/Helsinki:20240801T100000"
+        match name.split(';').next().unwrap_or("") {
+            "BEGIN" if value == "VEVENT" => (start, end) = (None, None),
+            "DTSTART" => start = Some(parse_time(value)?),
+            "DTEND" => end = Some(parse_time(value)?),
+            "END" if value == "VEVENT" => {
+                let (Some(start), Some(end)) = (start, end) else {
+                    return Err("Event without DTSTART or DTEND".to_owned());
+                };
+                events.push(Timeslot {
+                    start: start.to_rfc3339(),
+                    end: end.to_rfc3339(),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(events)
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Tz>, String> {
+    let invalid = || format!("Invalid time: {}", value);
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+        return Ok(timezone::zoned(&Utc.from_utc_datetime(&time)));
+    }
+
+    let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y%m%d").map(|date| date.and_time(chrono::NaiveTime::MIN)))
+        .map_err(|_| invalid())?;
+    Ok(timezone::at(time.date(), time.time()))
+}
+
+/// Write an iCalendar document with an event for each available time of the resource.
+/// The events are named after the resource and link to its booking page.
+pub(crate) fn write_calendar(resource: &Resource, available_times: &[Timeslot]) -> String {
+    let utc = |time: DateTime<Tz>| time.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string();
+    let now = utc(timezone::now());
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//laser-rust//EN".to_owned(),
+        format!("X-WR-CALNAME:{}", escape(&resource.name)),
+    ];
+    for time in available_times {
+        lines.extend([
+            "BEGIN:VEVENT".to_owned(),
+            format!("UID:{}-{}@laser-rust", utc(time.start_time()), resource.id),
+            format!("DTSTAMP:{}", now),
+            format!("DTSTART:{}", utc(time.start_time())),
+            format!("DTEND:{}", utc(time.end_time())),
+            format!("SUMMARY:{}", escape(&resource.name)),
+            format!("DESCRIPTION:{}", escape(&resource.booking_url())),
+            format!("URL:{}", resource.booking_url()),
+            "TRANSP:TRANSPARENT".to_owned(),
+            "END:VEVENT".to_owned(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_owned());
+
+    lines.iter().map(|line| fold(line) + "\r\n").collect()
+}
+
+/// Escape the characters that have a special meaning in iCalendar text values.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Split a line into lines of at most 75 bytes, continuing each with a space.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for character in line.chars() {
+        if length + character.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(character);
+        length += character.len_utf8();
+    }
+    folded
+}
@@ -0,0 +1,118 @@
+use chrono::{NaiveTime, Weekday};
+use serde::Deserialize;
+
+use crate::holidays;
+use crate::scheduler::deserialize_optional_time;
+use crate::timeslot::Timeslot;
+use crate::timezone;
+
+/// How interesting times are, e.g. evenings ×3 and weekends ×5, so a Saturday evening scores 15
+/// and a Tuesday morning 1:
+///
+/// ```toml
+/// [interest]
+/// min_score = 3
+///
+/// [[interest.weights]]
+/// from = "17:00"
+/// to = "22:00"
+/// weight = 3
+///
+/// [[interest.weights]]
+/// weekdays = ["sat", "sun"]
+/// weight = 5
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct InterestConfig {
+    pub(crate) weights: Vec<InterestWeight>,
+    /// Only notify about times scoring at least this much. 0 notifies about all times.
+    pub(crate) min_score: f64,
+}
+
+/// A weight the score of times overlapping its window is multiplied by.
+/// Without `weekdays` every day matches, and without `from` and `to` the whole day.
+#[derive(Deserialize)]
+pub(crate) struct InterestWeight {
+    #[serde(default)]
+    pub(crate) weekdays: Vec<Weekday>,
+    #[serde(default, deserialize_with = "deserialize_optional_time")]
+    pub(crate) from: Option<NaiveTime>,
+    #[serde(default, deserialize_with = "deserialize_optional_time")]
+    pub(crate) to: Option<NaiveTime>,
+    pub(crate) weight: f64,
+}
+
+impl InterestWeight {
+    /// Whether the time is at least partly within the window.
+    fn applies_to(&self, time: &Timeslot) -> bool {
+        let date = time.start_time().date_naive();
+        if !holidays::matches(&self.weekdays, date) {
+            return false;
+        }
+        let window_start = self.from.map_or(time.start_time(), |from| timezone::at(date, from));
+        let window_end = self.to.map_or(time.end_time(), |to| timezone::at(date, to));
+        time.start_time() < window_end && window_start < time.end_time()
+    }
+}
+
+impl InterestConfig {
+    /// Product of the weights that apply to the time, 1 if none do.
+    pub(crate) fn score(&self, time: &Timeslot) -> f64 {
+        self.weights.iter().filter(|weight| weight.applies_to(time)).map(|weight| weight.weight).product()
+    }
+
+    /// Whether the time scores high enough to be notified about.
+    pub(crate) fn is_interesting(&self, time: &Timeslot) -> bool {
+        self.score(time) >= self.min_score
+    }
+
+    /// Whether times are scored at all. Without weights, notifications aren't sorted or annotated.
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.weights.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(start: &str, end: &str) -> Timeslot {
+        Timeslot {
+            start: start.to_owned(),
+            end: end.to_owned(),
+        }
+    }
+
+    fn config() -> InterestConfig {
+        toml::from_str(
+            r#"
+            min_score = 3
+
+            [[weights]]
+            from = "17:00"
+            to = "22:00"
+            weight = 3
+
+            [[weights]]
+            weekdays = ["sat", "sun"]
+            weight = 5
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn multiplies_weights_of_overlapping_windows() {
+        let config = config();
+        // 2024-05-04 is a Saturday and 2024-05-07 a Tuesday.
+        let saturday_evening = time("2024-05-04T16:00:00+03:00", "2024-05-04T18:00:00+03:00");
+        let tuesday_morning = time("2024-05-07T09:00:00+03:00", "2024-05-07T10:00:00+03:00");
+        let tuesday_evening = time("2024-05-07T21:00:00+03:00", "2024-05-07T23:00:00+03:00");
+        assert_eq!(config.score(&saturday_evening), 15.0);
+        assert_eq!(config.score(&tuesday_morning), 1.0);
+        assert_eq!(config.score(&tuesday_evening), 3.0);
+        assert!(!config.is_interesting(&tuesday_morning));
+        assert!(config.is_interesting(&tuesday_evening));
+    }
+}
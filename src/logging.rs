@@ -0,0 +1,106 @@
+use serde::Deserialize;
+use std::io::IsTerminal;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Format of log output.
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for systemd/container log collectors.
+    Json,
+}
+
+/// Export of traces to an OpenTelemetry collector, e.g. Jaeger or Tempo, over OTLP/HTTP.
+/// Requires the "otlp" feature.
+///
+/// ```toml
+/// [otlp]
+/// endpoint = "http://localhost:4318/v1/traces"
+/// ```
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct OtlpConfig {
+    /// URL traces are posted to.
+    pub(crate) endpoint: String,
+    /// Name of the service the traces are reported under.
+    pub(crate) service_name: String,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        OtlpConfig {
+            endpoint: "http://localhost:4318/v1/traces".to_owned(),
+            service_name: "laser-rust".to_owned(),
+        }
+    }
+}
+
+#[cfg(feature = "otlp")]
+static TRACER_PROVIDER: once_cell::sync::OnceCell<opentelemetry_sdk::trace::SdkTracerProvider> =
+    once_cell::sync::OnceCell::new();
+
+/// Set up logging to stderr, with colors only if it's a terminal. The level is taken from RUST_LOG (e.g. "debug" or
+/// "laser_rust=debug,reqwest=info") and defaults to "info".
+///
+/// With `otlp` configured, spans at the same levels are also exported as traces.
+pub(crate) fn init(format: LogFormat, otlp: Option<&OtlpConfig>) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    let fmt = match format {
+        LogFormat::Text => fmt.boxed(),
+        LogFormat::Json => fmt.json().boxed(),
+    };
+    let registry = tracing_subscriber::registry().with(filter).with(fmt);
+
+    #[cfg(feature = "otlp")]
+    registry.with(otlp.and_then(otlp_layer)).init();
+    #[cfg(not(feature = "otlp"))]
+    {
+        registry.init();
+        if otlp.is_some() {
+            tracing::warn!("Traces are not exported: built without the \"otlp\" feature");
+        }
+    }
+}
+
+/// Create the layer exporting spans to the collector, in batches from a background thread.
+#[cfg(feature = "otlp")]
+fn otlp_layer<S>(config: &OtlpConfig) -> Option<impl Layer<S>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .build()
+        .inspect_err(|error| eprintln!("Failed to set up trace export to {}: {}", config.endpoint, error))
+        .ok()?;
+    let resource = opentelemetry_sdk::Resource::builder().with_service_name(config.service_name.clone()).build();
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build();
+    let tracer = provider.tracer("laser-rust");
+    TRACER_PROVIDER.set(provider).ok();
+    Some(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Export the traces still waiting to be sent. Call before exiting.
+pub(crate) fn shutdown() {
+    #[cfg(feature = "otlp")]
+    if let Some(provider) = TRACER_PROVIDER.get() {
+        if let Err(error) = provider.shutdown() {
+            eprintln!("Failed to export remaining traces: {}", error);
+        }
+    }
+}
@@ -1,190 +1,1051 @@
-use std::fs::OpenOptions;
-use chrono::{Local, Days};
-use std::io::{prelude::*, SeekFrom};
+use chrono::Local;
+use clap::{Parser, Subcommand};
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc, RwLock};
 
+mod availability;
+mod blackout;
+mod booking;
+mod breaker;
+mod changes;
+mod chat_id;
+mod client;
+mod commands;
+mod config;
+mod dashboard;
+mod desktop;
+mod diff;
+mod digest;
+mod doctor;
+#[cfg(any(test, feature = "fault-injection"))]
+mod fault;
+mod feed;
+mod heatmap;
+mod history;
+mod holidays;
+mod i18n;
+mod ical;
+mod interest;
+mod logging;
+mod maintenance;
+mod message;
+mod metrics;
+mod mqtt;
+mod output;
+mod overrides;
+mod own_reservations;
+mod paths;
+mod priority;
+mod respa;
+mod scheduler;
+mod selftest;
+mod server;
+#[cfg(windows)]
+mod service;
+mod source;
+mod state;
+mod stats;
+mod telegram;
 mod timeslot;
+mod timezone;
+mod unit;
+mod watch;
+mod wishlist;
+use availability::IntervalSet;
+use config::{Current, Notifier};
+use diff::Diff;
+use history::Observation;
+use message::Resource;
+use metrics::Metrics;
+use output::{ExportFormat, OutputFormat};
+use scheduler::Scheduler;
+use server::{Snapshot, Status};
+use state::State;
+use telegram::{OutgoingMessage, Subscriber};
 use timeslot::Timeslot;
 
 use once_cell::sync::Lazy;
-struct Config {
-    telegram_bot_token: String,
-    telegram_chat_id: String,
-    days: u64
+static CONFIG: Lazy<Current> = Lazy::new(|| Current::new(config::load()));
+
+/// Log file of --daemon in the state directory, unless `log_file` is configured.
+#[cfg(any(unix, windows))]
+const LOG_FILE: &str = "laser.log";
+
+/// Exit code of `--once` when new available times were found.
+const EXIT_NEW_TIMES: i32 = 2;
+
+/// Notifies about new available times of Oodi's laser cutter.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Check once and exit, even if the HTTP server is configured. The exit code is 0 if no new
+    /// times were found, 2 if new times were found and 1 if the check failed.
+    #[arg(long)]
+    once: bool,
+
+    /// Keep checking in the background, detached from the terminal, with the output appended to
+    /// `log_file`. Checks continue even without `[http]`. On Windows, runs as a service instead.
+    #[arg(long)]
+    daemon: bool,
+
+    /// Check once and print what would be notified, without sending anything or saving the state.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Stop sending notifications to all chats for this long, e.g. 3d, while checks go on. At most a year.
+    /// 0m resumes them. Chats can do the same with /pause and /resume.
+    #[arg(long, value_name = "DURATION", value_parser = parse_pause)]
+    pause_alerts: Option<chrono::Duration>,
+
+    /// Directory to keep the state in, instead of the platform's default, e.g. ~/.local/state/laser.
+    #[arg(long, global = true, value_name = "DIR")]
+    state_dir: Option<std::path::PathBuf>,
+
+    /// Format of the results printed by `list` and `--once`.
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List chats that have recently messaged the bot, to find the chat ID to configure.
+    ChatId {
+        /// Save the chat of the latest message as telegram_chat_id in the config file.
+        #[arg(long)]
+        write: bool,
+    },
+    /// Fetch and print the available times, without notifying or saving anything.
+    List,
+    /// Fetch the available times and print them in a file format, e.g. for importing into a calendar.
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Ics)]
+        format: ExportFormat,
+    },
+    /// Print statistics from the saved availability history, e.g. the busiest weekdays.
+    Stats {
+        #[command(subcommand)]
+        report: Option<StatsReport>,
+    },
+    /// Find resources by name, description or venue, to look up the resource ID to configure.
+    Search {
+        query: String,
+        /// Most resources to list.
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Reserve a time of a resource through the respa API, e.g.
+    /// `book axwzr3i57yba 2024-03-15T17:00 2024-03-15T19:00 --confirm`.
+    /// The time is checked to still be free right before booking. Exits with 1 if it can't be booked.
+    Book {
+        /// Respa ID of the resource.
+        resource: String,
+        /// Start of the time, as YYYY-MM-DDTHH:MM in local time or in RFC 3339 format.
+        start: String,
+        /// End of the time, in the same format.
+        end: String,
+        /// Make the reservation. Without this, only checks that the time is free.
+        #[arg(long)]
+        confirm: bool,
+    },
+    /// Save respa API responses for each monitored resource, to replay them later with
+    /// `replay_dir` in the config, e.g. for developing offline.
+    Record {
+        /// Directory to save the responses in.
+        #[arg(long, default_value = "recordings")]
+        dir: std::path::PathBuf,
+        /// Number of times to record each resource.
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+        /// Seconds between recordings.
+        #[arg(long, default_value_t = 300)]
+        interval_secs: u64,
+    },
+    /// Check the configuration, the respa API for each resource, the state file and the notifier
+    /// credentials, and print a report. Exits with 1 if any check fails.
+    Doctor {
+        /// Also send a test message through each notifier.
+        #[arg(long)]
+        send_test: bool,
+    },
+    /// Run the pipeline on bundled fixture data, without network access or a state file, e.g. to
+    /// check a cross-compiled build on the target machine. Exits with 1 if anything fails.
+    Selftest,
+}
+
+#[derive(Subcommand)]
+enum StatsReport {
+    /// How long new times stay available and how far ahead they get booked, by weekday and hour.
+    LeadTime,
+}
+
+fn parse_pause(value: &str) -> Result<chrono::Duration, String> {
+    commands::parse_duration(value).ok_or_else(|| "expected a duration like 30m, 12h, 3d or 2w, up to a year".to_owned())
 }
-static CONFIG: Lazy<Config> = Lazy::new(|| Config {
-    telegram_bot_token: env!("TELEGRAM_BOT_TOKEN").to_string(),
-    telegram_chat_id: env!("TELEGRAM_CHAT_ID").to_string(),
-    days: 14
-});
 
 fn main() {
-    println!("Fetching data...");
-    let api_data = fetch_api_data();
-
-    let opening_times = parse_opening_times(&api_data);
-    let reservations = parse_reservations(&api_data);
-    let available_times = timeslot::get_available_times(&opening_times, &reservations);
-
-    // Read existing data from a txt file called available_times. If the file does not exist, create a new empty file.
-    let mut file = match OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open("available_times") {
-        Ok(file) => file,
-        Err(_) => panic!("Failed to open file")
+    let cli = Cli::parse();
+    // The service control manager calls run once the service has started.
+    #[cfg(windows)]
+    if cli.daemon {
+        service::start();
+        return;
+    }
+    run(cli);
+}
+
+fn run(cli: Cli) {
+    if let Some(dir) = &cli.state_dir {
+        paths::set_state_dir(dir.clone());
+    }
+    // Variables already set in the environment take precedence over the .env file.
+    dotenvy::dotenv().ok();
+    if cli.daemon {
+        daemonize();
+    }
+    // The self test sends nothing, so it doesn't need a bot token.
+    let has_token = std::env::var_os("TELEGRAM_BOT_TOKEN").is_some() || std::env::var_os("TELEGRAM_BOT_TOKEN_FILE").is_some();
+    if matches!(cli.command, Some(Command::Selftest)) && !has_token {
+        std::env::set_var("TELEGRAM_BOT_TOKEN", "selftest");
+    }
+    // The doctor reports an invalid configuration instead of panicking on it.
+    if let Some(Command::Doctor { send_test }) = cli.command {
+        std::process::exit(if doctor::run(send_test) { 0 } else { 1 });
+    }
+    logging::init(CONFIG.log_format, CONFIG.otlp.as_ref());
+
+    match cli.command {
+        Some(Command::ChatId { write }) => {
+            chat_id::run(write);
+            return;
+        }
+        Some(Command::List) => {
+            output::print(&[fetch_or_exit()], cli.output);
+            return;
+        }
+        Some(Command::Search { query, limit }) => match respa::search(&query, limit) {
+            Ok(resources) => {
+                output::print_search(&resources, cli.output);
+                return;
+            }
+            Err(error) => {
+                eprintln!("Search failed: {}", error);
+                std::process::exit(1);
+            }
+        },
+        Some(Command::Book {
+            resource,
+            start,
+            end,
+            confirm,
+        }) => {
+            std::process::exit(if booking::run(&resource, &start, &end, confirm) { 0 } else { 1 });
+        }
+        Some(Command::Selftest) => {
+            std::process::exit(if selftest::run() { 0 } else { 1 });
+        }
+        Some(Command::Record {
+            dir,
+            count,
+            interval_secs,
+        }) => {
+            let resource_ids = unit::monitored_resources(&mut State::default());
+            let interval = std::time::Duration::from_secs(interval_secs);
+            std::process::exit(if source::record(&dir, &resource_ids, count, interval) { 0 } else { 1 });
+        }
+        Some(Command::Doctor { .. }) => unreachable!("handled before logging is set up"),
+        Some(Command::Stats { report }) => {
+            let state = State::load();
+            match report {
+                None => output::print_stats(&stats::report(&state.history, &state.changes), cli.output),
+                Some(StatsReport::LeadTime) => output::print_lead_times(&stats::lead_times(&state.changes), cli.output),
+            }
+            return;
+        }
+        Some(Command::Export { format }) => {
+            let snapshot = fetch_or_exit();
+            match format {
+                ExportFormat::Ics => print!("{}", ical::write_calendar(&snapshot.resource, &snapshot.available_times)),
+            }
+            return;
+        }
+        None => {}
+    }
+
+    if CONFIG.telegram_chat_id.is_empty() {
+        tracing::error!("No chat configured. Set TELEGRAM_CHAT_ID or run the chat-id command to find one.");
+        std::process::exit(1);
+    }
+
+    // A dry run neither sends nor saves anything, so it can run next to the bot.
+    let _lock = if cli.dry_run {
+        None
+    } else {
+        Some(state::lock().unwrap_or_else(|error| {
+            tracing::error!("{}", error);
+            std::process::exit(1);
+        }))
     };
+    let signals = handle_signals();
+    let mut state = State::load();
+    overrides::apply(state.overrides);
+    add_subscribers(&mut state);
+    if let Some(duration) = cli.pause_alerts {
+        let until = (duration > chrono::Duration::zero()).then(|| Local::now() + duration);
+        for subscriber in &mut state.subscribers {
+            subscriber.alerts_paused_until = until;
+        }
+    }
+    state.detect_stale(chrono::Duration::hours(CONFIG.stale_state_hours));
+
+    let jitter = std::time::Duration::from_secs(CONFIG.poll_jitter_secs);
 
-    let existing_available_times = read_existing_available_times(&mut file);
-    let new_times = &available_times
-        .iter()
-        .filter(|time| !existing_available_times.contains(time))
-        .collect::<Vec<&Timeslot>>();
+    if cli.dry_run {
+        let code = match run_cycle(&mut state, true) {
+            Ok(snapshots) if snapshots.iter().any(|snapshot| !snapshot.new_times.is_empty()) => EXIT_NEW_TIMES,
+            Ok(_) => 0,
+            Err(_) => 1,
+        };
+        logging::shutdown();
+        std::process::exit(code);
+    }
 
-    // Write available times to file, replacing the existing file contents.
-    update_file(&mut file, &available_times);
+    if cli.once {
+        std::thread::sleep(scheduler::random_delay(jitter));
+        let code = match run_cycle(&mut state, false) {
+            Ok(snapshots) => {
+                if cli.output == OutputFormat::Json && !snapshots.is_empty() {
+                    output::print(&snapshots, cli.output);
+                }
+                if snapshots.iter().all(|snapshot| snapshot.new_times.is_empty()) { 0 } else { EXIT_NEW_TIMES }
+            }
+            Err(_) => 1,
+        };
+        logging::shutdown();
+        std::process::exit(code);
+    }
 
-    // Send telegram message with new times.
-    send_telegram_message(new_times);
+    // Without the HTTP server or --daemon, check once and exit.
+    if CONFIG.http.is_none() && !cli.daemon {
+        std::thread::sleep(scheduler::random_delay(jitter));
+        run_cycle(&mut state, false).ok();
+        logging::shutdown();
+        return;
+    }
+
+    // Otherwise keep polling, and share the latest results with the HTTP server if there is one.
+    // Only these runs count as starts, so checking once from cron doesn't inflate them.
+    state.counters.start(Local::now());
+    let status = Arc::new(RwLock::new(Status {
+        resource_ids: unit::resource_ids(&state),
+        external_reservations: state.external_reservations.clone(),
+        changes: state.changes.clone(),
+        counters: state.counters.clone(),
+        ..Status::default()
+    }));
+    if let Some(http_config) = &CONFIG.http {
+        server::start(http_config, status.clone());
+    }
+    std::thread::sleep(scheduler::random_delay(jitter));
+    let mut scheduler = Scheduler::new(CONFIG.poll_interval(), CONFIG.schedule.as_ref(), jitter);
+    let mut config_modified = config::modified();
+    'cycles: loop {
+        if config::modified() != config_modified {
+            config_modified = config::modified();
+            reload_config(&mut state, &mut scheduler);
+        }
+        // The server keeps the reservations pushed to the webhook, and the state gets a copy to save.
+        {
+            let mut status = status.write().unwrap();
+            state::drop_past_reservations(&mut status.external_reservations, Local::now());
+            state.external_reservations = status.external_reservations.clone();
+        }
+        let snapshots = run_cycle(&mut state, false);
+
+        let mut status = status.write().unwrap();
+        status.resource_ids = unit::resource_ids(&state);
+        status.paused = state.paused;
+        status.set_changes(&state.changes);
+        status.counters = state.counters.clone();
+        if let Ok(snapshots) = snapshots {
+            status.set_snapshots(snapshots);
+        }
+        drop(status);
+
+        // Sleep until the next cycle, or stop if a termination signal has been received.
+        // SIGHUP reloads the config, and the next cycle is then one polling interval away.
+        scheduler.set_interval(CONFIG.poll_interval_at(Local::now()));
+        #[cfg(unix)]
+        {
+            let mut next_cycle = std::time::Instant::now() + scheduler.next_wait();
+            loop {
+                match signals.recv_timeout(next_cycle.saturating_duration_since(std::time::Instant::now())) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => break,
+                    Ok(Signal::Reload) => {
+                        config_modified = config::modified();
+                        reload_config(&mut state, &mut scheduler);
+                        next_cycle = std::time::Instant::now() + scheduler.next_wait();
+                    }
+                    Ok(Signal::Terminate) | Err(mpsc::RecvTimeoutError::Disconnected) => break 'cycles,
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        match signals.recv_timeout(scheduler.next_wait()) {
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Ok(Signal::Terminate) | Err(mpsc::RecvTimeoutError::Disconnected) => break 'cycles,
+        }
+    }
+
+    tracing::info!("Shutting down");
+    state.counters.seen(Local::now());
+    state.external_reservations = status.read().unwrap().external_reservations.clone();
+    state.save();
+    logging::shutdown();
 }
 
+/// Fetch the current availability for a command that prints it. Exits if the fetch fails.
+fn fetch_or_exit() -> Snapshot {
+    fetch_availability(&mut State::load(), &CONFIG.resource_id).unwrap_or_else(|error| {
+        tracing::error!(%error, "Failed to fetch API data");
+        std::process::exit(1);
+    })
+}
 
-/// Read existing available times from a file called available_times.
-/// If the file does not exist, return an empty vector.
-///
-/// The lines are in the following format:
-/// 2021-09-01T10:00:00+03:00,2021-09-01T11:00:00+03:00
-fn read_existing_available_times(file: &mut std::fs::File) -> Vec<Timeslot> {
-    let mut file_contents = String::new();
-    file.read_to_string(&mut file_contents).expect("Failed to read file");
-    let file_lines = file_contents.split("\n");
-    
-    let mut existing_available_times: Vec<Timeslot> = Vec::new();
-    for line in file_lines {
-        if line == "" {
-            continue;
+/// Detach from the terminal to run in the background, with stdout and stderr appended to the
+/// log file. The working directory is kept, since the config and state may be found there.
+/// Exits if the log file can't be opened or the process can't be detached.
+#[cfg(unix)]
+fn daemonize() {
+    let path = CONFIG.log_file.clone().unwrap_or_else(|| paths::state_file(LOG_FILE));
+    let daemon = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::OpenOptions::new().create(true).append(true).open(&path))
+        .and_then(|log| Ok((log.try_clone()?, log)))
+        .map_err(|error| error.to_string())
+        .and_then(|(stdout, stderr)| {
+            let working_directory = std::env::current_dir().map_err(|error| error.to_string())?;
+            daemonize::Daemonize::new()
+                .working_directory(working_directory)
+                .stdout(stdout)
+                .stderr(stderr)
+                .start()
+                .map_err(|error| error.to_string())
+        });
+    if let Err(error) = daemon {
+        eprintln!("Failed to start in the background with the log in {}: {}", path.display(), error);
+        std::process::exit(1);
+    }
+}
+
+/// Append stdout and stderr to the log file, since a Windows service has no console.
+/// Exits if the log file can't be opened.
+#[cfg(windows)]
+fn daemonize() {
+    use std::os::windows::io::IntoRawHandle;
+    use windows_sys::Win32::System::Console::{SetStdHandle, STD_ERROR_HANDLE, STD_OUTPUT_HANDLE};
+
+    let path = CONFIG.log_file.clone().unwrap_or_else(|| paths::state_file(LOG_FILE));
+    let log = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::OpenOptions::new().create(true).append(true).open(&path))
+        .and_then(|log| Ok((log.try_clone()?, log)));
+    match log {
+        // The handles stay open for the life of the process.
+        Ok((stdout, stderr)) => unsafe {
+            SetStdHandle(STD_OUTPUT_HANDLE, stdout.into_raw_handle());
+            SetStdHandle(STD_ERROR_HANDLE, stderr.into_raw_handle());
+        },
+        Err(error) => {
+            eprintln!("Failed to open the log file {}: {}", path.display(), error);
+            std::process::exit(1);
         }
-        let timeslot = Timeslot {
-            start: line.split(",").collect::<Vec<&str>>()[0].to_owned(),
-            end: line.split(",").collect::<Vec<&str>>()[1].to_owned(),
-        };
-        existing_available_times.push(timeslot);
     }
+}
 
-    return existing_available_times;
+#[cfg(not(any(unix, windows)))]
+fn daemonize() {
+    eprintln!("--daemon is not available on this platform");
+    std::process::exit(1);
 }
 
+/// A signal the polling loop acts on.
+enum Signal {
+    /// SIGINT or SIGTERM: finish the current cycle and exit.
+    Terminate,
+    /// SIGHUP: reload the config file.
+    #[cfg(unix)]
+    Reload,
+}
+
+/// Handle SIGINT and SIGTERM by letting the current cycle finish before exiting, and SIGHUP by
+/// reloading the config. Returns a channel that receives a message when a signal arrives.
+/// A second termination signal exits immediately.
+#[cfg(unix)]
+fn handle_signals() -> mpsc::Receiver<Signal> {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP]).expect("Failed to set signal handler");
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut terminating = false;
+        for signal in signals.forever() {
+            if signal == SIGHUP {
+                tracing::info!("Hangup signal received, reloading config");
+                sender.send(Signal::Reload).ok();
+                continue;
+            }
+            if terminating {
+                std::process::exit(130);
+            }
+            terminating = true;
+            tracing::info!("Termination signal received, finishing current cycle");
+            sender.send(Signal::Terminate).ok();
+        }
+    });
+    receiver
+}
+
+/// Sender of the channel handle_signals returns.
+#[cfg(not(unix))]
+static SIGNALS: once_cell::sync::OnceCell<std::sync::Mutex<mpsc::Sender<Signal>>> = once_cell::sync::OnceCell::new();
 
-/// Make an API request to api.hel.fi/respa and return response as a JSON object.
+/// Handle Ctrl+C and closing the console, and stopping the Windows service, by letting the
+/// current cycle finish before exiting. There is no SIGHUP, so the config is only reloaded when
+/// the file changes.
+#[cfg(not(unix))]
+fn handle_signals() -> mpsc::Receiver<Signal> {
+    let (sender, receiver) = mpsc::channel();
+    SIGNALS.set(std::sync::Mutex::new(sender)).ok();
+    ctrlc::set_handler(terminate).expect("Failed to set signal handler");
+    receiver
+}
+
+/// Finish the current cycle and exit. A second call exits immediately.
+#[cfg(not(unix))]
+fn terminate() {
+    static TERMINATING: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+    if TERMINATING.swap(true, Ordering::SeqCst) {
+        std::process::exit(130);
+    }
+    tracing::info!("Termination signal received, finishing current cycle");
+    if let Some(sender) = SIGNALS.get() {
+        sender.lock().unwrap().send(Signal::Terminate).ok();
+    }
+}
+
+/// Subscribe the configured chat and the chats of the routes, if they aren't already.
+fn add_subscribers(state: &mut State) {
+    state.add_subscriber(&CONFIG.telegram_chat_id);
+    for route in CONFIG.routes.values() {
+        for chat_id in &route.chats {
+            state.add_subscriber(chat_id);
+        }
+    }
+}
+
+/// Read the config file again and put it in effect without restarting or dropping the state.
+/// The next cycle is one polling interval from now. An invalid config is logged and ignored.
 ///
-/// # Panics
+/// If `resource_id` or `routes` changed, the next cycle records a baseline, since the saved
+/// availability and the chats notified about it belong to the old configuration.
 ///
-/// Panics if the API request fails or if the JSON parsing fails.
-fn fetch_api_data() -> serde_json::Value {
-    let current_time = Local::now();
-    let start_date = current_time.format("%Y-%m-%d").to_string();
-    let end_date = (current_time.checked_add_days(Days::new(CONFIG.days))).unwrap().format("%Y-%m-%d").to_string();
+/// The `[http]` server, logging, `[client]` and `replay_dir` are set up on startup, so changes
+/// to them need a restart.
+fn reload_config(state: &mut State, scheduler: &mut Scheduler) {
+    match config::read() {
+        Ok(config) => {
+            if config.resource_id != CONFIG.resource_id || config.routes != CONFIG.routes {
+                tracing::info!("Monitored resource or routes changed, recording a new baseline");
+                state.baseline = true;
+            }
+            CONFIG.replace(config);
+            add_subscribers(state);
+            let jitter = std::time::Duration::from_secs(CONFIG.poll_jitter_secs);
+            *scheduler = Scheduler::new(CONFIG.poll_interval_at(Local::now()), CONFIG.schedule.as_ref(), jitter);
+            tracing::info!(path = %config::path(), "Config reloaded");
+        }
+        Err(error) => tracing::error!(%error, "Failed to reload config, keeping the current one"),
+    }
+}
 
-    // Append "T23:59:59" to end_date to get all reservations for the day.
-    let end_date = format!("{}T23:59:59", end_date);
+/// Handle bot commands, then fetch the availability of each monitored resource, notify about
+/// new times and save the state. Returns the fetched availability, which is empty if monitoring
+/// is paused. Fails only if no resource could be fetched.
+///
+/// In a dry run, only fetches availability and prints what would be notified.
+/// Nothing is sent or published, and the state is not saved.
+fn run_cycle(state: &mut State, dry_run: bool) -> Result<Vec<Snapshot>, respa::FetchError> {
+    if !dry_run {
+        state.counters.seen(Local::now());
+        state.counters.skipped_cycles += scheduler::SKIPPED_CYCLES.swap(0, Ordering::Relaxed);
+        commands::handle_commands(state);
+        telegram::flush_outbox(state);
+    }
 
-    let request_url = format!("https://api.hel.fi/respa/v1/resource/axwzr3i57yba/?start={}&end={}&format=json", start_date, end_date);
+    // Skip polling entirely while monitoring is paused.
+    let today = timezone::now().date_naive();
+    if state.paused_until.is_some_and(|until| today >= until) {
+        state.paused_until = None;
+    }
+    if let Some(until) = state.paused_until.into_iter().chain(CONFIG.paused_until(today)).max() {
+        tracing::info!(%until, "Monitoring paused");
+        if !dry_run {
+            state.paused = true;
+            state.save();
+        }
+        return Ok(Vec::new());
+    }
 
-    println!("Request URL: {}", request_url);
+    // Back off while the API seems to be down.
+    if !dry_run && !breaker::allows_fetch(state) {
+        state.save();
+        return Ok(Vec::new());
+    }
 
-    let api_response = reqwest::blocking::get(&request_url).expect("Failed to fetch API data").text().unwrap();
-    let api_data: serde_json::Value = serde_json::from_str(&api_response).expect("Failed to parse JSON");
-    api_data
+    // Resources with a longer polling interval than the cycles are skipped until they're due.
+    // A cron schedule replaces the intervals, so then every resource is fetched on every cycle.
+    let now = Local::now();
+    let mut snapshots = Vec::new();
+    let mut error = None;
+    let resource_ids = unit::monitored_resources(state);
+    state.fetch_schedule.retain(&resource_ids);
+    for resource_id in resource_ids {
+        let interval = CONFIG.poll_interval_of_at(&resource_id, now);
+        if CONFIG.schedule.is_none()
+            && !state.fetch_schedule.is_due(&resource_id, interval, CONFIG.poll_interval_at(now), std::time::Instant::now())
+        {
+            tracing::debug!(resource = %resource_id, "Resource not due for a fetch yet");
+            continue;
+        }
+        match check_resource(state, &resource_id, dry_run) {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(fetch_error) => error = error.or(Some(fetch_error)),
+        }
+    }
+    if dry_run {
+        return match error {
+            Some(error) if snapshots.is_empty() => Err(error),
+            _ => Ok(snapshots),
+        };
+    }
+    match &error {
+        Some(error) if snapshots.is_empty() => breaker::record_failure(state, error),
+        // Nothing was due, so there's nothing to tell about the API.
+        None if snapshots.is_empty() => {}
+        _ => breaker::record_success(state),
+    }
+
+    // The digest and own reservations are about the configured resource only.
+    if let Some(snapshot) = snapshots.iter().find(|snapshot| snapshot.resource.id == CONFIG.resource_id) {
+        digest::send_if_due(state, &snapshot.resource, &snapshot.opening_times, &snapshot.available_times);
+        for message in own_reservations::update(state, &snapshot.resource.id) {
+            notify_operator(&snapshot.resource, &message);
+        }
+        state.paused = false;
+        state.baseline = false;
+        state.updated_at = Some(Local::now());
+    }
+    state.save();
+
+    match error {
+        Some(error) if snapshots.is_empty() => Err(error),
+        _ => Ok(snapshots),
+    }
 }
 
+/// Fetch the availability of a resource, notify about its new times and record them in the state.
+fn check_resource(state: &mut State, resource_id: &str, dry_run: bool) -> Result<Snapshot, respa::FetchError> {
+    let span = tracing::info_span!(
+        "cycle",
+        resource = %resource_id,
+        available = tracing::field::Empty,
+        new = tracing::field::Empty
+    );
+    let _span = span.enter();
 
-/// Parse all opening times from API data. Return a vector of Timeslot structs.
-/// Returns an empty vector on error.
-fn parse_opening_times(api_data: &serde_json::Value) -> Vec<Timeslot> {   
-    // Get opening hours from API data. If opening hours is null, return an empty vector.
-    let binding = Vec::new();
-    let opening_hours = api_data["opening_hours"].as_array().unwrap_or(&binding);
-        
-    // Create a Vec<Timeslot> from opening hours
-    let mut opening_times: Vec<Timeslot> = Vec::new();
-    for opening_hour in opening_hours {
-        // Skip opening hours that are null
-        if opening_hour["opens"].is_null() || opening_hour["closes"].is_null() {
-            continue;
+    let fetch_started = std::time::Instant::now();
+    let snapshot = match fetch_availability(state, resource_id) {
+        Ok(snapshot) => snapshot,
+        Err(error) => {
+            tracing::error!(%error, "Failed to fetch API data");
+            if !dry_run {
+                state.counters.failed_fetches += 1;
+            }
+            return Err(error);
         }
+    };
+    let fetch_duration = fetch_started.elapsed();
+    if !dry_run {
+        state.counters.fetches += 1;
+        state.fetched_at.insert(resource_id.to_owned(), Local::now());
+    }
+    let Snapshot {
+        resource,
+        opening_times,
+        available_times,
+        ..
+    } = snapshot;
+    span.record("available", available_times.len());
 
-        // Create Timeslot from opening hour
-        let timeslot = Timeslot { 
-            start: opening_hour["opens"].as_str().unwrap().to_owned(), 
-            end: opening_hour["closes"].as_str().unwrap().to_owned() 
-        };
+    // Usually nothing has changed, e.g. when the API answered 304 Not Modified.
+    let previous_times = state.available_times_of(resource_id).cloned();
+    let diff_span = tracing::info_span!("diff").entered();
+    let diff = if previous_times.as_ref() == Some(&available_times) {
+        tracing::debug!("Availability unchanged");
+        Diff::default()
+    } else {
+        CONFIG
+            .diff_strategy(resource_id)
+            .diff(previous_times.as_deref().unwrap_or_default(), &available_times)
+    };
+    diff_span.exit();
 
-        // Add Timeslot to opening times
-        opening_times.push(timeslot);
+    // After a pause or lost state the previous times are out of date, so only record the current ones.
+    // The same goes for resources fetched for the first time.
+    let baseline = state.paused || state.baseline || previous_times.is_none();
+    let new_times = if baseline {
+        tracing::info!("Recording current availability as a baseline, not notifying about current times");
+        if !dry_run {
+            state.unconfirmed_times.remove(resource_id);
+        }
+        Vec::new()
+    } else {
+        let new_times = diff
+            .added
+            .iter()
+            .filter(|time| within_notify_horizon(time))
+            .filter(|time| time.duration_minutes() >= CONFIG.min_duration_minutes())
+            .filter(|time| CONFIG.interest.is_interesting(time))
+            .filter(|time| !CONFIG.blackout.iter().any(|blackout| blackout.covers(time)))
+            .filter(|time| is_reachable(&resource, time))
+            .cloned()
+            .collect::<Vec<Timeslot>>();
+        confirm_new_times(state, &resource.id, new_times, &available_times, dry_run)
+            .into_iter()
+            .filter(|time| {
+                let repeat = state.was_notified(&resource.id, time, chrono::Duration::minutes(CONFIG.dedup_minutes));
+                if repeat {
+                    tracing::info!(%time, "Time was announced recently, not announcing it again");
+                }
+                !repeat
+            })
+            .collect()
+    };
+
+    span.record("new", new_times.len());
+
+    if dry_run {
+        let notified_times = apply_cooldown(state, &resource, &new_times, &available_times);
+        let alerts = wishlist::alerts(&resource.name, &new_times, &available_times);
+        print_dry_run(state, &resource, &notified_times, &alerts, &diff.removed);
+        return Ok(Snapshot {
+            resource,
+            opening_times,
+            available_times,
+            new_times,
+            fetched_at: Local::now(),
+        });
     }
 
-    return opening_times;
+    // After a long downtime, send one summary of the current availability.
+    if resource.id == CONFIG.resource_id {
+        if let Some(since) = state.resynced_after.take() {
+            let minutes = available_times.iter().map(Timeslot::duration_minutes).sum::<i64>();
+            let summary = CONFIG.language.resynced(timezone::zoned(&since).date_naive(), available_times.len(), minutes);
+            notify_all(state, &resource, &summary);
+        }
+    }
+
+    // Send notifications about new times, unless the resource is cooling down.
+    let notified_times = apply_cooldown(state, &resource, &new_times, &available_times);
+    state.remember_notified(&resource.id, &notified_times, chrono::Duration::minutes(CONFIG.dedup_minutes));
+    send_notifications(state, &resource, &notified_times);
+    for entry in &CONFIG.wishlist {
+        for notifier in CONFIG.notifiers_for(&resource.id) {
+            notify_times(state, *notifier, &resource, &new_times, false, |_, times| {
+                wishlist::alert(entry, &resource.name, times, &available_times).map(OutgoingMessage::text)
+            });
+        }
+    }
+    watch::notify(state, &resource, &new_times);
+
+    // The MQTT topics have no resource in them, so only the configured resource is published.
+    if let Some(mqtt_config) = CONFIG.mqtt.as_ref().filter(|_| resource.id == CONFIG.resource_id) {
+        mqtt::publish(mqtt_config, &available_times, &new_times);
+    }
+
+    if let Some(pushgateway_config) = &CONFIG.pushgateway {
+        let mut metrics = Metrics::default();
+        metrics.gauge("laser_fetch_duration_seconds", "Duration of the respa API request.", fetch_duration.as_secs_f64());
+        metrics.gauge("laser_last_fetch_timestamp_seconds", "Time of the last successful fetch.", Local::now().timestamp() as f64);
+        metrics.gauge("laser_available_slots", "Number of available time blocks.", available_times.len() as f64);
+        metrics.gauge(
+            "laser_available_hours",
+            "Total available hours.",
+            available_times.iter().map(Timeslot::duration_minutes).sum::<i64>() as f64 / 60.0,
+        );
+        metrics.gauge("laser_new_slots", "Number of new available time blocks found.", new_times.len() as f64);
+        metrics.gauge("laser_notified_slots", "Number of time blocks notified about.", notified_times.len() as f64);
+        state.counters.add_to(&mut metrics);
+        metrics::push(pushgateway_config, &resource.id, &metrics);
+    }
+
+    // Record what changed since the previous fetch. After a pause or lost state the previous times are out of date.
+    if !baseline {
+        let changes = changes::diff(&resource.id, &diff, Local::now());
+        let path = CONFIG.change_log.clone().unwrap_or_else(|| paths::state_file(changes::LOG_FILE));
+        if let Err(error) = changes::append(&path, &changes) {
+            tracing::warn!(%error, path = %path.display(), "Failed to append to the change log");
+        }
+        changes::record(&mut state.changes, changes);
+    }
+    let observation = Observation::new(&resource.id, &opening_times, &available_times, Local::now());
+    history::record(&mut state.history, observation);
+
+    // Save available times, replacing the previously seen times.
+    state.set_available_times(&resource.id, available_times.clone());
+
+    Ok(Snapshot {
+        resource,
+        opening_times,
+        available_times,
+        new_times,
+        fetched_at: Local::now(),
+    })
 }
 
-/// Parse all reservations times from API data. Return a vector of Timeslot structs.
-/// Returns an empty vector on error.
-fn parse_reservations(api_data: &serde_json::Value) -> Vec<Timeslot> {
-    // Get reservations from API data
-    let binding = Vec::new();
-    let reservations = api_data["reservations"].as_array().unwrap_or(&binding);
+/// Fetch the opening times and reservations of a resource from the data source and compute its
+/// available times.
+/// Reservations from the webhook, and for the configured resource the maintenance schedule,
+/// are subtracted too.
+/// The returned snapshot has no new times.
+fn fetch_availability(state: &mut State, resource_id: &str) -> Result<Snapshot, respa::FetchError> {
+    let api_data = tracing::info_span!("fetch").in_scope(|| source::source().fetch(resource_id))?;
 
-    // Create a Vec<Timeslot> from reservations
-    let mut reservation_times: Vec<Timeslot> = Vec::new();
-    for reservation in reservations {
-        // Skip reservations that are null
-        if reservation["begin"].is_null() || reservation["end"].is_null() {
-            continue;
+    let _span = tracing::info_span!("parse").entered();
+    let resource = respa::parse_resource(resource_id, &api_data);
+    let opening_times = respa::parse_opening_times(&api_data);
+    let mut reservations = respa::parse_reservations(&api_data);
+    if resource_id == CONFIG.resource_id {
+        reservations.extend(maintenance::reservations(&CONFIG.maintenance, &opening_times));
+    }
+    state::drop_past_reservations(&mut state.external_reservations, Local::now());
+    if let Some(sources) = state.external_reservations.get(&resource.id) {
+        reservations.extend(sources.values().flatten().cloned());
+    }
+    let mut available_times = availability::get_available_times(&opening_times, &reservations);
+
+    // Times further ahead than the resource can be reserved aren't really available.
+    if let Some(max_days) = respa::parse_max_days_in_advance(&api_data) {
+        if CONFIG.lookahead_days() > max_days {
+            static WARNING: std::sync::Once = std::sync::Once::new();
+            WARNING.call_once(|| {
+                tracing::warn!(
+                    lookahead_days = CONFIG.lookahead_days(),
+                    max_days,
+                    "lookahead_days is longer than the resource can be reserved in advance"
+                );
+            });
+            let last_day = timezone::now().date_naive() + chrono::Days::new(max_days);
+            available_times.retain(|time| time.start_time().date_naive() <= last_day);
         }
+    }
 
-        // Create Timeslot from reservation
-        let timeslot = Timeslot { 
-            start: reservation["begin"].as_str().unwrap().to_owned(), 
-            end: reservation["end"].as_str().unwrap().to_owned() 
-        };
+    Ok(Snapshot {
+        resource,
+        opening_times,
+        available_times,
+        new_times: Vec::new(),
+        fetched_at: Local::now(),
+    })
+}
 
-        // Add Timeslot to reservation times
-        reservation_times.push(timeslot);
+/// Print the notification and wishlist alerts a cycle would send, and the previously seen times
+/// that are no longer available.
+fn print_dry_run(state: &State, resource: &Resource, notified_times: &[Timeslot], alerts: &[String], removed_times: &[Timeslot]) {
+    if notified_times.is_empty() {
+        println!("No notification would be sent.");
+    } else {
+        for notifier in &CONFIG.notifiers {
+            let message = message::render(resource, notified_times, &state.changes, &CONFIG.format(*notifier));
+            println!("Would notify via {}:\n{}", notifier.name(), message);
+        }
+    }
+    for alert in alerts {
+        println!("Would alert:\n{}", alert);
     }
 
-    return reservation_times;
+    if !removed_times.is_empty() {
+        println!("No longer available:");
+        for time in removed_times {
+            println!("{}", time.localized(CONFIG.language));
+        }
+    }
+}
+
+/// Return true if the time starts within `notify_max_days_ahead` days from now, if set.
+fn within_notify_horizon(time: &Timeslot) -> bool {
+    match CONFIG.notify_max_days_ahead {
+        Some(max_days) => time.start_time() <= timezone::now() + chrono::Days::new(max_days),
+        None => true,
+    }
 }
 
-fn update_file(file: &mut std::fs::File, available_times: &[Timeslot]) {
-    file.set_len(0).expect("Failed to truncate file");
-    file.seek(SeekFrom::Start(0)).unwrap();
-    for time in available_times {
-        file.write_all(format!("{},{}\n", time.start, time.end).as_bytes()).expect("Failed to write to file");
+/// Return true if there's time to travel to the venue of the resource before the time starts.
+fn is_reachable(resource: &Resource, time: &Timeslot) -> bool {
+    let travel_time = CONFIG.travel.to_venue(resource.unit.as_deref());
+    time.start_time() >= Local::now() + travel_time
+}
+
+/// With `confirm_new_times`, hold back the new times until the next fetch and return the parts of
+/// the times held back by the previous fetch that are still available. Otherwise return the new times.
+fn confirm_new_times(
+    state: &mut State,
+    resource_id: &str,
+    new_times: Vec<Timeslot>,
+    available_times: &[Timeslot],
+    dry_run: bool,
+) -> Vec<Timeslot> {
+    if !CONFIG.confirm_new_times {
+        return new_times;
+    }
+    let unconfirmed = if dry_run {
+        state.unconfirmed_times.get(resource_id).cloned().unwrap_or_default()
+    } else {
+        state.unconfirmed_times.insert(resource_id.to_owned(), new_times.clone()).unwrap_or_default()
+    };
+    if !new_times.is_empty() {
+        tracing::info!(unconfirmed = new_times.len(), "New times held back until the next fetch confirms them");
+    }
+    IntervalSet::from_timeslots(&unconfirmed)
+        .intersection(&IntervalSet::from_timeslots(available_times))
+        .to_timeslots()
+}
+
+/// Hold back new times if the resource was notified about less than `cooldown_minutes` ago.
+/// Held back times are added to the next notification, if they are still available by then.
+/// Returns the times to notify about now.
+fn apply_cooldown(state: &mut State, resource: &Resource, new_times: &[Timeslot], available_times: &[Timeslot]) -> Vec<Timeslot> {
+    let mut times = state.pending_times.remove(&resource.id).unwrap_or_default();
+    times.retain(|time| available_times.iter().any(|available| available.covers(time)) && !new_times.contains(time));
+    times.extend(new_times.iter().cloned());
+    times.sort_by_key(|time| time.start_time());
+    if times.is_empty() {
+        return times;
+    }
+
+    let now = Local::now();
+    let cooldown = chrono::Duration::minutes(CONFIG.cooldown_minutes);
+    if let Some(last_notified) = state.last_notified.get(&resource.id) {
+        if now - *last_notified < cooldown {
+            tracing::info!(held_back = times.len(), "New times held back until the cooldown ends");
+            state.pending_times.insert(resource.id.clone(), times);
+            return Vec::new();
+        }
     }
+
+    state.last_notified.insert(resource.id.clone(), now);
+    times
 }
 
-fn send_telegram_message(new_times: &Vec<&Timeslot>) {
-    // Send telegram message with new available times
+fn send_notifications(state: &mut State, resource: &Resource, new_times: &[Timeslot]) {
+    // Send notifications with new available times through each configured notifier.
     // If there are no new available times, do nothing.
-    if new_times.len() == 0 {
-        println!("No new available times");
+    if new_times.is_empty() {
+        tracing::info!("No new available times");
         return;
     }
 
-    // Create message
-    let mut message = String::new();
-    message.push_str("New available times:%0A");
-    for time in new_times {
-        message.push_str(&format!("{}%0A", time));
+    // Create a message from the configured template for each notifier, in its format.
+    // Telegram messages get booking buttons if booking is configured.
+    for notifier in CONFIG.notifiers_for(&resource.id) {
+        let profile = CONFIG.format(*notifier);
+        notify_times(state, *notifier, resource, new_times, profile.markdown, |state, times| {
+            let message = message::render(resource, times, &state.changes, &profile);
+            Some(OutgoingMessage {
+                reply_markup: booking::keyboard(&resource.id, times, CONFIG.language),
+                ..OutgoingMessage::text(message)
+            })
+        });
     }
+    state.counters.notifications += 1;
 
-    let bot_token = CONFIG.telegram_bot_token.as_str();
-    let chat_id = CONFIG.telegram_chat_id.as_str();
+    // Follow the first message of the day about a venue with its location.
+    if !CONFIG.send_venue || !CONFIG.notifiers_for(&resource.id).contains(&Notifier::Telegram) {
+        return;
+    }
+    let Some(unit_id) = &resource.unit else {
+        return;
+    };
+    let today = timezone::now().date_naive();
+    let unsent = |subscriber: &Subscriber| subscriber.venue_sent_on.get(unit_id) != Some(&today);
+    if !state.subscribers.iter().any(|subscriber| !subscriber.inactive && unsent(subscriber)) {
+        return;
+    }
+    if let Some(unit) = respa::fetch_unit(unit_id) {
+        let delivered = telegram::broadcast_venue(state, &resource.id, &unit, unsent);
+        for subscriber in state.subscribers.iter_mut().filter(|subscriber| delivered.contains(&subscriber.chat_id)) {
+            subscriber.venue_sent_on.insert(unit_id.clone(), today);
+        }
+    }
+}
 
-    let url = format!("https://api.telegram.org/bot{}/sendMessage?chat_id={}&text={}", 
-        bot_token, 
-        chat_id, 
-        message);
+/// Send a plain text message through each notifier configured for the resource.
+fn notify_all(state: &mut State, resource: &Resource, message: &str) {
+    for notifier in CONFIG.notifiers_for(&resource.id) {
+        notify(state, *notifier, resource, message, false);
+    }
+}
+
+/// Send a plain text message through each notifier configured for the resource, over Telegram
+/// to the operator chat only, e.g. about the reservations of the API token's user.
+fn notify_operator(resource: &Resource, message: &str) {
+    for notifier in CONFIG.notifiers_for(&resource.id) {
+        match notifier {
+            Notifier::Telegram => {
+                if let Err(error) = telegram::send_message(CONFIG.operator_chat(), message) {
+                    tracing::warn!(%error, "Failed to send message to operator chat");
+                }
+            }
+            Notifier::Desktop => desktop::notify(&resource.name, message),
+        }
+    }
+}
 
-    let response = reqwest::blocking::get(&url).expect("Failed to send message");
-    println!("Telegram response: {}", response.text().unwrap());
+/// Send a message about new times of a resource through a notifier. `message_for` returns the
+/// message about some of the times and its Telegram buttons, or None if there is nothing to send.
+/// Each Telegram chat gets a message about the times not on its blackout dates.
+fn notify_times(
+    state: &mut State,
+    notifier: Notifier,
+    resource: &Resource,
+    new_times: &[Timeslot],
+    markdown: bool,
+    message_for: impl Fn(&State, &[Timeslot]) -> Option<OutgoingMessage>,
+) {
+    let _span = tracing::info_span!("send", notifier = notifier.name()).entered();
+    match notifier {
+        Notifier::Telegram => telegram::broadcast_times(state, &resource.id, new_times, markdown, message_for),
+        Notifier::Desktop => {
+            if let Some(message) = message_for(state, new_times) {
+                desktop::notify(&resource.name, &message.text);
+            }
+        }
+    }
+}
+
+/// Send a message about a resource through a notifier. Telegram messages go to the chats the
+/// resource is routed to. Markdown is only used by Telegram.
+fn notify(state: &mut State, notifier: Notifier, resource: &Resource, message: &str, markdown: bool) {
+    let _span = tracing::info_span!("send", notifier = notifier.name()).entered();
+    match notifier {
+        Notifier::Telegram => telegram::broadcast(state, &resource.id, message, markdown),
+        Notifier::Desktop => desktop::notify(&resource.name, message),
+    }
 }
@@ -0,0 +1,68 @@
+use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+use crate::scheduler::deserialize_time;
+use crate::timeslot::Timeslot;
+use crate::timezone;
+
+/// A recurring block of time when the resource can't be used even if respa shows it as free, e.g.
+///
+/// ```toml
+/// [[maintenance]]
+/// description = "Machine maintenance"
+/// weekday = "mon"
+/// week_of_month = 1
+/// from = "09:00"
+/// to = "12:00"
+/// ```
+///
+/// Without `weekday` the block recurs daily. `week_of_month` limits it to the nth such weekday
+/// of the month, or the last one with -1.
+#[derive(Deserialize)]
+pub(crate) struct MaintenanceBlock {
+    #[serde(default)]
+    pub(crate) description: String,
+    pub(crate) weekday: Option<Weekday>,
+    pub(crate) week_of_month: Option<i32>,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub(crate) from: NaiveTime,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub(crate) to: NaiveTime,
+}
+
+impl MaintenanceBlock {
+    fn occurs_on(&self, date: NaiveDate) -> bool {
+        if self.weekday.is_some_and(|weekday| date.weekday() != weekday) {
+            return false;
+        }
+        match self.week_of_month {
+            Some(-1) => (date + chrono::Days::new(7)).month() != date.month(),
+            Some(week) => (date.day0() / 7 + 1) as i32 == week,
+            None => true,
+        }
+    }
+}
+
+/// Return the maintenance blocks on the days of the given opening times, to be subtracted
+/// from the availability like reservations. Blocks ending before they start continue past midnight.
+pub(crate) fn reservations(blocks: &[MaintenanceBlock], opening_times: &[Timeslot]) -> Vec<Timeslot> {
+    let dates = opening_times
+        .iter()
+        .map(|time| time.start_time().date_naive())
+        .collect::<BTreeSet<NaiveDate>>();
+
+    let mut reservations: Vec<Timeslot> = Vec::new();
+    for date in dates {
+        for block in blocks.iter().filter(|block| block.occurs_on(date)) {
+            let end_date = if block.to <= block.from { date + chrono::Days::new(1) } else { date };
+            let (start, end) = (timezone::at(date, block.from), timezone::at(end_date, block.to));
+            tracing::debug!(description = %block.description, %start, "Maintenance block");
+            reservations.push(Timeslot {
+                start: start.to_rfc3339(),
+                end: end.to_rfc3339(),
+            });
+        }
+    }
+    reservations
+}
@@ -0,0 +1,301 @@
+use handlebars::Handlebars;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::changes::Change;
+use crate::stats;
+use crate::timeslot::{self, Block, Timeslot};
+use crate::CONFIG;
+
+/// Template used when none is configured.
+const DEFAULT_TEMPLATE: &str = "{{heading}}:\n{{#each slots}}{{this.text}}{{#if this.interest}} ({{this.interest}}){{/if}}{{#if this.urgent}} ({{this.urgent}}){{/if}}\n{{/each}}";
+
+/// Default template with Telegram MarkdownV2 formatting. Literal special characters must be escaped.
+const MARKDOWN_TEMPLATE: &str =
+    "*{{heading}}*:\n{{#each slots}}{{this.text}}{{#if this.interest}} _\\({{this.interest}}\\)_{{/if}}{{#if this.urgent}} _\\({{this.urgent}}\\)_{{/if}}\n{{/each}}";
+
+/// Default template for times grouped by day.
+const DAY_TEMPLATE: &str = "{{heading}}:\n{{summary}}\n\n{{#each days}}{{this.heading}}\n\
+     {{#each this.slots}}{{this.text}}{{#if this.interest}} ({{this.interest}}){{/if}}{{#if this.urgent}} ({{this.urgent}}){{/if}}\n{{/each}}{{#unless @last}}\n{{/unless}}{{/each}}";
+
+/// Default template for times grouped by day, with Telegram MarkdownV2 formatting.
+const MARKDOWN_DAY_TEMPLATE: &str = "*{{heading}}*:\n_{{summary}}_\n\n{{#each days}}*{{this.heading}}*\n\
+     {{#each this.slots}}{{this.text}}{{#if this.interest}} _\\({{this.interest}}\\)_{{/if}}{{#if this.urgent}} _\\({{this.urgent}}\\)_{{/if}}\n{{/each}}{{#unless @last}}\n{{/unless}}{{/each}}";
+
+/// How notifications are formatted for a notifier, e.g.
+///
+/// ```toml
+/// [formats.telegram]
+/// markdown = true
+/// emoji = true
+///
+/// [formats.desktop]
+/// max_length = 200
+/// grouping = "day"
+/// ```
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct FormatProfile {
+    /// Longest message in characters. Longer messages are cut at a line and end with "…". 0 means no limit.
+    pub(crate) max_length: usize,
+    /// Format with Telegram MarkdownV2, e.g. a bold heading. Desktop notifications ignore it.
+    pub(crate) markdown: bool,
+    /// Start the heading and each time with an emoji.
+    pub(crate) emoji: bool,
+    pub(crate) grouping: Grouping,
+}
+
+/// How the new times are listed.
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Grouping {
+    /// A line for each time, with its date.
+    None,
+    /// A summary, and the times of each day under a heading with the weekday and date.
+    #[default]
+    Day,
+}
+
+/// The monitored resource, as shown in notifications.
+#[derive(Clone)]
+pub(crate) struct Resource {
+    pub(crate) id: String,
+    /// Name in the configured language.
+    pub(crate) name: String,
+    /// Name in all languages the API provides, e.g. `{"fi": ..., "en": ..., "sv": ...}`.
+    pub(crate) names: serde_json::Value,
+    /// Respa ID of the venue the resource is at, e.g. "tprek:51342".
+    pub(crate) unit: Option<String>,
+}
+
+impl Resource {
+    /// Varaamo page where the resource can be booked.
+    pub(crate) fn booking_url(&self) -> String {
+        format!("https://varaamo.hel.fi/resources/{}", self.id)
+    }
+}
+
+/// Render the notification text for new available times using the configured template.
+///
+/// The template has access to `resource.id`, `resource.name`, `resource.names.<language>`, `resource.url`,
+/// `slots` (each with `start`, `end`, `hours`, `gaps`, the formatted `text`, `usually_booked_within`,
+/// `urgent`, `score` and `interest`), `days` (each with `date`, a `heading` like "Friday 1.12.", its
+/// `slots` with `start`, `end`, `text` without the date, `urgent` and `interest`, and the formatted `text`), `count`,
+/// `total_hours` (e.g. 1.5), `total_duration` like "1 h 30 min", a `summary` like "3 new slots, total 7 h",
+/// `emoji` and a localized `heading`.
+///
+/// The notifier's format profile picks the default template, adds emoji to the heading and the
+/// times, escapes the values for Telegram MarkdownV2 and limits the length of the message.
+///
+/// `usually_booked_within` is the median minutes that earlier times of the same weekday and hour
+/// stayed available, from the change log. If it is at most `urgent_minutes`, `urgent` is a note
+/// like "slots like this usually go within 40 minutes".
+///
+/// New times separated by at most `bridge_gap_minutes` are shown as one slot,
+/// with the reservations between them listed in `gaps`.
+///
+/// With `[interest]` weights configured, `score` is the interest score of the slot and the most
+/// interesting slots come first, with days ordered by their most interesting slot. `interest` is a
+/// note like "score 15" for slots scoring above 1.
+///
+/// Falls back to the default template if the configured one fails to render.
+pub(crate) fn render(resource: &Resource, new_times: &[Timeslot], changes: &[Change], profile: &FormatProfile) -> String {
+    let bullet = if profile.emoji { "🟢 " } else { "" };
+    let max_gap = chrono::Duration::minutes(CONFIG.bridge_gap_minutes);
+    let time_to_book = stats::time_to_book(changes);
+    let mut blocks = timeslot::bridge_gaps(new_times, max_gap);
+    if CONFIG.interest.is_enabled() {
+        blocks.sort_by(|a, b| CONFIG.interest.score(&b.slot).total_cmp(&CONFIG.interest.score(&a.slot)));
+    }
+    let score = |block: &Block| CONFIG.interest.is_enabled().then(|| CONFIG.interest.score(&block.slot));
+    let interest = |block: &Block| score(block).filter(|score| *score > 1.0).map(|score| CONFIG.language.interest_score(score));
+    let urgent = |block: &Block| {
+        let usually_booked_within = stats::median_time_to_book(&time_to_book, &block.slot);
+        let urgent = usually_booked_within
+            .filter(|minutes| CONFIG.urgent_minutes > 0 && *minutes <= CONFIG.urgent_minutes)
+            .map(|minutes| CONFIG.language.usually_booked_within(minutes));
+        (usually_booked_within, urgent)
+    };
+    let slots = blocks
+        .iter()
+        .map(|block| {
+            let (usually_booked_within, urgent) = urgent(block);
+            json!({
+                "start": block.slot.start,
+                "end": block.slot.end,
+                "hours": block.slot.duration(),
+                "gaps": block.gaps.iter().map(|gap| json!({ "start": gap.start, "end": gap.end })).collect::<Vec<_>>(),
+                "text": format!("{}{}", bullet, block.localized(CONFIG.language)),
+                "usually_booked_within": usually_booked_within,
+                "urgent": urgent,
+                "score": score(block),
+                "interest": interest(block),
+            })
+        })
+        .collect::<Vec<serde_json::Value>>();
+
+    let mut days: Vec<serde_json::Value> = Vec::new();
+    for block in &blocks {
+        let day = block.slot.start_time().date_naive();
+        let date = day.format(CONFIG.language.date_format()).to_string();
+        let text = format!("{}{}", bullet, block.localized_hours(CONFIG.language));
+        let slot = json!({
+            "start": block.slot.start,
+            "end": block.slot.end,
+            "text": text,
+            "urgent": urgent(block).1,
+            "interest": interest(block),
+        });
+        match days.iter_mut().find(|day| day["date"] == date) {
+            Some(day) => {
+                day["slots"].as_array_mut().unwrap().push(slot);
+                day["text"] = json!(format!("{}\n{}", day["text"].as_str().unwrap_or_default(), text));
+            }
+            None => {
+                let heading = CONFIG.language.day_heading(day);
+                days.push(json!({
+                    "date": date,
+                    "heading": heading,
+                    "slots": [slot],
+                    "text": format!("{}\n{}", heading, text),
+                }));
+            }
+        }
+    }
+
+    let heading = CONFIG.language.new_available_times();
+    let total_minutes = new_times.iter().map(Timeslot::duration_minutes).sum::<i64>();
+    let data = json!({
+        "heading": if profile.emoji { format!("🔔 {}", heading) } else { heading.to_owned() },
+        "resource": {
+            "id": resource.id,
+            "name": resource.name,
+            "names": resource.names,
+            "url": resource.booking_url(),
+        },
+        "slots": slots,
+        "days": days,
+        "emoji": profile.emoji,
+        "count": slots.len(),
+        "total_hours": hours(total_minutes),
+        "total_duration": CONFIG.language.duration(total_minutes),
+        "summary": CONFIG.language.new_times_summary(slots.len(), total_minutes),
+    });
+
+    // Messages are plain text or Markdown, so nothing should be HTML-escaped.
+    let mut handlebars = Handlebars::new();
+    if profile.markdown {
+        handlebars.register_escape_fn(escape_markdown);
+    } else {
+        handlebars.register_escape_fn(handlebars::no_escape);
+    }
+
+    let default_template = match (profile.grouping, profile.markdown) {
+        (Grouping::None, false) => DEFAULT_TEMPLATE,
+        (Grouping::None, true) => MARKDOWN_TEMPLATE,
+        (Grouping::Day, false) => DAY_TEMPLATE,
+        (Grouping::Day, true) => MARKDOWN_DAY_TEMPLATE,
+    };
+    let template = CONFIG.template.as_deref().unwrap_or(default_template);
+    let message = match handlebars.render_template(template, &data) {
+        Ok(message) => message,
+        Err(error) => {
+            tracing::warn!(%error, "Failed to render message template");
+            handlebars.render_template(default_template, &data).unwrap()
+        }
+    };
+    truncate(&message, profile.max_length, profile.markdown)
+}
+
+/// Escape the characters that have a special meaning in Telegram MarkdownV2.
+pub(crate) fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::new();
+    for character in text.chars() {
+        if "_*[]()~`>#+-=|{}.!\\".contains(character) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
+
+/// Remove the escapes added by escape_markdown, for sending a message Telegram couldn't parse as
+/// plain text instead.
+pub(crate) fn unescape_markdown(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut characters = text.chars();
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' => unescaped.extend(characters.next()),
+            _ => unescaped.push(character),
+        }
+    }
+    unescaped
+}
+
+/// Cut a message to at most `max_length` characters at the end of a line, ending it with "…".
+/// A `max_length` of 0 means no limit. In `markdown`, a line cut in the middle isn't cut between
+/// a backslash and the character it escapes.
+fn truncate(message: &str, max_length: usize, markdown: bool) -> String {
+    if max_length == 0 || message.chars().count() <= max_length {
+        return message.to_owned();
+    }
+
+    let mut truncated = String::new();
+    for line in message.lines() {
+        if truncated.chars().count() + line.chars().count() + 2 > max_length {
+            break;
+        }
+        truncated.push_str(line);
+        truncated.push('\n');
+    }
+    if truncated.is_empty() {
+        // Not even the first line fits, so cut it.
+        truncated = message.chars().take(max_length.saturating_sub(1)).collect();
+        let trailing_backslashes = truncated.chars().rev().take_while(|character| *character == '\\').count();
+        if markdown && trailing_backslashes % 2 == 1 {
+            truncated.pop();
+        }
+    }
+    truncated.push('…');
+    truncated
+}
+
+/// Minutes as hours for templates: a whole number if it is one, otherwise rounded to a tenth.
+fn hours(minutes: i64) -> serde_json::Value {
+    if minutes % 60 == 0 {
+        json!(minutes / 60)
+    } else {
+        json!((minutes as f64 / 6.0).round() / 10.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gives_total_hours_in_tenths() {
+        assert_eq!(hours(120), json!(2));
+        assert_eq!(hours(60), json!(1));
+        assert_eq!(hours(90), json!(1.5));
+        assert_eq!(hours(20), json!(0.3));
+    }
+
+    #[test]
+    fn truncates_at_lines_and_keeps_escapes_whole() {
+        assert_eq!(truncate("10:00\n12:00\n14:00\n", 13, false), "10:00\n12:00\n…");
+        assert_eq!(truncate("short", 0, false), "short");
+        // Cutting the only line after 5 characters would leave "a\\.b\\" with a dangling backslash.
+        let escaped = escape_markdown("a.b.c.d");
+        assert_eq!(truncate(&escaped, 6, true), "a\\.b…");
+        assert_eq!(truncate(&escaped, 6, false), "a\\.b\\…");
+    }
+
+    #[test]
+    fn unescapes_markdown_to_the_original_text() {
+        let text = "Fri 1.12. 10:00 - 12:00 (2 h) [book](https://varaamo.hel.fi)!";
+        assert_eq!(unescape_markdown(&escape_markdown(text)), text);
+        assert_eq!(unescape_markdown("back\\\\slash"), "back\\slash");
+    }
+}
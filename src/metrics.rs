@@ -0,0 +1,157 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::client;
+
+/// Number of run periods kept in the uptime history.
+const MAX_UPTIME_PERIODS: usize = 100;
+
+/// Prometheus Pushgateway settings, e.g.
+///
+/// ```toml
+/// [pushgateway]
+/// url = "http://localhost:9091"
+/// ```
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct PushgatewayConfig {
+    pub(crate) url: String,
+    pub(crate) job: String,
+}
+
+impl Default for PushgatewayConfig {
+    fn default() -> Self {
+        PushgatewayConfig {
+            url: "http://localhost:9091".to_owned(),
+            job: "laser_rust".to_owned(),
+        }
+    }
+}
+
+/// Lifetime totals, kept in the state so they survive restarts.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub(crate) struct Counters {
+    /// Successful fetches.
+    pub(crate) fetches: u64,
+    /// Fetches that failed after retries.
+    pub(crate) failed_fetches: u64,
+    /// Notifications about new times sent.
+    pub(crate) notifications: u64,
+    /// Cycles skipped because the previous one overran its time.
+    pub(crate) skipped_cycles: u64,
+    /// Number of times the bot has been started.
+    pub(crate) starts: u64,
+    /// Latest periods the bot has been running, oldest first.
+    pub(crate) uptime: Vec<UptimePeriod>,
+}
+
+/// A period the bot was running.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct UptimePeriod {
+    pub(crate) started_at: DateTime<Local>,
+    /// Start of the latest cycle of the run.
+    pub(crate) last_seen_at: DateTime<Local>,
+}
+
+impl Counters {
+    /// Record the start of a run.
+    pub(crate) fn start(&mut self, now: DateTime<Local>) {
+        self.starts += 1;
+        self.uptime.push(UptimePeriod {
+            started_at: now,
+            last_seen_at: now,
+        });
+        if self.uptime.len() > MAX_UPTIME_PERIODS {
+            self.uptime.remove(0);
+        }
+    }
+
+    /// Extend the current run up to `now`.
+    pub(crate) fn seen(&mut self, now: DateTime<Local>) {
+        if let Some(period) = self.uptime.last_mut() {
+            period.last_seen_at = now;
+        }
+    }
+
+    /// Total running time in the uptime history.
+    pub(crate) fn uptime_seconds(&self) -> i64 {
+        self.uptime
+            .iter()
+            .map(|period| (period.last_seen_at - period.started_at).num_seconds())
+            .sum()
+    }
+
+    /// Add the lifetime totals to metrics.
+    pub(crate) fn add_to(&self, metrics: &mut Metrics) {
+        metrics.counter("laser_fetches_total", "Successful fetches.", self.fetches as f64);
+        metrics.counter("laser_fetch_failures_total", "Fetches that failed after retries.", self.failed_fetches as f64);
+        metrics.counter("laser_notifications_total", "Notifications about new times sent.", self.notifications as f64);
+        metrics.counter(
+            "laser_skipped_cycles_total",
+            "Cycles skipped because the previous cycle overran the polling interval.",
+            self.skipped_cycles as f64,
+        );
+        metrics.counter("laser_starts_total", "Times the bot has been started.", self.starts as f64);
+        metrics.counter(
+            "laser_uptime_seconds_total",
+            "Running time over the latest runs.",
+            self.uptime_seconds() as f64,
+        );
+    }
+}
+
+struct Sample {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    value: f64,
+}
+
+/// Metrics collected during a run.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    samples: Vec<Sample>,
+}
+
+impl Metrics {
+    /// Record a gauge value. Names should follow Prometheus conventions, e.g. "laser_available_hours".
+    pub(crate) fn gauge(&mut self, name: &'static str, help: &'static str, value: f64) {
+        self.samples.push(Sample { name, help, kind: "gauge", value });
+    }
+
+    /// Record the current value of a counter. Counter names should end in "_total".
+    pub(crate) fn counter(&mut self, name: &'static str, help: &'static str, value: f64) {
+        self.samples.push(Sample { name, help, kind: "counter", value });
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub(crate) fn render(&self) -> String {
+        let mut text = String::new();
+        for sample in &self.samples {
+            text.push_str(&format!("# HELP {} {}\n", sample.name, sample.help));
+            text.push_str(&format!("# TYPE {} {}\n", sample.name, sample.kind));
+            text.push_str(&format!("{} {}\n", sample.name, sample.value));
+        }
+        text
+    }
+}
+
+/// Push metrics to the Pushgateway, replacing the previous values for this job and resource.
+/// Failures are logged, not fatal.
+pub(crate) fn push(config: &PushgatewayConfig, resource_id: &str, metrics: &Metrics) {
+    let url = format!(
+        "{}/metrics/job/{}/resource/{}",
+        config.url.trim_end_matches('/'),
+        config.job,
+        resource_id
+    );
+    let result = client::client()
+        .put(&url)
+        .body(metrics.render())
+        .send()
+        .and_then(|response| response.error_for_status());
+    if let Err(error) = result {
+        tracing::warn!(%error, "Failed to push metrics");
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::Local;
+use rumqttc::{Client, Event, MqttOptions, Outgoing, QoS};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::timeslot::Timeslot;
+
+/// MQTT broker settings, e.g.
+///
+/// ```toml
+/// [mqtt]
+/// host = "homeassistant.local"
+/// topic_prefix = "laser"
+/// ```
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct MqttConfig {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+    pub(crate) client_id: String,
+    pub(crate) topic_prefix: String,
+    /// Quality of service level (0, 1 or 2) used for all messages.
+    pub(crate) qos: u8,
+    /// Publish Home Assistant MQTT discovery payloads.
+    pub(crate) discovery: bool,
+    pub(crate) discovery_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            host: "localhost".to_owned(),
+            port: 1883,
+            username: None,
+            password: None,
+            client_id: "laser-rust".to_owned(),
+            topic_prefix: "laser".to_owned(),
+            qos: 1,
+            discovery: true,
+            discovery_prefix: "homeassistant".to_owned(),
+        }
+    }
+}
+
+/// Publish the current availability and any new times to the MQTT broker.
+///
+/// The availability is published as a retained message to `<prefix>/state` and
+/// new times as an event to `<prefix>/new_times`. Failures are logged, not fatal.
+pub(crate) fn publish(config: &MqttConfig, available_times: &[Timeslot], new_times: &[Timeslot]) {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        options.set_credentials(username, password);
+    }
+    let qos = match config.qos {
+        0 => QoS::AtMostOnce,
+        2 => QoS::ExactlyOnce,
+        _ => QoS::AtLeastOnce,
+    };
+
+    let state_topic = format!("{}/state", config.topic_prefix);
+    let mut messages: Vec<(String, bool, serde_json::Value)> = Vec::new();
+    if config.discovery {
+        messages.extend(discovery_messages(config, &state_topic));
+    }
+    messages.push((state_topic, true, state_payload(available_times)));
+    if !new_times.is_empty() {
+        let new_times_topic = format!("{}/new_times", config.topic_prefix);
+        messages.push((new_times_topic, false, json!({ "new_times": times_json(new_times) })));
+    }
+
+    let (client, mut connection) = Client::new(options, messages.len() + 1);
+    for (topic, retain, payload) in messages {
+        if let Err(error) = client.publish(topic, qos, retain, payload.to_string()) {
+            tracing::warn!(%error, "Failed to queue MQTT message");
+        }
+    }
+    client.disconnect().ok();
+
+    // Drive the connection until everything queued has been written to the broker.
+    for notification in connection.iter() {
+        match notification {
+            Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+            Ok(_) => {}
+            Err(error) => {
+                tracing::warn!(%error, "Failed to publish to MQTT broker");
+                break;
+            }
+        }
+    }
+}
+
+fn times_json(times: &[Timeslot]) -> Vec<serde_json::Value> {
+    times
+        .iter()
+        .map(|time| json!({ "start": time.start, "end": time.end, "hours": time.duration() }))
+        .collect()
+}
+
+/// Retained state document describing the current availability.
+fn state_payload(available_times: &[Timeslot]) -> serde_json::Value {
+    json!({
+        "available": !available_times.is_empty(),
+        "available_hours": available_times.iter().map(Timeslot::duration).sum::<i64>(),
+        "available_times": times_json(available_times),
+        "updated": Local::now().to_rfc3339(),
+    })
+}
+
+/// Home Assistant discovery payloads for an "available hours" sensor and
+/// an "available" binary sensor, both reading from the state topic.
+fn discovery_messages(config: &MqttConfig, state_topic: &str) -> Vec<(String, bool, serde_json::Value)> {
+    let device = json!({
+        "identifiers": [config.client_id],
+        "name": "Laser cutter availability",
+    });
+
+    let hours_sensor = json!({
+        "name": "Available hours",
+        "unique_id": format!("{}_available_hours", config.client_id),
+        "state_topic": state_topic,
+        "value_template": "{{ value_json.available_hours }}",
+        "unit_of_measurement": "h",
+        "json_attributes_topic": state_topic,
+        "json_attributes_template": "{{ {'available_times': value_json.available_times} | tojson }}",
+        "device": device,
+    });
+
+    let available_sensor = json!({
+        "name": "Available",
+        "unique_id": format!("{}_available", config.client_id),
+        "state_topic": state_topic,
+        "value_template": "{{ 'ON' if value_json.available else 'OFF' }}",
+        "device": device,
+    });
+
+    vec![
+        (
+            format!("{}/sensor/{}/available_hours/config", config.discovery_prefix, config.client_id),
+            true,
+            hours_sensor,
+        ),
+        (
+            format!("{}/binary_sensor/{}/available/config", config.discovery_prefix, config.client_id),
+            true,
+            available_sensor,
+        ),
+    ]
+}
@@ -0,0 +1,149 @@
+use clap::ValueEnum;
+use serde_json::json;
+
+use crate::respa::FoundResource;
+use crate::server::Snapshot;
+use crate::stats::{LeadTime, Report};
+use crate::timeslot::Timeslot;
+use crate::i18n::Language;
+use crate::CONFIG;
+
+/// Format of the results printed by `list` and `--once`.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// Human readable lines. `--once` prints nothing.
+    Text,
+    /// A JSON document, for jq and other tools.
+    Json,
+}
+
+/// File formats for the `export` command.
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum ExportFormat {
+    /// iCalendar, with an event for each available time.
+    Ics,
+}
+
+/// Print the results of a fetch to stdout. Logs go to stderr, so they don't mix with the output.
+pub(crate) fn print(snapshots: &[Snapshot], format: OutputFormat) {
+    match format {
+        OutputFormat::Text => {
+            for snapshot in snapshots {
+                println!("{}", snapshot.resource.name);
+                for time in &snapshot.available_times {
+                    let new = if snapshot.new_times.contains(time) { " (new)" } else { "" };
+                    println!("{}{}", time.localized(CONFIG.language), new);
+                }
+            }
+        }
+        OutputFormat::Json => println!("{}", document(snapshots)),
+    }
+}
+
+/// Print the statistics from the `stats` command.
+pub(crate) fn print_stats(report: &Report, format: OutputFormat) {
+    if format == OutputFormat::Json {
+        println!("{}", json!(report));
+        return;
+    }
+
+    let days = |count: usize| format!("{} {}", count, if count == 1 { "day" } else { "days" });
+    println!("Based on {} checks covering {}.", report.observations, days(report.days));
+    match report.average_daily_free_hours {
+        Some(hours) => println!("Average free hours per day: {:.1}", hours),
+        None => println!("Average free hours per day: no data yet"),
+    }
+    if !report.busiest_weekdays.is_empty() {
+        println!("Busiest weekdays:");
+        for weekday in &report.busiest_weekdays {
+            println!("  {} {:.0} % booked ({})", weekday.weekday, weekday.booked_percent, days(weekday.days));
+        }
+    }
+    match report.evening_time_to_book_minutes {
+        Some(minutes) => println!("Evening times are usually booked within {} minutes.", minutes),
+        None => println!("Not enough evening times booked yet to tell how quickly they go."),
+    }
+}
+
+/// Print the lead times from `stats lead-time`.
+pub(crate) fn print_lead_times(lead_times: &[LeadTime], format: OutputFormat) {
+    match format {
+        OutputFormat::Text => print!("{}", lead_times_text(lead_times, CONFIG.language)),
+        OutputFormat::Json => println!("{}", json!(lead_times)),
+    }
+}
+
+/// Lead times in the language as a line for each weekday and hour, e.g.
+/// "Sat 10:00 available 40 min, booked 52 h ahead (5 times)".
+pub(crate) fn lead_times_text(lead_times: &[LeadTime], language: Language) -> String {
+    if lead_times.is_empty() {
+        return format!("{}\n", language.not_enough_bookings());
+    }
+    lead_times.iter().map(|lead_time| format!("{}\n", language.lead_time(lead_time))).collect()
+}
+
+/// Print the resources found by the `search` command.
+pub(crate) fn print_search(resources: &[FoundResource], format: OutputFormat) {
+    match format {
+        OutputFormat::Text => print!("{}", search_text(resources)),
+        OutputFormat::Json => println!("{}", json!(resources)),
+    }
+}
+
+/// Found resources as a line each with the ID, name and unit, followed by the equipment, e.g.
+/// "axwzr3i57yba  Laser cutter, Oodi".
+pub(crate) fn search_text(resources: &[FoundResource]) -> String {
+    if resources.is_empty() {
+        return "No matching resources.\n".to_owned();
+    }
+    let mut text = String::new();
+    for resource in resources {
+        text.push_str(&format!("{}  {}", resource.id, resource.name));
+        if let Some(unit) = &resource.unit {
+            text.push_str(&format!(", {}", unit));
+        }
+        text.push('\n');
+        if !resource.equipment.is_empty() {
+            text.push_str(&format!("    {}\n", resource.equipment.join(", ")));
+        }
+    }
+    text
+}
+
+/// The results of a fetch as JSON, e.g.
+///
+/// ```json
+/// {
+///   "fetched_at": "2024-08-01T12:00:00+03:00",
+///   "resources": [{
+///     "id": "axwzr3i57yba", "name": "Laserleikkuri", "url": "https://varaamo.hel.fi/resources/axwzr3i57yba",
+///     "available": [{"start": "2024-08-02T10:00:00+03:00", "end": "2024-08-02T12:00:00+03:00", "hours": 2}],
+///     "new": []
+///   }]
+/// }
+/// ```
+fn document(snapshots: &[Snapshot]) -> serde_json::Value {
+    let slots = |times: &[Timeslot]| {
+        times
+            .iter()
+            .map(|time| json!({ "start": time.start, "end": time.end, "hours": time.duration() }))
+            .collect::<Vec<serde_json::Value>>()
+    };
+
+    let resources = snapshots
+        .iter()
+        .map(|snapshot| {
+            json!({
+                "id": snapshot.resource.id,
+                "name": snapshot.resource.name,
+                "url": snapshot.resource.booking_url(),
+                "available": slots(&snapshot.available_times),
+                "new": slots(&snapshot.new_times),
+            })
+        })
+        .collect::<Vec<serde_json::Value>>();
+    json!({
+        "fetched_at": snapshots.iter().map(|snapshot| snapshot.fetched_at).max().map(|time| time.to_rfc3339()),
+        "resources": resources,
+    })
+}
@@ -0,0 +1,28 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+/// Settings changed at runtime with /set, replacing the ones in the config file.
+/// They are saved in the state, so they survive restarts.
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(default)]
+pub(crate) struct Overrides {
+    /// Seconds between fetches of the monitored resource.
+    pub(crate) poll_interval_secs: Option<u64>,
+    /// Number of days ahead to fetch availability for.
+    pub(crate) lookahead_days: Option<u64>,
+    /// Shortest new time notified about, in minutes.
+    pub(crate) min_duration_minutes: Option<i64>,
+}
+
+/// Overrides in effect, for the code that doesn't have the state at hand.
+static CURRENT: Lazy<RwLock<Overrides>> = Lazy::new(Default::default);
+
+pub(crate) fn current() -> Overrides {
+    *CURRENT.read().unwrap()
+}
+
+/// Put overrides in effect, e.g. the ones saved in the state on startup.
+pub(crate) fn apply(overrides: Overrides) {
+    *CURRENT.write().unwrap() = overrides;
+}
@@ -0,0 +1,113 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::booking::BookingError;
+use crate::respa;
+use crate::state::State;
+use crate::timeslot::Timeslot;
+use crate::timezone;
+use crate::CONFIG;
+
+/// A reservation of the monitored resource made by the user of the API token.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct OwnReservation {
+    pub(crate) id: String,
+    pub(crate) time: Timeslot,
+    /// Reminders sent about the reservation, by minutes before its start as configured.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) reminded: Vec<i64>,
+}
+
+/// Fetch the upcoming reservations of a resource made by the user of the API token.
+/// Reservations the library has cancelled or denied are left out.
+pub(crate) fn fetch(resource_id: &str) -> Result<Vec<OwnReservation>, BookingError> {
+    fetch_since(resource_id, timezone::now())
+}
+
+/// Fetch the reservations of a resource made by the user of the API token that end after `since`,
+/// leaving out ones the library has cancelled or denied.
+pub(crate) fn fetch_since(resource_id: &str, since: DateTime<Tz>) -> Result<Vec<OwnReservation>, BookingError> {
+    if CONFIG.auth.api_token.is_empty() {
+        return Err(BookingError::NoApiToken);
+    }
+    let reservations = respa::fetch_own_reservations(resource_id, &CONFIG.auth.api_token, since).map_err(BookingError::Api)?;
+    let mut own = reservations
+        .iter()
+        .filter(|reservation| !matches!(reservation["state"].as_str(), Some("cancelled" | "denied")))
+        .filter_map(|reservation| {
+            let id = match &reservation["id"] {
+                serde_json::Value::String(id) => id.clone(),
+                serde_json::Value::Number(id) => id.to_string(),
+                _ => return None,
+            };
+            Some(OwnReservation {
+                id,
+                time: Timeslot {
+                    start: reservation["begin"].as_str()?.to_owned(),
+                    end: reservation["end"].as_str()?.to_owned(),
+                },
+                reminded: Vec::new(),
+            })
+        })
+        .collect::<Vec<OwnReservation>>();
+    own.sort_by_key(|reservation| reservation.time.start_time());
+    Ok(own)
+}
+
+/// Refresh the saved own reservations of a resource. Returns messages about reservations that
+/// were cancelled or moved since the previous check, and reminders of ones starting soon.
+///
+/// Does nothing without an API token. If fetching fails, the saved reservations are kept.
+pub(crate) fn update(state: &mut State, resource_id: &str) -> Vec<String> {
+    if CONFIG.auth.api_token.is_empty() {
+        return Vec::new();
+    }
+    let mut current = match fetch(resource_id) {
+        Ok(current) => current,
+        Err(error) => {
+            tracing::warn!(%error, "Failed to fetch own reservations");
+            return Vec::new();
+        }
+    };
+
+    let now = timezone::now();
+    let language = CONFIG.language;
+    let mut messages = Vec::new();
+    for previous in state.own_reservations.iter().filter(|previous| previous.time.end_time() > now) {
+        match current.iter_mut().find(|reservation| reservation.id == previous.id) {
+            None => {
+                tracing::info!(id = %previous.id, time = %previous.time, "Own reservation is gone");
+                messages.push(language.reservation_cancelled(&previous.time.localized(language)));
+            }
+            Some(reservation) if reservation.time != previous.time => {
+                tracing::info!(id = %previous.id, from = %previous.time, to = %reservation.time, "Own reservation was moved");
+                messages.push(language.reservation_modified(
+                    &previous.time.localized(language),
+                    &reservation.time.localized(language),
+                ));
+            }
+            Some(reservation) => reservation.reminded = previous.reminded.clone(),
+        }
+    }
+
+    // Reminders that are due together are sent as one, telling the actual time left.
+    for reservation in &mut current {
+        let start = reservation.time.start_time();
+        let due = CONFIG
+            .reminder_minutes
+            .iter()
+            .filter(|minutes| !reservation.reminded.contains(minutes))
+            .filter(|minutes| now >= start - chrono::Duration::minutes(**minutes) && now < start)
+            .copied()
+            .collect::<Vec<i64>>();
+        if !due.is_empty() {
+            let minutes = (start - now).num_minutes();
+            messages.push(language.reservation_starts_in(&reservation.time.localized(language), minutes));
+            reservation.reminded.extend(due);
+        }
+    }
+
+    state.own_reservations = current;
+    messages
+}
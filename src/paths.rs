@@ -0,0 +1,95 @@
+use directories::ProjectDirs;
+use once_cell::sync::OnceCell;
+use std::path::{Path, PathBuf};
+
+/// Name of the config file.
+const CONFIG_FILE: &str = "laser.toml";
+
+/// Name of the state file, used to recognize a working directory with the state of an earlier version.
+const LEGACY_STATE_FILE: &str = "state.json";
+
+/// Directory of the state files, decided on first use unless given with --state-dir.
+static STATE_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "laser")
+}
+
+/// Keep the state in `dir`, e.g. from --state-dir. Must be called before the state is used.
+pub(crate) fn set_state_dir(dir: PathBuf) {
+    STATE_DIR.set(dir).ok();
+}
+
+/// Directory of the state files: the one given with --state-dir, or the platform's directory for
+/// application state, e.g. `$XDG_STATE_HOME/laser` on Linux. A working directory with a state
+/// file from an earlier version is used as it is, so upgrading doesn't lose the state.
+pub(crate) fn state_dir() -> &'static Path {
+    STATE_DIR.get_or_init(|| state_dir_in(Path::new("."), project_dirs().as_ref()))
+}
+
+fn state_dir_in(working_dir: &Path, dirs: Option<&ProjectDirs>) -> PathBuf {
+    if working_dir.join(LEGACY_STATE_FILE).exists() {
+        tracing::info!("Using the state in the working directory. Move it to the default state directory or use --state-dir.");
+        return working_dir.to_owned();
+    }
+    dirs.map(|dirs| dirs.state_dir().unwrap_or_else(|| dirs.data_local_dir()).to_owned())
+        .unwrap_or_else(|| working_dir.to_owned())
+}
+
+/// Path of a file in the state directory.
+pub(crate) fn state_file(name: &str) -> PathBuf {
+    state_dir().join(name)
+}
+
+/// Default path of the config file: laser.toml in the working directory if there is one, and
+/// otherwise in the platform's config directory, e.g. `$XDG_CONFIG_HOME/laser/laser.toml`.
+pub(crate) fn default_config_file() -> PathBuf {
+    config_file_in(Path::new(""), project_dirs().as_ref())
+}
+
+fn config_file_in(working_dir: &Path, dirs: Option<&ProjectDirs>) -> PathBuf {
+    let local = working_dir.join(CONFIG_FILE);
+    if local.exists() {
+        return local;
+    }
+    dirs.map(|dirs| dirs.config_dir().join(CONFIG_FILE)).unwrap_or(local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for a test, removed first if an earlier run left it behind.
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("laser-rust-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn keeps_state_where_an_earlier_version_left_it() {
+        let dir = empty_dir("state-dir");
+        let dirs = project_dirs();
+        if let Some(dirs) = &dirs {
+            assert_ne!(state_dir_in(&dir, Some(dirs)), dir);
+        }
+        assert_eq!(state_dir_in(&dir, None), dir);
+        std::fs::write(dir.join(LEGACY_STATE_FILE), "{}").unwrap();
+        assert_eq!(state_dir_in(&dir, dirs.as_ref()), dir);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn prefers_config_file_in_working_directory() {
+        let dir = empty_dir("config-file");
+        let dirs = project_dirs();
+        if let Some(dirs) = &dirs {
+            assert_eq!(config_file_in(&dir, Some(dirs)), dirs.config_dir().join(CONFIG_FILE));
+        }
+        assert_eq!(config_file_in(&dir, None), dir.join(CONFIG_FILE));
+        std::fs::write(dir.join(CONFIG_FILE), "").unwrap();
+        assert_eq!(config_file_in(&dir, dirs.as_ref()), dir.join(CONFIG_FILE));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
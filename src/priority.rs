@@ -0,0 +1,87 @@
+use chrono::{NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::holidays;
+use crate::i18n::Language;
+use crate::timeslot::Timeslot;
+use crate::timezone;
+
+/// Times a chat wants to hear about with sound, set with "/priority sat,sun 10:00-18:00".
+/// Notifications about other times arrive silently. Without weekdays every day matches.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct PriorityWindow {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) weekdays: Vec<Weekday>,
+    pub(crate) from: NaiveTime,
+    pub(crate) to: NaiveTime,
+}
+
+impl PriorityWindow {
+    /// Parse the arguments of /priority, optional comma-separated weekdays and a time range,
+    /// e.g. `["sat,sun", "10:00-18:00"]` or `["17:00-21:00"]`.
+    pub(crate) fn parse(arguments: &[&str]) -> Option<PriorityWindow> {
+        let (weekdays, range) = match arguments {
+            [range] => (Vec::new(), range),
+            [weekdays, range] => {
+                let weekdays = weekdays.split(',').map(|weekday| weekday.parse::<Weekday>().ok()).collect::<Option<Vec<_>>>()?;
+                (weekdays, range)
+            }
+            _ => return None,
+        };
+        let (from, to) = range.split_once('-')?;
+        let window = PriorityWindow {
+            weekdays,
+            from: NaiveTime::parse_from_str(from, "%H:%M").ok()?,
+            to: NaiveTime::parse_from_str(to, "%H:%M").ok()?,
+        };
+        (window.from < window.to).then_some(window)
+    }
+
+    /// Return true if any part of `time` is within the window.
+    pub(crate) fn overlaps(&self, time: &Timeslot) -> bool {
+        let date = time.start_time().date_naive();
+        if !holidays::matches(&self.weekdays, date) {
+            return false;
+        }
+        time.start_time() < timezone::at(date, self.to) && timezone::at(date, self.from) < time.end_time()
+    }
+
+    /// Format the window for display in the language, e.g. "Sat, Sun 10:00 - 18:00".
+    pub(crate) fn localized(&self, language: Language) -> String {
+        let range = format!("{} - {}", self.from.format("%H:%M"), self.to.format("%H:%M"));
+        if self.weekdays.is_empty() {
+            return range;
+        }
+        let weekdays = self.weekdays.iter().map(|weekday| language.weekday_short(*weekday)).collect::<Vec<&str>>();
+        format!("{} {}", weekdays.join(", "), range)
+    }
+}
+
+/// Return true if a notification about the times should arrive silently: the chat has priority
+/// windows and none of the times is within them.
+pub(crate) fn is_silent(windows: &[PriorityWindow], times: &[Timeslot]) -> bool {
+    !windows.is_empty() && !times.iter().any(|time| windows.iter().any(|window| window.overlaps(time)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rings_only_for_times_in_priority_windows() {
+        let windows = [PriorityWindow::parse(&["sat,sun", "10:00-18:00"]).unwrap()];
+        let time = |start: &str, end: &str| Timeslot {
+            start: start.to_owned(),
+            end: end.to_owned(),
+        };
+        // 2024-05-04 is a Saturday and 2024-05-07 a Tuesday.
+        let saturday = time("2024-05-04T17:00:00+03:00", "2024-05-04T19:00:00+03:00");
+        let tuesday = time("2024-05-07T12:00:00+03:00", "2024-05-07T14:00:00+03:00");
+        assert!(!is_silent(&windows, &[tuesday.clone(), saturday]));
+        assert!(is_silent(&windows, std::slice::from_ref(&tuesday)));
+        assert!(!is_silent(&[], &[tuesday]));
+        assert!(PriorityWindow::parse(&["someday", "10:00-18:00"]).is_none());
+        assert_eq!(windows[0].localized(Language::En), "Sat, Sun 10:00 - 18:00");
+        assert_eq!(windows[0].localized(Language::Fi), "la, su 10:00 - 18:00");
+    }
+}
@@ -0,0 +1,588 @@
+use chrono::{DateTime, Days};
+use chrono_tz::Tz;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::client;
+use crate::message::Resource;
+use crate::timeslot::Timeslot;
+use crate::timezone;
+use crate::CONFIG;
+
+
+/// Number of times a fetch failing with a temporary error is retried.
+const MAX_FETCH_RETRIES: u32 = 2;
+
+/// Wait before the first retry. Each further retry waits one step longer.
+const RETRY_DELAY: Duration = if cfg!(test) { Duration::ZERO } else { Duration::from_secs(5) };
+
+/// The latest response for each resource, by resource ID. The next request for the same URL is
+/// made conditional, and if the API answers 304 Not Modified, the cached data is used instead of
+/// downloading and parsing it again.
+static CACHE: Lazy<Mutex<HashMap<String, CachedResponse>>> = Lazy::new(Default::default);
+
+struct CachedResponse {
+    url: String,
+    validators: Validators,
+    data: serde_json::Value,
+}
+
+/// ETag and Last-Modified headers of a response, sent back as If-None-Match and
+/// If-Modified-Since to ask for the data only if it has changed.
+#[derive(Clone, Default)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &reqwest::header::HeaderMap) -> Validators {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_owned);
+        Validators {
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+        }
+    }
+}
+
+/// Reasons fetching data from respa can fail.
+#[derive(Debug)]
+pub(crate) enum FetchError {
+    /// The API could not be reached.
+    Network(reqwest::Error),
+    /// The API did not respond in time.
+    Timeout,
+    /// The API rejected the request, e.g. for an unknown resource or a bad date range.
+    Api { status: u16, detail: String },
+    /// The requested resource does not exist.
+    ResourceNotFound { resource_id: String },
+    /// The response was not valid JSON.
+    InvalidResponse(serde_json::Error),
+    /// The response is missing a field, e.g. because it was cut short.
+    IncompleteResponse(&'static str),
+    /// A recorded response could not be read. See source::FileSource.
+    Replay(String),
+}
+
+impl FetchError {
+    /// Return true if fetching may succeed if tried again shortly,
+    /// e.g. when the API is unreachable, overloaded or rate limiting.
+    pub(crate) fn is_temporary(&self) -> bool {
+        match self {
+            FetchError::Network(_) | FetchError::Timeout => true,
+            FetchError::Api { status, .. } => *status == 429 || *status >= 500,
+            FetchError::ResourceNotFound { .. }
+            | FetchError::InvalidResponse(_)
+            | FetchError::IncompleteResponse(_)
+            | FetchError::Replay(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Network(error) => write!(f, "respa API could not be reached: {}", error),
+            FetchError::Timeout => write!(f, "respa API did not respond in time"),
+            FetchError::Api { status, detail } => write!(f, "respa API returned {}: {}", status, detail),
+            // A mistyped ID says nothing about the resource, so finding the right one is left to a search by name.
+            FetchError::ResourceNotFound { resource_id } => {
+                write!(f, "resource '{}' not found, find its ID with `laser-rust search <name>`", resource_id)
+            }
+            FetchError::InvalidResponse(error) => write!(f, "respa API returned invalid JSON: {}", error),
+            FetchError::IncompleteResponse(field) => write!(f, "respa API response has no {}", field),
+            FetchError::Replay(error) => write!(f, "recorded response could not be read: {}", error),
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            return FetchError::Timeout;
+        }
+        FetchError::Network(error)
+    }
+}
+
+/// Base URL of the respa API, without a trailing slash.
+fn api_url() -> &'static str {
+    CONFIG.respa_url.trim_end_matches('/')
+}
+
+/// Fetch a resource with its opening hours and reservations from api.hel.fi/respa as a JSON object.
+/// Returns an error if the API request fails, the API returns an error or the JSON parsing fails.
+///
+/// Requests failing with a temporary error are retried up to MAX_FETCH_RETRIES times.
+pub(crate) fn fetch_resource_data(resource_id: &str) -> Result<serde_json::Value, FetchError> {
+    let mut retries = 0;
+    loop {
+        match fetch_api_data_once(resource_id) {
+            Err(error) if error.is_temporary() && retries < MAX_FETCH_RETRIES => {
+                retries += 1;
+                tracing::warn!(%error, retries, "Fetching API data failed, retrying");
+                std::thread::sleep(RETRY_DELAY * retries);
+            }
+            result => return result,
+        }
+    }
+}
+
+fn fetch_api_data_once(resource_id: &str) -> Result<serde_json::Value, FetchError> {
+    let current_time = timezone::now();
+    let start_date = current_time.format("%Y-%m-%d").to_string();
+    let end_date = (current_time.checked_add_days(Days::new(CONFIG.lookahead_days()))).unwrap().format("%Y-%m-%d").to_string();
+
+    // Append "T23:59:59" to end_date to get all reservations for the day.
+    let end_date = format!("{}T23:59:59", end_date);
+
+    let request_url = format!("{}/resource/{}/?start={}&end={}&format=json", api_url(), resource_id, start_date, end_date);
+
+    tracing::info!(url = %request_url, "Fetching data");
+
+    let cached_validators = CACHE
+        .lock()
+        .unwrap()
+        .get(resource_id)
+        .filter(|cached| cached.url == request_url)
+        .map(|cached| cached.validators.clone())
+        .unwrap_or_default();
+    let (status, validators, api_response) = get(&request_url, &cached_validators)?;
+
+    if status == reqwest::StatusCode::NOT_MODIFIED {
+        let cache = CACHE.lock().unwrap();
+        let cached = cache.get(resource_id).filter(|cached| cached.url == request_url);
+        return match cached {
+            Some(cached) => {
+                tracing::debug!("Not modified, using the cached response");
+                Ok(cached.data.clone())
+            }
+            None => Err(FetchError::Api {
+                status: status.as_u16(),
+                detail: "Not modified, but no response is cached".to_owned(),
+            }),
+        };
+    }
+    if status == reqwest::StatusCode::NOT_FOUND {
+        return Err(FetchError::ResourceNotFound {
+            resource_id: resource_id.to_owned(),
+        });
+    }
+    if !status.is_success() {
+        return Err(FetchError::Api {
+            status: status.as_u16(),
+            detail: parse_error_detail(&api_response),
+        });
+    }
+
+    let api_data: serde_json::Value = serde_json::from_str(&api_response).map_err(FetchError::InvalidResponse)?;
+    check_complete(&api_data)?;
+
+    let cached = CachedResponse {
+        url: request_url,
+        validators,
+        data: api_data.clone(),
+    };
+    CACHE.lock().unwrap().insert(resource_id.to_owned(), cached);
+    Ok(api_data)
+}
+
+/// Make a GET request and return the response status, validators and body. The request is
+/// conditional if `validators` has any.
+/// With fault injection enabled, a scheduled fault is returned instead, if any.
+fn get(url: &str, validators: &Validators) -> Result<(reqwest::StatusCode, Validators, String), FetchError> {
+    #[cfg(any(test, feature = "fault-injection"))]
+    if let Some(fault) = crate::fault::next() {
+        tracing::warn!(?fault, "Injecting fault");
+        let (status, body) = fault.response()?;
+        return Ok((status, Validators::default(), body));
+    }
+
+    let mut request = client::client().get(url);
+    if let Some(etag) = &validators.etag {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &validators.last_modified {
+        request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+    }
+    let response = request.send()?;
+    let status = response.status();
+    let validators = Validators::from_headers(response.headers());
+    Ok((status, validators, response.text()?))
+}
+
+/// Check that resource data has the opening hours and reservations. A response without them
+/// would look like the resource was closed and fully booked.
+pub(crate) fn check_complete(api_data: &serde_json::Value) -> Result<(), FetchError> {
+    for field in ["opening_hours", "reservations"] {
+        if !api_data[field].is_array() {
+            return Err(FetchError::IncompleteResponse(field));
+        }
+    }
+    Ok(())
+}
+
+/// Most pages of a paginated list that are fetched, in case the API keeps linking to more.
+const MAX_PAGES: usize = 50;
+
+/// Fetch a list from the API, e.g. /reservation/, and return the results of all its pages.
+/// Results come `page_size` per page, and the `next` link of each page is followed until the last.
+/// With `api_token`, the requests are made as its user.
+fn get_list(path: &str, query: &[(&str, &str)], api_token: Option<&str>) -> Result<Vec<serde_json::Value>, FetchError> {
+    let client = client::client();
+    let page_size = CONFIG.page_size.to_string();
+    let mut request = client
+        .get(format!("{}{}", api_url(), path))
+        .query(query)
+        .query(&[("page_size", page_size.as_str()), ("format", "json")]);
+    let mut results = Vec::new();
+    for page in 1.. {
+        if let Some(api_token) = api_token {
+            request = request.header(reqwest::header::AUTHORIZATION, format!("Bearer {}", api_token));
+        }
+        let response = request.send()?;
+        let status = response.status();
+        let text = response.text()?;
+        if !status.is_success() {
+            return Err(FetchError::Api {
+                status: status.as_u16(),
+                detail: parse_error_detail(&text),
+            });
+        }
+
+        let list: serde_json::Value = serde_json::from_str(&text).map_err(FetchError::InvalidResponse)?;
+        results.extend(list["results"].as_array().ok_or(FetchError::IncompleteResponse("results"))?.iter().cloned());
+        let Some(next) = list["next"].as_str() else {
+            break;
+        };
+        if page == MAX_PAGES {
+            tracing::warn!(path, count = results.len(), "List has more than {} pages, ignoring the rest", MAX_PAGES);
+            break;
+        }
+        tracing::debug!(path, page = page + 1, "Fetching next page");
+        request = client.get(next);
+    }
+    Ok(results)
+}
+
+/// A reservation made through the API.
+pub(crate) struct Reservation {
+    pub(crate) id: String,
+    /// E.g. "confirmed", or "requested" for resources whose reservations need approval.
+    pub(crate) state: String,
+    /// Code for the door or the machine, for resources that have one.
+    pub(crate) access_code: Option<String>,
+}
+
+/// Reserve a time of a resource as the user of the configured API token.
+/// Returns the new reservation. Failed reservations are not retried.
+pub(crate) fn create_reservation(resource_id: &str, time: &Timeslot, api_token: &str) -> Result<Reservation, FetchError> {
+    let body = serde_json::json!({
+        "resource": resource_id,
+        "begin": time.start,
+        "end": time.end,
+    });
+    tracing::info!(resource_id, %time, "Creating reservation");
+    let response = client::client()
+        .post(format!("{}/reservation/", api_url()))
+        .query(&[("format", "json")])
+        .header(reqwest::header::AUTHORIZATION, format!("Bearer {}", api_token))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()?;
+    let status = response.status();
+    let text = response.text()?;
+    if !status.is_success() {
+        return Err(FetchError::Api {
+            status: status.as_u16(),
+            detail: parse_error_detail(&text),
+        });
+    }
+    parse_reservation(&serde_json::from_str(&text).map_err(FetchError::InvalidResponse)?)
+}
+
+/// Parse a reservation object. The ID may be a string or a number, and the access code is
+/// missing or empty for resources without one.
+fn parse_reservation(reservation: &serde_json::Value) -> Result<Reservation, FetchError> {
+    let id = match &reservation["id"] {
+        serde_json::Value::String(id) => id.clone(),
+        serde_json::Value::Number(id) => id.to_string(),
+        _ => return Err(FetchError::IncompleteResponse("id")),
+    };
+    Ok(Reservation {
+        id,
+        state: reservation["state"].as_str().unwrap_or("confirmed").to_owned(),
+        access_code: reservation["access_code"].as_str().filter(|code| !code.is_empty()).map(str::to_owned),
+    })
+}
+
+/// Fetch the reservations of a resource made by the user of the API token that end after `since`,
+/// including ones the library has cancelled or denied. Returns the raw reservation objects.
+pub(crate) fn fetch_own_reservations(resource_id: &str, api_token: &str, since: DateTime<Tz>) -> Result<Vec<serde_json::Value>, FetchError> {
+    let start = since.to_rfc3339();
+    get_list(
+        "/reservation/",
+        &[("resource", resource_id), ("is_own", "true"), ("start", &start)],
+        Some(api_token),
+    )
+}
+
+/// A venue where resources are located.
+pub(crate) struct Unit {
+    pub(crate) name: String,
+    pub(crate) address: String,
+    pub(crate) latitude: f64,
+    pub(crate) longitude: f64,
+}
+
+/// Fetch the name, address and coordinates of a unit.
+/// Returns None if the unit can't be fetched or has no coordinates.
+pub(crate) fn fetch_unit(unit_id: &str) -> Option<Unit> {
+    let response = client::client()
+        .get(format!("{}/unit/{}/", api_url(), unit_id))
+        .query(&[("format", "json")])
+        .send()
+        .and_then(|response| response.text());
+    let unit: serde_json::Value = match response {
+        Ok(body) => serde_json::from_str(&body).ok()?,
+        Err(error) => {
+            tracing::warn!(%error, unit_id, "Failed to fetch unit");
+            return None;
+        }
+    };
+
+    // Locations are GeoJSON points, with the longitude first.
+    let coordinates = unit["location"]["coordinates"].as_array()?;
+    let address = [
+        CONFIG.language.pick(&unit["street_address"]),
+        unit["address_zip"].as_str().map(str::to_owned),
+        CONFIG.language.pick(&unit["municipality"]).or(unit["municipality"].as_str().map(str::to_owned)),
+    ];
+    Some(Unit {
+        name: CONFIG.language.pick(&unit["name"]).unwrap_or_else(|| unit_id.to_owned()),
+        address: address.into_iter().flatten().collect::<Vec<String>>().join(", "),
+        latitude: coordinates.get(1)?.as_f64()?,
+        longitude: coordinates.first()?.as_f64()?,
+    })
+}
+
+/// Parse the error message from a respa error response.
+///
+/// Errors are either `{"detail": "Not found."}` or per-field messages like
+/// `{"end": ["End time must be after start time."]}`. Other bodies are returned as is.
+fn parse_error_detail(body: &str) -> String {
+    let Ok(serde_json::Value::Object(fields)) = serde_json::from_str::<serde_json::Value>(body) else {
+        return body.chars().take(200).collect();
+    };
+
+    let mut messages: Vec<String> = Vec::new();
+    for (field, value) in &fields {
+        let text = match value {
+            serde_json::Value::Array(items) => {
+                items.iter().filter_map(|item| item.as_str()).collect::<Vec<&str>>().join(" ")
+            }
+            serde_json::Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        if field == "detail" {
+            messages.push(text);
+        } else {
+            messages.push(format!("{}: {}", field, text));
+        }
+    }
+
+    messages.join("; ")
+}
+
+/// A resource found by search.
+#[derive(Serialize)]
+pub(crate) struct FoundResource {
+    pub(crate) id: String,
+    /// Name in the configured language, or the ID.
+    pub(crate) name: String,
+    /// Name of the venue, or its ID if the name isn't known.
+    pub(crate) unit: Option<String>,
+    /// Name of the type of the resource, e.g. "Työpiste".
+    #[serde(rename = "type")]
+    pub(crate) resource_type: Option<String>,
+    pub(crate) equipment: Vec<String>,
+}
+
+/// Search respa for up to `limit` resources matching `query` by name or description, followed by
+/// resources of units matching by name from the search endpoint.
+pub(crate) fn search(query: &str, limit: usize) -> Result<Vec<FoundResource>, FetchError> {
+    let response = client::client()
+        .get(format!("{}/resource/", api_url()))
+        .query(&[
+            ("search", query),
+            ("page_size", &limit.to_string()),
+            ("include", "unit_detail"),
+            ("format", "json"),
+        ])
+        .send()?;
+    let status = response.status();
+    let text = response.text()?;
+    if !status.is_success() {
+        return Err(FetchError::Api {
+            status: status.as_u16(),
+            detail: parse_error_detail(&text),
+        });
+    }
+    let results: serde_json::Value = serde_json::from_str(&text).map_err(FetchError::InvalidResponse)?;
+    let mut found = results["results"]
+        .as_array()
+        .ok_or(FetchError::IncompleteResponse("results"))?
+        .iter()
+        .filter_map(parse_found_resource)
+        .collect::<Vec<FoundResource>>();
+
+    // The search endpoint finds resources by the name of their unit too. It's only a supplement,
+    // so failures are ignored.
+    let response = client::client()
+        .get(format!("{}/search/", api_url()))
+        .query(&[("search", query), ("format", "json")])
+        .send()
+        .and_then(|response| response.text());
+    if let Ok(text) = response {
+        let results: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+        for resource in results["resource"].as_array().unwrap_or(&Vec::new()) {
+            let Some(resource) = parse_found_resource(resource) else {
+                continue;
+            };
+            if found.len() < limit && !found.iter().any(|other| other.id == resource.id) {
+                found.push(resource);
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// List the reservable resources of a unit.
+pub(crate) fn unit_resources(unit_id: &str) -> Result<Vec<FoundResource>, FetchError> {
+    let resources = get_list("/resource/", &[("unit", unit_id), ("reservable", "true")], None)?;
+    Ok(resources.iter().filter_map(parse_found_resource).collect())
+}
+
+fn parse_found_resource(resource: &serde_json::Value) -> Option<FoundResource> {
+    let id = resource["id"].as_str()?;
+    let unit = match &resource["unit"] {
+        serde_json::Value::String(unit_id) => Some(unit_id.clone()),
+        unit @ serde_json::Value::Object(_) => CONFIG.language.pick(&unit["name"]).or(unit["id"].as_str().map(str::to_owned)),
+        _ => None,
+    };
+    // Equipment is listed either directly or wrapped in an alias object.
+    let equipment = resource["equipment"]
+        .as_array()
+        .unwrap_or(&Vec::new())
+        .iter()
+        .filter_map(|item| CONFIG.language.pick(&item["name"]).or_else(|| CONFIG.language.pick(&item["equipment"]["name"])))
+        .collect();
+    Some(FoundResource {
+        id: id.to_owned(),
+        name: CONFIG.language.pick(&resource["name"]).unwrap_or_else(|| id.to_owned()),
+        unit,
+        resource_type: CONFIG.language.pick(&resource["type"]["name"]),
+        equipment,
+    })
+}
+
+/// Parse the name of a resource from API data.
+/// The name is picked in the configured language, falling back to the resource ID if it is missing.
+pub(crate) fn parse_resource(resource_id: &str, api_data: &serde_json::Value) -> Resource {
+    Resource {
+        id: resource_id.to_owned(),
+        name: CONFIG.language.pick(&api_data["name"]).unwrap_or_else(|| resource_id.to_owned()),
+        names: api_data["name"].clone(),
+        unit: api_data["unit"].as_str().map(str::to_owned),
+    }
+}
+
+/// Parse how many days in advance the resource can be reserved, if the resource limits it.
+pub(crate) fn parse_max_days_in_advance(api_data: &serde_json::Value) -> Option<u64> {
+    api_data["reservable_max_days_in_advance"].as_u64()
+}
+
+/// Parse all opening times from API data. Return a vector of Timeslot structs.
+/// Returns an empty vector on error.
+pub(crate) fn parse_opening_times(api_data: &serde_json::Value) -> Vec<Timeslot> {   
+    // Get opening hours from API data. If opening hours is null, return an empty vector.
+    let binding = Vec::new();
+    let opening_hours = api_data["opening_hours"].as_array().unwrap_or(&binding);
+        
+    // Create a Vec<Timeslot> from opening hours
+    let mut opening_times: Vec<Timeslot> = Vec::new();
+    for opening_hour in opening_hours {
+        // Skip opening hours that are null
+        if opening_hour["opens"].is_null() || opening_hour["closes"].is_null() {
+            continue;
+        }
+
+        // Create Timeslot from opening hour
+        let timeslot = Timeslot { 
+            start: opening_hour["opens"].as_str().unwrap().to_owned(), 
+            end: opening_hour["closes"].as_str().unwrap().to_owned() 
+        };
+
+        // Add Timeslot to opening times
+        opening_times.push(timeslot);
+    }
+
+    opening_times
+}
+
+/// Parse all reservations times from API data. Return a vector of Timeslot structs.
+/// Returns an empty vector on error.
+pub(crate) fn parse_reservations(api_data: &serde_json::Value) -> Vec<Timeslot> {
+    // Get reservations from API data
+    let binding = Vec::new();
+    let reservations = api_data["reservations"].as_array().unwrap_or(&binding);
+
+    // Create a Vec<Timeslot> from reservations
+    let mut reservation_times: Vec<Timeslot> = Vec::new();
+    for reservation in reservations {
+        // Skip reservations that are null
+        if reservation["begin"].is_null() || reservation["end"].is_null() {
+            continue;
+        }
+
+        // Create Timeslot from reservation
+        let timeslot = Timeslot { 
+            start: reservation["begin"].as_str().unwrap().to_owned(), 
+            end: reservation["end"].as_str().unwrap().to_owned() 
+        };
+
+        // Add Timeslot to reservation times
+        reservation_times.push(timeslot);
+    }
+
+    reservation_times
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_created_reservations() {
+        let reservation = parse_reservation(&serde_json::json!({
+            "id": 123456,
+            "state": "requested",
+            "access_code": "4321",
+        }))
+        .unwrap();
+        assert_eq!(reservation.id, "123456");
+        assert_eq!(reservation.state, "requested");
+        assert_eq!(reservation.access_code.as_deref(), Some("4321"));
+
+        let reservation = parse_reservation(&serde_json::json!({ "id": "abc", "access_code": "" })).unwrap();
+        assert_eq!(reservation.state, "confirmed");
+        assert_eq!(reservation.access_code, None);
+        assert!(matches!(
+            parse_reservation(&serde_json::json!({ "state": "confirmed" })),
+            Err(FetchError::IncompleteResponse("id"))
+        ));
+    }
+}
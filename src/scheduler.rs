@@ -0,0 +1,231 @@
+use chrono::{DateTime, Local, NaiveTime, Timelike};
+use croner::parser::{CronParser, Seconds};
+use croner::Cron;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::timezone;
+
+/// Number of cycles skipped because the previous cycle was still running at their start time.
+pub(crate) static SKIPPED_CYCLES: AtomicU64 = AtomicU64::new(0);
+
+/// Fixed-rate schedule for fetch cycles.
+///
+/// Cycles are run one at a time. If a cycle overruns the start time of the next ones,
+/// those are skipped rather than run late back to back, and counted in SKIPPED_CYCLES.
+///
+/// With a cron schedule, cycles run at the times matching it instead.
+///
+/// Each cycle starts a random delay of up to `jitter` after its scheduled time, so several
+/// instances don't all fetch at the same second. The delay doesn't shift later cycles.
+pub(crate) struct Scheduler {
+    interval: Duration,
+    schedule: Option<&'static Cron>,
+    jitter: Duration,
+    next: Instant,
+}
+
+impl Scheduler {
+    /// Create a schedule with the first cycle due now.
+    pub(crate) fn new(interval: Duration, schedule: Option<&'static Cron>, jitter: Duration) -> Scheduler {
+        Scheduler {
+            interval,
+            schedule,
+            jitter,
+            next: Instant::now(),
+        }
+    }
+
+    /// Change the time between cycles, starting from the next cycle.
+    pub(crate) fn set_interval(&mut self, interval: Duration) {
+        if interval != self.interval {
+            tracing::info!(interval_secs = interval.as_secs(), "Polling interval changed");
+        }
+        self.interval = interval;
+    }
+
+    /// Advance to the next cycle and return how long to wait before starting it.
+    pub(crate) fn next_wait(&mut self) -> Duration {
+        if let Some(schedule) = self.schedule {
+            let now = Local::now();
+            match next_occurrence(schedule, now) {
+                Some(next) => return (next - now).to_std().unwrap_or_default() + random_delay(self.jitter),
+                None => tracing::warn!(%schedule, "Cron schedule has no upcoming times, polling at the interval instead"),
+            }
+        }
+
+        self.next += self.interval;
+
+        let now = Instant::now();
+        let mut skipped = 0;
+        while self.next <= now {
+            self.next += self.interval;
+            skipped += 1;
+        }
+        if skipped > 0 {
+            tracing::warn!(skipped, "Cycle took longer than the polling interval, skipping cycles");
+            SKIPPED_CYCLES.fetch_add(skipped, Ordering::Relaxed);
+        }
+
+        self.next - now + random_delay(self.jitter)
+    }
+}
+
+/// When each resource is due for its next fetch, for resources polled less often than the cycles run.
+#[derive(Default)]
+pub(crate) struct ResourceSchedule {
+    due: HashMap<String, Instant>,
+}
+
+impl ResourceSchedule {
+    /// Return true if the resource should be fetched in the cycle starting at `now`, and if so,
+    /// schedule its next fetch `interval` later. Resources not fetched yet are due. Cycles run
+    /// `cycle` apart and start a little late or early, so a fetch due within half a cycle is
+    /// made now rather than a whole cycle late.
+    pub(crate) fn is_due(&mut self, resource_id: &str, interval: Duration, cycle: Duration, now: Instant) -> bool {
+        if self.due.get(resource_id).is_some_and(|due| now + cycle / 2 < *due) {
+            return false;
+        }
+        self.due.insert(resource_id.to_owned(), now + interval);
+        true
+    }
+
+    /// Forget the resources no longer monitored, e.g. ones removed from the unit.
+    pub(crate) fn retain(&mut self, resource_ids: &[String]) {
+        self.due.retain(|resource_id, _| resource_ids.contains(resource_id));
+    }
+}
+
+/// Return the first time after `time` matching the cron schedule in the configured time zone,
+/// or None if there is none, e.g. for "0 0 30 2 *".
+pub(crate) fn next_occurrence(schedule: &Cron, time: DateTime<Local>) -> Option<DateTime<Local>> {
+    schedule
+        .find_next_occurrence(&timezone::zoned(&time), false)
+        .ok()
+        .map(|occurrence| occurrence.with_timezone(&Local))
+}
+
+/// Return the last time before `time` matching the cron schedule in the configured time zone.
+pub(crate) fn previous_occurrence(schedule: &Cron, time: DateTime<Local>) -> DateTime<Local> {
+    schedule
+        .find_previous_occurrence(&timezone::zoned(&time), false)
+        .map_or(time, |occurrence| occurrence.with_timezone(&Local))
+}
+
+/// Parse a five-field cron expression such as "*/5 7-22 * * *".
+/// Seconds aren't allowed, so the schedule can't poll more than once a minute.
+pub(crate) fn deserialize_schedule<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Cron>, D::Error> {
+    let Some(expression) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    let parser = CronParser::builder().seconds(Seconds::Disallowed).build();
+    parser.parse(&expression).map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Return a random duration between zero and `max`.
+pub(crate) fn random_delay(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(rand::random_range(0..=max.as_millis() as u64))
+}
+
+/// Poll faster around times of day with desirable slots, e.g.
+///
+/// ```toml
+/// [adaptive_polling]
+/// interval_secs = 60
+/// lead_minutes = 120
+/// windows = [{ from = "17:00", to = "21:00" }]
+/// ```
+///
+/// Polls every `interval_secs` from `lead_minutes` before the start of each window until its end,
+/// so last-minute cancellations are caught quickly.
+#[derive(Deserialize)]
+pub(crate) struct AdaptivePollingConfig {
+    pub(crate) interval_secs: u64,
+    #[serde(default)]
+    pub(crate) lead_minutes: u32,
+    pub(crate) windows: Vec<TimeWindow>,
+}
+
+/// A daily time window. Windows ending before they start continue past midnight.
+#[derive(Deserialize)]
+pub(crate) struct TimeWindow {
+    #[serde(deserialize_with = "deserialize_time")]
+    pub(crate) from: NaiveTime,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub(crate) to: NaiveTime,
+}
+
+impl AdaptivePollingConfig {
+    /// Return true if `time` of day is within a window or `lead_minutes` before one.
+    pub(crate) fn is_active(&self, time: NaiveTime) -> bool {
+        const DAY: u32 = 24 * 60;
+        let minute = time.hour() * 60 + time.minute();
+        self.windows.iter().any(|window| {
+            let start = (window.from.hour() * 60 + window.from.minute() + DAY - self.lead_minutes % DAY) % DAY;
+            let end = window.to.hour() * 60 + window.to.minute();
+            if start <= end {
+                start <= minute && minute < end
+            } else {
+                minute >= start || minute < end
+            }
+        })
+    }
+}
+
+/// Parse a time of day written as "HH:MM".
+pub(crate) fn deserialize_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let time = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&time, "%H:%M").map_err(serde::de::Error::custom)
+}
+
+/// Parse an optional time of day written as "HH:MM".
+pub(crate) fn deserialize_optional_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveTime>, D::Error> {
+    deserialize_time(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn cron(expression: &str) -> Cron {
+        CronParser::builder().seconds(Seconds::Disallowed).build().parse(expression).unwrap()
+    }
+
+    #[test]
+    fn finds_the_next_scheduled_time() {
+        let time = timezone::DEFAULT.with_ymd_and_hms(2024, 5, 7, 21, 50, 0).unwrap().with_timezone(&Local);
+        let next = next_occurrence(&cron("*/15 7-22 * * *"), time).unwrap();
+        assert_eq!(timezone::zoned(&next).to_rfc3339(), "2024-05-07T22:00:00+03:00");
+        let late = next_occurrence(&cron("*/15 7-22 * * *"), time + chrono::Duration::minutes(60)).unwrap();
+        assert_eq!(timezone::zoned(&late).to_rfc3339(), "2024-05-08T07:00:00+03:00");
+        assert!(next_occurrence(&cron("0 0 30 2 *"), time).is_none());
+    }
+
+    #[test]
+    fn fetches_resources_at_their_own_intervals() {
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+        let start = Instant::now();
+        let mut schedule = ResourceSchedule::default();
+        let mut fetched = Vec::new();
+        // Cycles every 2 minutes, a few seconds late, for the laser every 2 minutes and the sewing
+        // machine every 6.
+        for cycle in 0..7 {
+            let now = start + minutes(2 * cycle) + Duration::from_secs(cycle % 3);
+            for (resource_id, interval) in [("laser", minutes(2)), ("sewing", minutes(6))] {
+                if schedule.is_due(resource_id, interval, minutes(2), now) {
+                    fetched.push(format!("{} {}", 2 * cycle, resource_id));
+                }
+            }
+        }
+        let laser = fetched.iter().filter(|fetch| fetch.ends_with("laser")).count();
+        let sewing = fetched.iter().filter(|fetch| fetch.ends_with("sewing")).cloned().collect::<Vec<_>>();
+        assert_eq!(laser, 7);
+        assert_eq!(sewing, ["0 sewing", "6 sewing", "12 sewing"]);
+    }
+}
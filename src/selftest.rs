@@ -0,0 +1,148 @@
+use chrono::{Local, NaiveTime};
+
+use crate::availability;
+use crate::changes::{self, ChangeKind};
+use crate::diff::{DiffStrategy, ExactMatch, IntervalAware};
+use crate::history::{self, Observation};
+use crate::message::{self, Resource};
+use crate::state::State;
+use crate::timeslot::Timeslot;
+use crate::timezone;
+use crate::{feed, ical, respa, stats, CONFIG};
+
+/// A respa response for the resource, open from 10 to 18 tomorrow with reservations from 12 to 14
+/// and from 15 to 16. The dates and UTC offset are filled in when the test runs.
+const FIXTURE: &str = include_str!("../fixtures/selftest-resource.json");
+
+/// Run the pipeline from parsing a respa response to notifying and saving the state on fixture
+/// data, without network access, a state file or sending anything. Prints the result of each
+/// step and returns true if all of them passed.
+pub(crate) fn run() -> bool {
+    let mut state = State::default();
+    let mut notified = Vec::new();
+    let mut passed = true;
+
+    type Step = fn(&mut State, &mut Vec<String>) -> Result<(), String>;
+    let steps: [(&str, Step); 5] = [
+        ("parse respa response", |_, _| parse().map(|_| ())),
+        ("record baseline", record_baseline),
+        ("find cancelled times", find_cancelled),
+        ("notify", notify),
+        ("export and save", export),
+    ];
+    for (name, step) in steps {
+        match step(&mut state, &mut notified) {
+            Ok(()) => println!("ok      {}", name),
+            Err(error) => {
+                println!("FAILED  {}: {}", name, error);
+                passed = false;
+            }
+        }
+    }
+    println!("{}", if passed { "Self test passed." } else { "Self test failed." });
+    passed
+}
+
+/// The fixture with tomorrow's date, and with the reservation from 12 to 14 cancelled if `cancelled`.
+fn fixture(cancelled: bool) -> Result<serde_json::Value, String> {
+    let date = timezone::now().date_naive() + chrono::Days::new(1);
+    let opening = timezone::at(date, NaiveTime::from_hms_opt(10, 0, 0).unwrap_or_default());
+    let text = FIXTURE
+        .replace("{date}", &date.format("%Y-%m-%d").to_string())
+        .replace("{next_date}", &(date + chrono::Days::new(1)).format("%Y-%m-%d").to_string())
+        .replace("{offset}", &opening.format("%:z").to_string());
+    let mut api_data: serde_json::Value = serde_json::from_str(&text).map_err(|error| error.to_string())?;
+    if cancelled {
+        if let Some(reservations) = api_data["reservations"].as_array_mut() {
+            reservations.remove(0);
+        }
+    }
+    Ok(api_data)
+}
+
+fn parse() -> Result<(Resource, Vec<Timeslot>, Vec<Timeslot>), String> {
+    let api_data = fixture(false)?;
+    let resource = respa::parse_resource(&CONFIG.resource_id, &api_data);
+    let opening_times = respa::parse_opening_times(&api_data);
+    let reservations = respa::parse_reservations(&api_data);
+    ensure(resource.unit.as_deref() == Some("tprek:51342"), "unit not parsed")?;
+    ensure(opening_times.len() == 1, format!("{} opening times instead of 1", opening_times.len()))?;
+    ensure(reservations.len() == 2, format!("{} reservations instead of 2", reservations.len()))?;
+    ensure(respa::parse_max_days_in_advance(&api_data) == Some(14), "reservation limit not parsed")?;
+    Ok((resource, opening_times, reservations))
+}
+
+/// Available times of the fixture, without and with the cancellation.
+fn available_times(cancelled: bool) -> Result<Vec<Timeslot>, String> {
+    let api_data = fixture(cancelled)?;
+    let opening_times = respa::parse_opening_times(&api_data);
+    let reservations = respa::parse_reservations(&api_data);
+    Ok(availability::get_available_times(&opening_times, &reservations))
+}
+
+fn record_baseline(state: &mut State, _: &mut Vec<String>) -> Result<(), String> {
+    let (resource, opening_times, _) = parse()?;
+    let available_times = available_times(false)?;
+    let hours = available_times.iter().map(Timeslot::duration).collect::<Vec<i64>>();
+    ensure(hours == [2, 1, 2], format!("available hours {:?} instead of [2, 1, 2]", hours))?;
+    let observation = Observation::new(&resource.id, &opening_times, &available_times, Local::now());
+    ensure(observation.days.values().map(|day| day.free_minutes).sum::<i64>() == 5 * 60, "free minutes miscounted")?;
+    history::record(&mut state.history, observation);
+    state.available_times = available_times;
+    Ok(())
+}
+
+fn find_cancelled(state: &mut State, _: &mut Vec<String>) -> Result<(), String> {
+    let current = available_times(true)?;
+
+    let exact = ExactMatch.diff(&state.available_times, &current);
+    ensure(exact.added.len() == 1, format!("{} new times instead of 1", exact.added.len()))?;
+    ensure(exact.removed.len() == 2, format!("{} removed times instead of 2", exact.removed.len()))?;
+
+    let interval = IntervalAware.diff(&state.available_times, &current);
+    let hours = interval.added.iter().map(|time| time.start_time().format("%H").to_string()).collect::<Vec<String>>();
+    ensure(hours == ["12"] && interval.removed.is_empty(), format!("new parts start at {:?} instead of 12", hours))?;
+
+    let changes = changes::diff(&CONFIG.resource_id, &exact, Local::now());
+    ensure(changes.iter().filter(|change| change.kind == ChangeKind::Added).count() == 1, "change log miscounted")?;
+    changes::record(&mut state.changes, changes);
+    state.available_times = current;
+    Ok(())
+}
+
+/// Render the notification for each configured notifier and hand it to a notifier that only
+/// keeps the messages.
+fn notify(state: &mut State, notified: &mut Vec<String>) -> Result<(), String> {
+    let (resource, ..) = parse()?;
+    let new_times = state.available_times.clone();
+    for notifier in &CONFIG.notifiers {
+        let message = message::render(&resource, &new_times, &state.changes, &CONFIG.format(*notifier));
+        ensure(!message.trim().is_empty(), format!("empty message for {}", notifier.name()))?;
+        notified.push(message);
+    }
+    ensure(!notified.is_empty(), "no notifiers configured")?;
+    state.counters.notifications += 1;
+    Ok(())
+}
+
+fn export(state: &mut State, _: &mut Vec<String>) -> Result<(), String> {
+    let (resource, ..) = parse()?;
+    let calendar = ical::write_calendar(&resource, &state.available_times);
+    let events = ical::parse_events(&calendar)?;
+    ensure(events.len() == state.available_times.len(), "calendar does not round-trip")?;
+    ensure(feed::write_feed(&resource, &state.changes).contains("<entry>"), "feed has no entries")?;
+    stats::report(&state.history, &state.changes);
+
+    let saved = serde_json::to_string(state).map_err(|error| error.to_string())?;
+    let loaded: State = serde_json::from_str(&saved).map_err(|error| error.to_string())?;
+    ensure(loaded.available_times == state.available_times, "state does not round-trip")?;
+    Ok(())
+}
+
+fn ensure(condition: bool, error: impl Into<String>) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(error.into())
+    }
+}
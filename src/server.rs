@@ -0,0 +1,854 @@
+use chrono::{DateTime, Local, NaiveDate};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Read, Write};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::changes::Change;
+use crate::dashboard;
+use crate::feed;
+use crate::ical;
+use crate::metrics::{Counters, Metrics};
+use crate::stats;
+use crate::message::Resource;
+use crate::timeslot::Timeslot;
+
+/// Largest accepted request body.
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Paths served without a token or an allowed address.
+const PUBLIC_PATHS: [&str; 3] = ["/healthz", "/readyz", "/status"];
+
+/// Time between comments sent to idle event streams, so closed connections are noticed.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Event streams currently open, each with a thread of its own.
+static OPEN_EVENT_STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Embedded HTTP server settings, e.g.
+///
+/// ```toml
+/// [http]
+/// listen = "127.0.0.1:8080"
+/// allow_ips = ["127.0.0.1", "192.168.1.0/24"]
+/// cors_origins = ["https://dashboard.example.com"]
+/// rate_limit_per_minute = 60
+/// max_event_streams = 16
+///
+/// [http.tokens]
+/// dashboard = "secret"
+/// ```
+#[derive(Deserialize)]
+#[serde(default)]
+pub(crate) struct HttpConfig {
+    pub(crate) listen: String,
+    /// /readyz fails if the last successful fetch is older than this many scheduled fetches.
+    pub(crate) ready_intervals: u32,
+    /// Bearer tokens by client name. If any are set, requests other than health checks and /status need one.
+    pub(crate) tokens: HashMap<String, String>,
+    /// Addresses or CIDR ranges allowed to make requests other than health checks and /status. Empty allows all.
+    pub(crate) allow_ips: Vec<String>,
+    /// Origins of browser pages allowed to call the API, or "*" for any.
+    pub(crate) cors_origins: Vec<String>,
+    /// Requests allowed per client address per minute, excluding health checks. 0 disables the limit.
+    pub(crate) rate_limit_per_minute: u32,
+    /// /events streams open at once. Further ones are refused until one closes.
+    pub(crate) max_event_streams: usize,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            listen: "127.0.0.1:8080".to_owned(),
+            ready_intervals: 3,
+            tokens: HashMap::new(),
+            allow_ips: Vec::new(),
+            cors_origins: Vec::new(),
+            rate_limit_per_minute: 60,
+            max_event_streams: 16,
+        }
+    }
+}
+
+/// Result of the latest successful fetch, shared with the HTTP server.
+#[derive(Clone)]
+pub(crate) struct Snapshot {
+    pub(crate) resource: Resource,
+    pub(crate) opening_times: Vec<Timeslot>,
+    pub(crate) available_times: Vec<Timeslot>,
+    /// Available times that were not available on the previous fetch.
+    pub(crate) new_times: Vec<Timeslot>,
+    pub(crate) fetched_at: DateTime<Local>,
+}
+
+/// Monitoring status shared between the polling loop and the HTTP server.
+#[derive(Default)]
+pub(crate) struct Status {
+    /// Results of the latest successful fetch of each monitored resource, the configured one first.
+    pub(crate) snapshots: Vec<Snapshot>,
+    /// Set while monitoring is paused and no fetches are made.
+    pub(crate) paused: bool,
+    /// Latest changes in availability, oldest first.
+    pub(crate) changes: Vec<Change>,
+    /// Lifetime totals as of the latest cycle.
+    pub(crate) counters: Counters,
+    /// IDs of the monitored resources, the configured one first.
+    pub(crate) resource_ids: Vec<String>,
+    /// Reservations pushed to the webhook, by resource ID and source. See State::external_reservations.
+    pub(crate) external_reservations: HashMap<String, HashMap<String, Vec<Timeslot>>>,
+    /// Open /events streams.
+    pub(crate) event_streams: Vec<Sender<Change>>,
+}
+
+impl Status {
+    /// Latest result for the configured resource.
+    pub(crate) fn snapshot(&self) -> Option<&Snapshot> {
+        self.snapshot_of(&crate::CONFIG.resource_id)
+    }
+
+    /// Latest result for a resource.
+    pub(crate) fn snapshot_of(&self, resource_id: &str) -> Option<&Snapshot> {
+        self.snapshots.iter().find(|snapshot| snapshot.resource.id == resource_id)
+    }
+
+    /// Replace the results for the fetched resources. Resources that failed keep their previous result.
+    pub(crate) fn set_snapshots(&mut self, snapshots: Vec<Snapshot>) {
+        for snapshot in snapshots {
+            match self.snapshots.iter_mut().find(|previous| previous.resource.id == snapshot.resource.id) {
+                Some(previous) => *previous = snapshot,
+                None => self.snapshots.push(snapshot),
+            }
+        }
+    }
+
+    /// Replace the change log, sending the changes not seen before to the open event streams.
+    pub(crate) fn set_changes(&mut self, changes: &[Change]) {
+        let last_seen = self.changes.last().map(|change| change.detected_at);
+        for change in changes.iter().filter(|change| last_seen.is_none_or(|at| change.detected_at > at)) {
+            self.event_streams.retain(|stream| stream.send(change.clone()).is_ok());
+        }
+        self.changes = changes.to_vec();
+    }
+}
+
+pub(crate) type SharedStatus = Arc<RwLock<Status>>;
+
+/// Start serving HTTP requests on a background thread.
+///
+/// # Panics
+///
+/// Panics if the listen address cannot be bound or `allow_ips` has an invalid entry.
+pub(crate) fn start(config: &'static HttpConfig, status: SharedStatus) {
+    let allow_ips = config
+        .allow_ips
+        .iter()
+        .map(|range| IpRange::parse(range).unwrap_or_else(|| panic!("Invalid address in allow_ips: {}", range)))
+        .collect::<Vec<IpRange>>();
+    let server = Server::http(&config.listen).expect("Failed to start HTTP server");
+    tracing::info!(listen = %config.listen, "HTTP server listening");
+
+    std::thread::spawn(move || {
+        let mut rate_limiter = RateLimiter::new(config.rate_limit_per_minute);
+        for mut request in server.incoming_requests() {
+            let mut response = if let Err(response) = rate_limiter.check(&request) {
+                response
+            } else if request.method() == &Method::Options {
+                preflight(&allow_ips, &request)
+            } else {
+                match authorize(config, &allow_ips, &request) {
+                    Ok(()) if request.method() == &Method::Get && request.url().split('?').next() == Some("/events") => {
+                        // Streams are only opened on this thread, so the count can't grow past the check.
+                        if OPEN_EVENT_STREAMS.load(Ordering::SeqCst) >= config.max_event_streams {
+                            tracing::warn!(max = config.max_event_streams, "Too many open event streams");
+                            text_response(503, "Too many event streams")
+                        } else {
+                            stream_events(config, request, &status);
+                            continue;
+                        }
+                    }
+                    Ok(()) => handle(config, &mut request, &status),
+                    Err(response) => response,
+                }
+            };
+            add_cors_headers(config, &request, &mut response);
+            if let Err(error) = request.respond(response) {
+                tracing::warn!(%error, "Failed to send HTTP response");
+            }
+        }
+    });
+}
+
+fn handle(config: &HttpConfig, request: &mut Request, status: &SharedStatus) -> Response<Cursor<Vec<u8>>> {
+    let mut body = String::new();
+    if request.method() == &Method::Post {
+        if let Err(error) = request.as_reader().take(MAX_BODY_BYTES).read_to_string(&mut body) {
+            return text_response(400, &format!("Invalid request body: {}", error));
+        }
+    }
+
+    let request = &*request;
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    let segments = path.trim_matches('/').split('/').collect::<Vec<&str>>();
+
+    match (request.method(), segments.as_slice()) {
+        (Method::Get, ["healthz"]) => text_response(200, "OK"),
+        (Method::Get, ["readyz"]) => readiness(config, &status.read().unwrap()),
+        (Method::Get, ["status"]) => {
+            let status = status.read().unwrap();
+            let html = dashboard::render_status(&status, ready(config, &status).is_ok());
+            let content_type = Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap();
+            Response::from_string(html).with_header(content_type)
+        }
+        (Method::Get, ["metrics"]) => metrics(&status.read().unwrap()),
+        (Method::Get, ["dashboard"]) => {
+            let status = status.read().unwrap();
+            let content_type = Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap();
+            Response::from_string(dashboard::render(&status.snapshots.iter().collect::<Vec<&Snapshot>>())).with_header(content_type)
+        }
+        (Method::Get, ["api", "timeline"]) => match status.read().unwrap().snapshot() {
+            Some(snapshot) => json_response(&timeline(snapshot)),
+            None => text_response(503, "No data fetched yet"),
+        },
+        (Method::Get, ["resources"]) => {
+            let resources = status.read().unwrap().snapshots.iter().map(resource_summary).collect::<Vec<serde_json::Value>>();
+            json_response(&serde_json::Value::Array(resources))
+        }
+        (Method::Get, ["stats"]) => {
+            let time_to_book = stats::time_to_book_summary(&status.read().unwrap().changes);
+            json_response(&json!({ "time_to_book": time_to_book }))
+        }
+        (Method::Get, ["changes"]) => changes(query, &status.read().unwrap()),
+        (Method::Get, ["resources", id, "availability"]) => {
+            let status = status.read().unwrap();
+            match status.snapshot_of(id) {
+                Some(snapshot) => availability(request, query, snapshot),
+                None if status.snapshots.is_empty() => text_response(503, "No data fetched yet"),
+                None => text_response(404, "Unknown resource"),
+            }
+        }
+        (Method::Get, ["feed.xml"]) => {
+            let status = status.read().unwrap();
+            match status.snapshot() {
+                Some(snapshot) => {
+                    let content_type = Header::from_bytes("Content-Type", "application/atom+xml; charset=utf-8").unwrap();
+                    Response::from_string(feed::write_feed(&snapshot.resource, &status.changes)).with_header(content_type)
+                }
+                None => text_response(503, "No data fetched yet"),
+            }
+        }
+        (Method::Get, ["calendar.ics"]) => match status.read().unwrap().snapshot() {
+            Some(snapshot) => calendar_response(snapshot),
+            None => text_response(503, "No data fetched yet"),
+        },
+        (Method::Get, ["resources", id, "calendar.ics"]) => {
+            let status = status.read().unwrap();
+            match status.snapshot_of(id) {
+                Some(snapshot) => calendar_response(snapshot),
+                None if status.snapshots.is_empty() => text_response(503, "No data fetched yet"),
+                None => text_response(404, "Unknown resource"),
+            }
+        }
+        (Method::Post, ["resources", id, "reservations"]) => {
+            let mut status = status.write().unwrap();
+            if status.resource_ids.iter().any(|resource_id| resource_id == id) {
+                receive_reservations(request, id, query, &body, &mut status)
+            } else {
+                text_response(404, "Unknown resource")
+            }
+        }
+        _ => text_response(404, "Not found"),
+    }
+}
+
+/// Answer a CORS preflight request. Preflight requests carry no credentials, so only the client
+/// address is checked, and only paths that are served get an answer.
+fn preflight(allow_ips: &[IpRange], request: &Request) -> Response<Cursor<Vec<u8>>> {
+    let path = request.url().split('?').next().unwrap_or("");
+    if !PUBLIC_PATHS.contains(&path) {
+        if let Err(response) = check_address(allow_ips, request) {
+            return response;
+        }
+    }
+    if !is_served(path) {
+        return text_response(404, "Not found");
+    }
+    Response::from_data(Vec::new()).with_status_code(204)
+}
+
+/// Whether a path is one of the routes in handle, or /events.
+fn is_served(path: &str) -> bool {
+    let segments = path.trim_matches('/').split('/').collect::<Vec<&str>>();
+    matches!(
+        segments.as_slice(),
+        ["healthz" | "readyz" | "status" | "metrics" | "dashboard" | "resources" | "stats" | "changes" | "events"]
+            | ["feed.xml" | "calendar.ics"]
+            | ["api", "timeline"]
+            | ["resources", _, "availability" | "calendar.ics" | "reservations"]
+    )
+}
+
+/// Stream changes in availability as Server-Sent Events on a background thread, until the client
+/// disconnects. Each change is an "added" or "removed" event with the change as JSON data.
+fn stream_events(config: &HttpConfig, request: Request, status: &SharedStatus) {
+    let (sender, receiver) = mpsc::channel();
+    status.write().unwrap().event_streams.push(sender);
+
+    let mut head = Response::from_data(Vec::new());
+    add_cors_headers(config, &request, &mut head);
+    let mut text = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n".to_owned();
+    for header in head.headers() {
+        text.push_str(&format!("{}: {}\r\n", header.field, header.value));
+    }
+    text.push_str("\r\n");
+
+    let address = request.remote_addr().map(|address| address.ip());
+    let mut writer = request.into_writer();
+    OPEN_EVENT_STREAMS.fetch_add(1, Ordering::SeqCst);
+    std::thread::spawn(move || {
+        tracing::debug!(?address, "Event stream opened");
+        let result = writer.write_all(text.as_bytes()).and_then(|_| writer.flush());
+        if result.is_ok() {
+            send_events(&mut writer, receiver).ok();
+        }
+        OPEN_EVENT_STREAMS.fetch_sub(1, Ordering::SeqCst);
+        tracing::debug!(?address, "Event stream closed");
+    });
+}
+
+fn send_events(writer: &mut impl Write, receiver: Receiver<Change>) -> std::io::Result<()> {
+    loop {
+        match receiver.recv_timeout(KEEPALIVE_INTERVAL) {
+            Ok(change) => {
+                let kind = json!(change.kind);
+                let data = json!(change);
+                write!(writer, "event: {}\ndata: {}\n\n", kind.as_str().unwrap_or("change"), data)?;
+            }
+            Err(RecvTimeoutError::Timeout) => writer.write_all(b": keepalive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        writer.flush()?;
+    }
+}
+
+/// Replace the reservations pushed by a source, e.g. a club's maintenance calendar.
+/// They are subtracted from the availability of the resource on the following fetches.
+///
+/// The body is either an iCalendar document (Content-Type text/calendar) or a JSON array of
+/// `{"start": ..., "end": ...}` objects with RFC 3339 times. The source is named with the `source`
+/// query parameter, and an empty list removes its reservations.
+fn receive_reservations(
+    request: &Request,
+    resource_id: &str,
+    query: &str,
+    body: &str,
+    status: &mut Status,
+) -> Response<Cursor<Vec<u8>>> {
+    let source = source_of(query);
+    let source = source.as_str();
+    let is_calendar = request
+        .headers()
+        .iter()
+        .any(|header| header.field.equiv("Content-Type") && header.value.as_str().starts_with("text/calendar"));
+
+    let reservations = if is_calendar {
+        ical::parse_events(body)
+    } else {
+        serde_json::from_str::<Vec<Timeslot>>(body).map_err(|error| error.to_string())
+    };
+    let reservations = match reservations {
+        Ok(reservations) => reservations,
+        Err(error) => return text_response(400, &error),
+    };
+    let valid = |time: &str| DateTime::parse_from_rfc3339(time).is_ok();
+    if let Some(invalid) = reservations
+        .iter()
+        .find(|time| !valid(&time.start) || !valid(&time.end) || time.start_time() >= time.end_time())
+    {
+        return text_response(400, &format!("Invalid reservation: {} - {}", invalid.start, invalid.end));
+    }
+
+    tracing::info!(resource = resource_id, source, count = reservations.len(), "Received external reservations");
+    let sources = status.external_reservations.entry(resource_id.to_owned()).or_default();
+    if reservations.is_empty() {
+        sources.remove(source);
+    } else {
+        sources.insert(source.to_owned(), reservations);
+    }
+    text_response(204, "")
+}
+
+/// The `source` query parameter naming the sender of external reservations, "default" without one.
+fn source_of(query: &str) -> String {
+    query_param(query, "source").filter(|source| !source.is_empty()).unwrap_or_else(|| "default".to_owned())
+}
+
+/// Current availability and lifetime totals in the Prometheus text format.
+fn metrics(status: &Status) -> Response<Cursor<Vec<u8>>> {
+    let mut metrics = Metrics::default();
+    if let Some(snapshot) = status.snapshot() {
+        metrics.gauge(
+            "laser_last_fetch_timestamp_seconds",
+            "Time of the last successful fetch.",
+            snapshot.fetched_at.timestamp() as f64,
+        );
+        metrics.gauge("laser_available_slots", "Number of available time blocks.", snapshot.available_times.len() as f64);
+        metrics.gauge(
+            "laser_available_hours",
+            "Total available hours.",
+            snapshot.available_times.iter().map(Timeslot::duration_minutes).sum::<i64>() as f64 / 60.0,
+        );
+    }
+    metrics.gauge("laser_paused", "1 while monitoring is paused.", if status.paused { 1.0 } else { 0.0 });
+    status.counters.add_to(&mut metrics);
+
+    let content_type = Header::from_bytes("Content-Type", "text/plain; version=0.0.4").unwrap();
+    Response::from_string(metrics.render()).with_header(content_type)
+}
+
+/// A monitored resource and the time its availability was last fetched.
+fn resource_summary(snapshot: &Snapshot) -> serde_json::Value {
+    json!({
+        "id": snapshot.resource.id,
+        "name": snapshot.resource.name,
+        "unit": snapshot.resource.unit,
+        "url": snapshot.resource.booking_url(),
+        "fetched_at": snapshot.fetched_at.to_rfc3339(),
+        "available_slots": snapshot.available_times.len(),
+    })
+}
+
+/// Changes in availability detected after the `since` query parameter (RFC 3339), or all kept changes.
+fn changes(query: &str, status: &Status) -> Response<Cursor<Vec<u8>>> {
+    let since = match query.split('&').find_map(|pair| pair.strip_prefix("since=")) {
+        Some(since) => match DateTime::parse_from_rfc3339(&percent_decode(since)) {
+            Ok(since) => Some(since),
+            Err(_) => return text_response(400, &format!("Invalid value for since: {}", since)),
+        },
+        None => None,
+    };
+
+    let changes = status
+        .changes
+        .iter()
+        .filter(|change| since.is_none_or(|since| change.detected_at > since))
+        .collect::<Vec<&Change>>();
+    json_response(&json!(changes))
+}
+
+/// Decode %XX escapes in a query parameter, e.g. "%2B03:00" to "+03:00".
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail.get(..2).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Filters for the availability endpoint, from the query string
+/// `?from=YYYY-MM-DD&to=YYYY-MM-DD&min_hours=N`. The dates are inclusive.
+#[derive(Default)]
+struct AvailabilityQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    min_hours: i64,
+}
+
+impl AvailabilityQuery {
+    fn parse(query: &str) -> Result<AvailabilityQuery, String> {
+        let mut parsed = AvailabilityQuery::default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let invalid = || format!("Invalid value for {}: {}", key, value);
+            match key {
+                "from" => parsed.from = Some(NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| invalid())?),
+                "to" => parsed.to = Some(NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| invalid())?),
+                "min_hours" => parsed.min_hours = value.parse().map_err(|_| invalid())?,
+                "access_token" => {}
+                _ => return Err(format!("Unknown parameter: {}", key)),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn matches(&self, time: &Timeslot) -> bool {
+        let date = time.start_time().date_naive();
+        self.from.is_none_or(|from| date >= from)
+            && self.to.is_none_or(|to| date <= to)
+            && time.duration() >= self.min_hours
+    }
+}
+
+/// Available times of the resource, filtered by the query.
+///
+/// The response has an ETag that only changes when the returned times change, and is cacheable
+/// until the next fetch is due. Requests with a matching If-None-Match get an empty 304 response.
+fn availability(request: &Request, query: &str, snapshot: &Snapshot) -> Response<Cursor<Vec<u8>>> {
+    let query = match AvailabilityQuery::parse(query) {
+        Ok(query) => query,
+        Err(error) => return text_response(400, &error),
+    };
+
+    let slots = snapshot
+        .available_times
+        .iter()
+        .filter(|time| query.matches(time))
+        .map(|time| json!({ "start": time.start, "end": time.end, "hours": time.duration() }))
+        .collect::<Vec<serde_json::Value>>();
+    let body = json!({
+        "resource": {
+            "id": snapshot.resource.id,
+            "name": snapshot.resource.name,
+            "url": snapshot.resource.booking_url(),
+        },
+        "slots": slots,
+    });
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    body.to_string().hash(&mut hasher);
+    let etag = format!("\"{:016x}\"", hasher.finish());
+
+    let next_fetch = crate::CONFIG.next_fetch_after(&snapshot.resource.id, snapshot.fetched_at);
+    let max_age = (next_fetch - Local::now()).num_seconds().max(0);
+    let headers = [
+        Header::from_bytes("ETag", etag.as_str()).unwrap(),
+        Header::from_bytes("Cache-Control", format!("max-age={}", max_age)).unwrap(),
+    ];
+
+    let not_modified = request
+        .headers()
+        .iter()
+        .any(|header| header.field.equiv("If-None-Match") && header.value.as_str() == etag);
+    let mut response = if not_modified {
+        Response::from_data(Vec::new()).with_status_code(304)
+    } else {
+        json_response(&body)
+    };
+    for header in headers {
+        response.add_header(header);
+    }
+    response
+}
+
+/// Allow browser pages from `cors_origins` to read responses and send the Authorization header.
+fn add_cors_headers(config: &HttpConfig, request: &Request, response: &mut Response<Cursor<Vec<u8>>>) {
+    let Some(origin) = request.headers().iter().find(|header| header.field.equiv("Origin")) else {
+        return;
+    };
+    let origin = origin.value.as_str();
+    let allowed = config.cors_origins.iter().any(|allowed| allowed == "*" || allowed == origin);
+    if !allowed {
+        return;
+    }
+
+    for (field, value) in [
+        ("Access-Control-Allow-Origin", origin),
+        ("Access-Control-Allow-Methods", "GET, OPTIONS"),
+        ("Access-Control-Allow-Headers", "Authorization, If-None-Match"),
+        ("Access-Control-Expose-Headers", "ETag"),
+        ("Access-Control-Max-Age", "3600"),
+        ("Vary", "Origin"),
+    ] {
+        response.add_header(Header::from_bytes(field, value).unwrap());
+    }
+}
+
+/// Per-address token bucket allowing `per_minute` requests per minute on average, in bursts of up to
+/// `per_minute` requests. Health checks are not limited.
+struct RateLimiter {
+    per_minute: u32,
+    /// Remaining requests and the time they were counted, by client address.
+    buckets: HashMap<IpAddr, (f64, Instant)>,
+}
+
+impl RateLimiter {
+    fn new(per_minute: u32) -> RateLimiter {
+        RateLimiter {
+            per_minute,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Count a request. Returns a 429 response if its client has used up its requests.
+    fn check(&mut self, request: &Request) -> Result<(), Response<Cursor<Vec<u8>>>> {
+        let path = request.url().split('?').next().unwrap_or("");
+        let Some(address) = request.remote_addr().map(|address| address.ip()) else {
+            return Ok(());
+        };
+        if self.per_minute == 0 || path == "/healthz" || path == "/readyz" {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let capacity = self.per_minute as f64;
+        let per_second = capacity / 60.0;
+        // Forget clients whose buckets have refilled, so the map doesn't grow without bound.
+        self.buckets.retain(|_, (tokens, updated)| *tokens + updated.elapsed().as_secs_f64() * per_second < capacity);
+
+        let (tokens, updated) = self.buckets.entry(address).or_insert((capacity, now));
+        *tokens = (*tokens + (now - *updated).as_secs_f64() * per_second).min(capacity);
+        *updated = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            return Ok(());
+        }
+
+        tracing::warn!(%address, path, "HTTP client rate limited");
+        let retry_after = ((1.0 - *tokens) / per_second).ceil() as u64;
+        let retry_after = Header::from_bytes("Retry-After", retry_after.to_string()).unwrap();
+        Err(text_response(429, "Too many requests").with_header(retry_after))
+    }
+}
+
+/// Check the client address and bearer token of a request. Health checks and /status are always allowed.
+/// The token may also be given as the `access_token` query parameter, for clients that can't set headers.
+fn authorize(config: &HttpConfig, allow_ips: &[IpRange], request: &Request) -> Result<(), Response<Cursor<Vec<u8>>>> {
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    if PUBLIC_PATHS.contains(&path) {
+        return Ok(());
+    }
+
+    check_address(allow_ips, request)?;
+
+    if config.tokens.is_empty() {
+        return Ok(());
+    }
+    let bearer = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "));
+    let token = bearer.map(str::to_owned).or_else(|| query_param(query, "access_token"));
+    match token.and_then(|token| client_of(&config.tokens, &token)) {
+        Some(client) => {
+            tracing::debug!(%client, path, "Authorized HTTP request");
+            Ok(())
+        }
+        None => {
+            let challenge = Header::from_bytes("WWW-Authenticate", "Bearer").unwrap();
+            Err(text_response(401, "Unauthorized").with_header(challenge))
+        }
+    }
+}
+
+/// The client a bearer token belongs to. Tokens are compared in constant time, so response times
+/// don't tell how much of a guessed token was right.
+fn client_of<'a>(tokens: &'a HashMap<String, String>, token: &str) -> Option<&'a str> {
+    tokens
+        .iter()
+        .find(|(_, valid)| constant_time_eq(valid.as_bytes(), token.as_bytes()))
+        .map(|(client, _)| client.as_str())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+/// The percent-decoded value of a query parameter.
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+        .map(percent_decode)
+}
+
+/// Check that the client address is in `allow_ips`, if any are configured.
+fn check_address(allow_ips: &[IpRange], request: &Request) -> Result<(), Response<Cursor<Vec<u8>>>> {
+    if allow_ips.is_empty() {
+        return Ok(());
+    }
+    let address = request.remote_addr().map(|address| address.ip());
+    if !address.is_some_and(|address| allow_ips.iter().any(|range| range.contains(address))) {
+        let path = request.url().split('?').next().unwrap_or("");
+        tracing::warn!(?address, path, "HTTP request from address not in allow_ips");
+        return Err(text_response(403, "Forbidden"));
+    }
+    Ok(())
+}
+
+/// An IP address range in CIDR notation, or a single address.
+struct IpRange {
+    network: IpAddr,
+    prefix_len: u32,
+}
+
+impl IpRange {
+    fn parse(range: &str) -> Option<IpRange> {
+        let (address, prefix_len) = range.split_once('/').unwrap_or((range, ""));
+        let network: IpAddr = address.parse().ok()?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            "" => max_len,
+            prefix_len => prefix_len.parse().ok().filter(|len| *len <= max_len)?,
+        };
+        Some(IpRange { network, prefix_len })
+    }
+
+    fn contains(&self, address: IpAddr) -> bool {
+        // Compare the leading prefix_len bits. IPv4 clients of a dual-stack socket appear as mapped IPv6 addresses.
+        let address = match address {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(address),
+            address => address,
+        };
+        let (network, address, bits) = match (self.network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => (u32::from(network) as u128, u32::from(address) as u128, 32),
+            (IpAddr::V6(network), IpAddr::V6(address)) => (u128::from(network), u128::from(address), 128),
+            _ => return false,
+        };
+        let mask = u128::MAX.checked_shl(bits - self.prefix_len).unwrap_or(0);
+        network & mask == address & mask
+    }
+}
+
+/// /readyz: 200 if ready, otherwise 503 with the reason.
+fn readiness(config: &HttpConfig, status: &Status) -> Response<Cursor<Vec<u8>>> {
+    match ready(config, status) {
+        Ok(()) if status.paused => text_response(200, "Paused"),
+        Ok(()) => text_response(200, "OK"),
+        Err(reason) => text_response(503, &reason),
+    }
+}
+
+/// Ready if monitoring is paused, or the last successful fetch is recent enough.
+fn ready(config: &HttpConfig, status: &Status) -> Result<(), String> {
+    if status.paused {
+        return Ok(());
+    }
+
+    let oldest_allowed = crate::CONFIG.scheduled_fetch_before(&crate::CONFIG.resource_id, Local::now(), config.ready_intervals);
+    match status.snapshot() {
+        Some(snapshot) if snapshot.fetched_at >= oldest_allowed => Ok(()),
+        Some(snapshot) => Err(format!("Last successful fetch at {}", snapshot.fetched_at.to_rfc3339())),
+        None => Err("No successful fetch yet".to_owned()),
+    }
+}
+
+fn json_response(value: &serde_json::Value) -> Response<Cursor<Vec<u8>>> {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    Response::from_string(value.to_string()).with_header(content_type)
+}
+
+fn calendar_response(snapshot: &Snapshot) -> Response<Cursor<Vec<u8>>> {
+    let calendar = ical::write_calendar(&snapshot.resource, &snapshot.available_times);
+    let content_type = Header::from_bytes("Content-Type", "text/calendar; charset=utf-8").unwrap();
+    Response::from_string(calendar).with_header(content_type)
+}
+
+fn text_response(status: u16, text: &str) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(text).with_status_code(status)
+}
+
+/// Availability as consecutive ranges in the "available", "reserved" or "closed" state,
+/// suitable for a Grafana state timeline panel with the JSON API datasource.
+///
+/// Each range has `time` (start as epoch milliseconds), `start`, `end`, `state` and `resource`.
+/// A final "closed" point marks the end of the last range.
+fn timeline(snapshot: &Snapshot) -> serde_json::Value {
+    let mut ranges: Vec<(DateTime<Tz>, DateTime<Tz>, &str)> = Vec::new();
+    let mut opening_times = snapshot.opening_times.clone();
+    opening_times.sort_by_key(|time| time.start_time());
+
+    for opening_time in &opening_times {
+        let (opens, closes) = (opening_time.start_time(), opening_time.end_time());
+        if let Some((_, previous_end, _)) = ranges.last() {
+            if *previous_end < opens {
+                ranges.push((*previous_end, opens, "closed"));
+            }
+        }
+
+        // Split the opening time into available and reserved ranges.
+        let mut cursor = opens;
+        for available_time in &snapshot.available_times {
+            let start = available_time.start_time().max(opens);
+            let end = available_time.end_time().min(closes);
+            if start >= end || start < cursor {
+                continue;
+            }
+            if cursor < start {
+                ranges.push((cursor, start, "reserved"));
+            }
+            ranges.push((start, end, "available"));
+            cursor = end;
+        }
+        if cursor < closes {
+            ranges.push((cursor, closes, "reserved"));
+        }
+    }
+
+    let mut points = ranges
+        .iter()
+        .map(|(start, end, state)| {
+            json!({
+                "time": start.timestamp_millis(),
+                "start": start.to_rfc3339(),
+                "end": end.to_rfc3339(),
+                "state": state,
+                "resource": snapshot.resource.id,
+            })
+        })
+        .collect::<Vec<serde_json::Value>>();
+    if let Some((_, end, _)) = ranges.last() {
+        points.push(json!({
+            "time": end.timestamp_millis(),
+            "start": end.to_rfc3339(),
+            "end": end.to_rfc3339(),
+            "state": "closed",
+            "resource": snapshot.resource.id,
+        }));
+    }
+
+    serde_json::Value::Array(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_clients_by_decoded_tokens() {
+        let tokens = HashMap::from([("dashboard".to_owned(), "s3cret+/=".to_owned())]);
+        let token = query_param("from=2024-05-01&access_token=s3cret%2B%2F%3D", "access_token").unwrap();
+        assert_eq!(client_of(&tokens, &token), Some("dashboard"));
+        assert_eq!(client_of(&tokens, "s3cret"), None);
+        assert_eq!(client_of(&tokens, "s3cret+/=x"), None);
+        assert_eq!(query_param("access_tokens=x", "access_token"), None);
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(constant_time_eq(b"abc", b"abc"));
+    }
+
+    #[test]
+    fn names_sources_by_decoded_parameter() {
+        assert_eq!(source_of("source=club%20calendar"), "club calendar");
+        assert_eq!(source_of("access_token=x&source=maintenance"), "maintenance");
+        assert_eq!(source_of("source="), "default");
+        assert_eq!(source_of(""), "default");
+    }
+
+    #[test]
+    fn serves_only_known_paths() {
+        for path in ["/healthz", "/events", "/api/timeline", "/resources/axwzr3i57yba/availability", "/calendar.ics"] {
+            assert!(is_served(path), "{} should be served", path);
+        }
+        for path in ["/", "/admin", "/resources/axwzr3i57yba", "/api/timeline/extra"] {
+            assert!(!is_served(path), "{} should not be served", path);
+        }
+    }
+}
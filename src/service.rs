@@ -0,0 +1,58 @@
+use std::ffi::OsString;
+use std::time::Duration;
+use windows_service::service::{ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::{define_windows_service, service_dispatcher};
+
+/// Name the service is registered under, e.g. with
+/// `sc create laser-rust binPath= "C:\laser\laser-rust.exe --daemon" start= auto`.
+const NAME: &str = "laser-rust";
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Run as a Windows service, started by the service control manager. Blocks until the service
+/// has stopped. Exits if the process wasn't started as a service.
+pub(crate) fn start() {
+    if let Err(error) = service_dispatcher::start(NAME, ffi_service_main) {
+        eprintln!("--daemon runs the bot as a Windows service, so it must be started by the service control manager: {}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Report the service as running, and check until the service control manager asks it to stop.
+/// Services start in the system directory, so the working directory is changed to that of the
+/// executable, where laser.toml and .env are looked for.
+fn service_main(_arguments: Vec<OsString>) {
+    let handler = |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            crate::terminate();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    };
+    let status_handle = match service_control_handler::register(NAME, handler) {
+        Ok(status_handle) => status_handle,
+        Err(error) => {
+            eprintln!("Failed to register the service control handler: {}", error);
+            return;
+        }
+    };
+    let status = |current_state, controls_accepted| ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    };
+
+    if let Some(dir) = std::env::current_exe().ok().as_deref().and_then(std::path::Path::parent) {
+        std::env::set_current_dir(dir).ok();
+    }
+    let running = status(ServiceState::Running, ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN);
+    status_handle.set_service_status(running).ok();
+    crate::run(<crate::Cli as clap::Parser>::parse());
+    status_handle.set_service_status(status(ServiceState::Stopped, ServiceControlAccept::empty())).ok();
+}
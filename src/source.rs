@@ -0,0 +1,151 @@
+use chrono::Local;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::respa::{self, FetchError};
+use crate::CONFIG;
+
+/// Where resource data (opening hours and reservations, in the format of the respa API) comes from.
+pub(crate) trait DataSource: Send + Sync {
+    fn fetch(&self, resource_id: &str) -> Result<serde_json::Value, FetchError>;
+}
+
+/// The live respa API.
+pub(crate) struct RespaApi;
+
+impl DataSource for RespaApi {
+    fn fetch(&self, resource_id: &str) -> Result<serde_json::Value, FetchError> {
+        respa::fetch_resource_data(resource_id)
+    }
+}
+
+/// Responses recorded with `laser-rust record`, replayed from a directory.
+///
+/// The files of a resource are named `<resource_id>-<time>.json`, or just `<resource_id>.json`.
+/// Each fetch returns the next file in name order, and the last one once all have been returned,
+/// so a series of recordings plays back the changes seen while recording.
+pub(crate) struct FileSource {
+    dir: PathBuf,
+    /// Number of files returned so far, per resource ID.
+    replayed: Mutex<HashMap<String, usize>>,
+}
+
+impl FileSource {
+    pub(crate) fn new(dir: impl Into<PathBuf>) -> FileSource {
+        FileSource {
+            dir: dir.into(),
+            replayed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Recorded files of a resource, in the order they are replayed.
+    fn recordings(&self, resource_id: &str) -> Result<Vec<PathBuf>, FetchError> {
+        let entries = std::fs::read_dir(&self.dir).map_err(|error| FetchError::Replay(format!("{}: {}", self.dir.display(), error)))?;
+        let prefix = format!("{}-", resource_id);
+        let mut files = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+                let stem = name.strip_suffix(".json").unwrap_or_default();
+                stem == resource_id || stem.starts_with(&prefix)
+            })
+            .collect::<Vec<PathBuf>>();
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl DataSource for FileSource {
+    fn fetch(&self, resource_id: &str) -> Result<serde_json::Value, FetchError> {
+        let files = self.recordings(resource_id)?;
+        let Some(last) = files.len().checked_sub(1) else {
+            return Err(FetchError::Replay(format!("no recordings of {} in {}", resource_id, self.dir.display())));
+        };
+        let index = {
+            let mut replayed = self.replayed.lock().unwrap();
+            let count = replayed.entry(resource_id.to_owned()).or_default();
+            *count += 1;
+            (*count - 1).min(last)
+        };
+
+        let path = &files[index];
+        tracing::info!(path = %path.display(), "Replaying recorded response");
+        let contents = std::fs::read_to_string(path).map_err(|error| FetchError::Replay(format!("{}: {}", path.display(), error)))?;
+        let api_data = serde_json::from_str(&contents).map_err(FetchError::InvalidResponse)?;
+        respa::check_complete(&api_data)?;
+        Ok(api_data)
+    }
+}
+
+static SOURCE: Lazy<Box<dyn DataSource>> = Lazy::new(|| match &CONFIG.replay_dir {
+    Some(dir) => {
+        tracing::warn!(dir, "Replaying recorded responses instead of fetching from respa");
+        Box::new(FileSource::new(dir))
+    }
+    None => Box::new(RespaApi),
+});
+
+/// The configured data source: recordings in `replay_dir` if set, otherwise the respa API.
+pub(crate) fn source() -> &'static dyn DataSource {
+    SOURCE.as_ref()
+}
+
+/// Handle `laser-rust record`: fetch each monitored resource from the respa API `count` times,
+/// `interval` apart, saving the responses in `dir` for FileSource. Returns false if any fetch or
+/// write failed.
+pub(crate) fn record(dir: &Path, resource_ids: &[String], count: u32, interval: std::time::Duration) -> bool {
+    if let Err(error) = std::fs::create_dir_all(dir) {
+        eprintln!("Can't create {}: {}", dir.display(), error);
+        return false;
+    }
+    let mut succeeded = true;
+    for round in 0..count {
+        if round > 0 {
+            std::thread::sleep(interval);
+        }
+        for resource_id in resource_ids {
+            let path = dir.join(format!("{}-{}.json", resource_id, Local::now().format("%Y%m%dT%H%M%S")));
+            let result = RespaApi
+                .fetch(resource_id)
+                .map_err(|error| error.to_string())
+                .and_then(|api_data| {
+                    let contents = serde_json::to_string_pretty(&api_data).unwrap_or_default();
+                    std::fs::write(&path, contents).map_err(|error| error.to_string())
+                });
+            match result {
+                Ok(()) => println!("Recorded {} to {}", resource_id, path.display()),
+                Err(error) => {
+                    eprintln!("Failed to record {}: {}", resource_id, error);
+                    succeeded = false;
+                }
+            }
+        }
+    }
+    succeeded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_recordings_in_order_and_repeats_the_last() {
+        let dir = std::env::temp_dir().join(format!("laser-rust-replay-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, reservations) in [("res-20240801T120000.json", 1), ("res-20240801T120500.json", 2), ("other.json", 3)] {
+            let data = serde_json::json!({ "opening_hours": [], "reservations": vec![serde_json::json!({}); reservations] });
+            std::fs::write(dir.join(name), data.to_string()).unwrap();
+        }
+
+        let source = FileSource::new(&dir);
+        let counts = (0..3)
+            .map(|_| source.fetch("res").unwrap()["reservations"].as_array().unwrap().len())
+            .collect::<Vec<usize>>();
+        let missing = source.fetch("missing");
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(counts, vec![1, 2, 2]);
+        assert!(matches!(missing, Err(FetchError::Replay(_))));
+    }
+}
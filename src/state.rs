@@ -0,0 +1,447 @@
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::breaker::Breaker;
+use crate::changes::Change;
+use crate::digest::UnchangedDigest;
+use crate::history::Observation;
+use crate::metrics::Counters;
+use crate::overrides::Overrides;
+use crate::own_reservations::OwnReservation;
+use crate::paths;
+use crate::scheduler::ResourceSchedule;
+use crate::telegram::{QueuedMessage, Subscriber};
+use crate::timeslot::Timeslot;
+use crate::CONFIG;
+
+/// File in the state directory the bot state is persisted to between runs.
+const STATE_FILE: &str = "state.json";
+
+/// The state is written here first and then renamed over the state file.
+const TEMP_FILE: &str = "state.json.tmp";
+
+/// The previous state file, used if the current one is missing or unreadable.
+const BACKUP_FILE: &str = "state.json.bak";
+
+/// An unreadable state file is moved here for inspection.
+const CORRUPT_FILE: &str = "state.json.corrupt";
+
+/// Written and removed by check, to test that the state file could be saved.
+const PROBE_FILE: &str = "state.json.probe";
+
+/// Locked while the bot runs, and holds its process ID.
+const LOCK_FILE: &str = "laser.lock";
+
+/// Plain-text file used by earlier versions to store available times.
+const LEGACY_AVAILABLE_TIMES_FILE: &str = "available_times";
+
+/// Everything the bot needs to remember between runs.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct State {
+    /// When each monitored resource is next due for a fetch, while running continuously.
+    #[serde(skip)]
+    pub(crate) fetch_schedule: ResourceSchedule,
+
+    /// Available times seen on the previous run.
+    pub(crate) available_times: Vec<Timeslot>,
+
+    /// Available times of the other monitored resources of the unit seen on the previous run,
+    /// by resource ID. Resources not fetched yet are missing.
+    pub(crate) unit_available_times: HashMap<String, Vec<Timeslot>>,
+
+    /// IDs of the resources found in the monitored unit.
+    pub(crate) unit_resources: Vec<String>,
+
+    /// Time the resources of the monitored unit were last listed.
+    pub(crate) unit_discovered_at: Option<DateTime<Local>>,
+
+    /// Offset of the next Telegram update to fetch.
+    pub(crate) update_offset: i64,
+
+    /// Pause requested with the /pause command.
+    pub(crate) paused_until: Option<NaiveDate>,
+
+    /// Set while monitoring is paused. The first run after a pause only records
+    /// the current availability, so slots freed during the pause aren't announced as new.
+    pub(crate) paused: bool,
+
+    /// Set when the saved availability couldn't be recovered. The next run only records
+    /// the current availability, so every available slot isn't announced as new.
+    pub(crate) baseline: bool,
+
+    /// Settings changed with /set.
+    pub(crate) overrides: Overrides,
+
+    /// Time of the last successful fetch per resource ID.
+    pub(crate) fetched_at: HashMap<String, DateTime<Local>>,
+
+    /// Time availability was last fetched and saved.
+    pub(crate) updated_at: Option<DateTime<Local>>,
+
+    /// Time of the last update before a long downtime. Set when stale state is detected on
+    /// startup, and cleared once the "resynced" summary has been sent.
+    pub(crate) resynced_after: Option<DateTime<Local>>,
+
+    /// Chats that receive notifications.
+    pub(crate) subscribers: Vec<Subscriber>,
+
+    /// Messages waiting to be retried because Telegram couldn't be reached.
+    pub(crate) outbox: Vec<QueuedMessage>,
+
+    /// Time of the last notification per resource ID.
+    pub(crate) last_notified: HashMap<String, DateTime<Local>>,
+
+    /// New times held back by the notification cooldown, per resource ID.
+    pub(crate) pending_times: HashMap<String, Vec<Timeslot>>,
+
+    /// New times seen in the last fetch, waiting to be seen again when `confirm_new_times` is set.
+    pub(crate) unconfirmed_times: HashMap<String, Vec<Timeslot>>,
+
+    /// Times announced as new within the last `dedup_minutes`, per resource ID.
+    pub(crate) notified_times: HashMap<String, Vec<NotifiedTime>>,
+
+    /// Consecutive failures of the respa API, and whether it is considered down.
+    pub(crate) breaker: Breaker,
+
+    /// Lifetime totals, such as the number of fetches.
+    pub(crate) counters: Counters,
+
+    /// Latest changes in availability, oldest first.
+    pub(crate) changes: Vec<Change>,
+
+    /// Availability seen on the latest fetches, oldest first.
+    pub(crate) history: Vec<Observation>,
+
+    /// Upcoming reservations made by the user of the API token, as last fetched.
+    pub(crate) own_reservations: Vec<OwnReservation>,
+
+    /// Day the daily digest was last sent.
+    pub(crate) digest_sent_on: Option<NaiveDate>,
+
+    /// Reservations pushed to the webhook by other systems, per resource ID and source.
+    /// They are subtracted from the availability like reservations made in respa.
+    pub(crate) external_reservations: HashMap<String, HashMap<String, Vec<Timeslot>>>,
+}
+
+/// Drop the external reservations that have ended, and the sources and resources left without any.
+pub(crate) fn drop_past_reservations(external: &mut HashMap<String, HashMap<String, Vec<Timeslot>>>, now: DateTime<Local>) {
+    for sources in external.values_mut() {
+        for reservations in sources.values_mut() {
+            reservations.retain(|time| time.end_time() > now);
+        }
+        sources.retain(|_, reservations| !reservations.is_empty());
+    }
+    external.retain(|_, sources| !sources.is_empty());
+}
+
+/// A time announced as new, remembered so it isn't announced again if it flickers.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct NotifiedTime {
+    pub(crate) time: Timeslot,
+    pub(crate) notified_at: DateTime<Local>,
+}
+
+impl State {
+    /// Available times of a resource seen on the previous run, or None if it hasn't been fetched.
+    pub(crate) fn available_times_of(&self, resource_id: &str) -> Option<&Vec<Timeslot>> {
+        if resource_id == CONFIG.resource_id {
+            Some(&self.available_times)
+        } else {
+            self.unit_available_times.get(resource_id)
+        }
+    }
+
+    /// Return true if the time, or a time covering it, was announced within `window`.
+    pub(crate) fn was_notified(&self, resource_id: &str, time: &Timeslot, window: chrono::Duration) -> bool {
+        let since = Local::now() - window;
+        self.notified_times
+            .get(resource_id)
+            .is_some_and(|notified_times| notified_times.iter().any(|notified| notified.notified_at > since && notified.time.covers(time)))
+    }
+
+    /// Remember the times announced now, forgetting the ones announced over `window` ago.
+    pub(crate) fn remember_notified(&mut self, resource_id: &str, times: &[Timeslot], window: chrono::Duration) {
+        let now = Local::now();
+        let notified_times = self.notified_times.entry(resource_id.to_owned()).or_default();
+        notified_times.retain(|notified| notified.notified_at > now - window);
+        if window > chrono::Duration::zero() {
+            notified_times.extend(times.iter().map(|time| NotifiedTime {
+                time: time.clone(),
+                notified_at: now,
+            }));
+        }
+    }
+
+    /// Save the available times of a resource, replacing the previously seen times.
+    pub(crate) fn set_available_times(&mut self, resource_id: &str, times: Vec<Timeslot>) {
+        if resource_id == CONFIG.resource_id {
+            self.available_times = times;
+        } else {
+            self.unit_available_times.insert(resource_id.to_owned(), times);
+        }
+    }
+
+    /// Load state from the state file.
+    ///
+    /// If the state file is missing or unreadable, the backup of the previous state is used.
+    /// An unreadable state file is moved aside. If neither file exists, available times are
+    /// imported from the legacy file, if any. If no state can be recovered, the next run
+    /// records a new baseline.
+    pub(crate) fn load() -> State {
+        match read_state_file(&paths::state_file(STATE_FILE)) {
+            Ok(Some(state)) => return state,
+            Ok(None) => {}
+            Err(error) => {
+                tracing::error!(%error, "State file is unreadable, moving it to {}", CORRUPT_FILE);
+                fs::rename(paths::state_file(STATE_FILE), paths::state_file(CORRUPT_FILE)).ok();
+            }
+        }
+
+        match read_state_file(&paths::state_file(BACKUP_FILE)) {
+            Ok(Some(state)) => {
+                tracing::warn!("Recovered state from {}", BACKUP_FILE);
+                return state;
+            }
+            Ok(None) if !paths::state_file(CORRUPT_FILE).exists() => {
+                return State {
+                    available_times: read_legacy_available_times(),
+                    ..State::default()
+                };
+            }
+            Ok(None) => {}
+            Err(error) => tracing::error!(%error, "State backup is unreadable"),
+        }
+
+        tracing::warn!("No state could be recovered, recording current availability as a baseline");
+        State {
+            baseline: true,
+            ..State::default()
+        }
+    }
+
+    /// Record a new baseline if the saved availability is older than `max_age`, e.g. because the
+    /// bot was off for a long time. Slots freed up during the downtime are then summarized
+    /// instead of announced one by one.
+    pub(crate) fn detect_stale(&mut self, max_age: chrono::Duration) {
+        let Some(updated_at) = self.updated_at else {
+            return;
+        };
+        if self.paused || Local::now() - updated_at <= max_age {
+            return;
+        }
+
+        tracing::warn!(%updated_at, "State is stale, recording current availability as a baseline");
+        self.baseline = true;
+        self.resynced_after = Some(updated_at);
+    }
+
+    /// Add a chat to the subscribers, unless it is already subscribed
+    /// or has since been migrated to a new chat ID.
+    pub(crate) fn add_subscriber(&mut self, chat_id: &str) {
+        let known = self.subscribers.iter().any(|subscriber| {
+            subscriber.chat_id == chat_id || subscriber.migrated_from.as_deref() == Some(chat_id)
+        });
+        if !known {
+            self.subscribers.push(Subscriber {
+                chat_id: chat_id.to_owned(),
+                migrated_from: None,
+                failures: 0,
+                inactive: false,
+                last_digest: None,
+                unchanged_digest: UnchangedDigest::default(),
+                watches: Vec::new(),
+                alerts_paused_until: None,
+                blackouts: Vec::new(),
+                throttle_minutes: None,
+                last_sent_at: None,
+                priority_windows: Vec::new(),
+                venue_sent_on: HashMap::new(),
+            });
+        }
+    }
+
+    /// Return the subscriber with the given chat ID.
+    pub(crate) fn subscriber_mut(&mut self, chat_id: &str) -> Option<&mut Subscriber> {
+        self.subscribers.iter_mut().find(|subscriber| subscriber.chat_id == chat_id)
+    }
+
+    /// Write state to the state file, keeping the previous file as a backup.
+    ///
+    /// The state is written to a temporary file which then replaces the state file,
+    /// so a crash mid-write can't leave a partially written state file behind.
+    /// Failures are logged, not fatal.
+    pub(crate) fn save(&self) {
+        let contents = serde_json::to_string_pretty(self).expect("Failed to serialize state");
+        if let Err(error) = write_atomically(&contents) {
+            tracing::error!(%error, "Failed to write state file");
+        }
+    }
+}
+
+/// Exclusive lock on the state directory. The operating system releases it when the process
+/// exits, even after a crash, so a lock file left behind by a dead process doesn't block anything.
+pub(crate) struct Lock {
+    _file: fs::File,
+}
+
+/// Lock the state directory, so that a second instance can't send the same notifications again
+/// or overwrite the state. Fails with a message naming the instance holding the lock.
+pub(crate) fn lock() -> Result<Lock, String> {
+    lock_in(paths::state_dir())
+}
+
+fn lock_in(dir: &Path) -> Result<Lock, String> {
+    let path = dir.join(LOCK_FILE);
+    let mut file = fs::create_dir_all(dir)
+        .and_then(|()| fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path))
+        .map_err(|error| format!("Failed to open lock file {}: {}", path.display(), error))?;
+    match file.try_lock() {
+        Ok(()) => {
+            file.set_len(0)
+                .and_then(|()| file.write_all(std::process::id().to_string().as_bytes()))
+                .map_err(|error| format!("Failed to write lock file {}: {}", path.display(), error))?;
+            Ok(Lock { _file: file })
+        }
+        Err(fs::TryLockError::WouldBlock) => {
+            let pid = fs::read_to_string(&path).unwrap_or_default();
+            Err(format!(
+                "Another instance (process {}) is already running with the state in {}. Stop it first, \
+                 or give this one a separate --state-dir.",
+                pid.trim(),
+                dir.display()
+            ))
+        }
+        Err(fs::TryLockError::Error(error)) => Err(format!("Failed to lock {}: {}", path.display(), error)),
+    }
+}
+
+/// Check that the state file can be read, if it exists, and that it could be saved.
+/// Returns the path of the state file.
+pub(crate) fn check() -> Result<String, String> {
+    let state_file = paths::state_file(STATE_FILE);
+    read_state_file(&state_file).map_err(|error| format!("{} is unreadable: {}", state_file.display(), error))?;
+    let probe_file = paths::state_file(PROBE_FILE);
+    fs::create_dir_all(paths::state_dir())
+        .and_then(|()| fs::write(&probe_file, ""))
+        .and_then(|()| fs::remove_file(&probe_file))
+        .map_err(|error| format!("can't write next to {}: {}", state_file.display(), error))?;
+    Ok(state_file.display().to_string())
+}
+
+fn write_atomically(contents: &str) -> std::io::Result<()> {
+    fs::create_dir_all(paths::state_dir())?;
+    let temp_file = paths::state_file(TEMP_FILE);
+    let mut file = fs::File::create(&temp_file)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+
+    let state_file = paths::state_file(STATE_FILE);
+    if state_file.exists() {
+        fs::rename(&state_file, paths::state_file(BACKUP_FILE))?;
+    }
+    fs::rename(temp_file, state_file)
+}
+
+/// Read and parse a state file. Returns None if the file does not exist.
+fn read_state_file(path: &Path) -> Result<Option<State>, Box<dyn std::error::Error>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Read available times from the legacy available_times file, which earlier versions kept in the
+/// working directory. If the file does not exist, return an empty vector.
+///
+/// The lines are in the following format:
+/// 2021-09-01T10:00:00+03:00,2021-09-01T11:00:00+03:00
+fn read_legacy_available_times() -> Vec<Timeslot> {
+    let file_contents = fs::read_to_string(LEGACY_AVAILABLE_TIMES_FILE).unwrap_or_default();
+
+    let mut existing_available_times: Vec<Timeslot> = Vec::new();
+    for line in file_contents.lines() {
+        if let Some((start, end)) = line.split_once(',') {
+            existing_available_times.push(Timeslot {
+                start: start.to_owned(),
+                end: end.to_owned(),
+            });
+        }
+    }
+
+    existing_available_times
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_lock_fails_until_first_is_released() {
+        let dir = std::env::temp_dir().join(format!("laser-rust-lock-{}", std::process::id()));
+        let lock = lock_in(&dir).unwrap();
+        let error = lock_in(&dir).err().unwrap();
+        assert!(error.contains(&format!("process {}", std::process::id())), "{}", error);
+        drop(lock);
+        assert!(lock_in(&dir).is_ok());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn drops_ended_external_reservations() {
+        let time = |start: &str, end: &str| Timeslot {
+            start: format!("2024-05-04T{}:00+03:00", start),
+            end: format!("2024-05-04T{}:00+03:00", end),
+        };
+        let mut external = HashMap::from([
+            (
+                "axwzr3i57yba".to_owned(),
+                HashMap::from([
+                    ("club".to_owned(), vec![time("08:00", "10:00"), time("14:00", "16:00")]),
+                    ("maintenance".to_owned(), vec![time("09:00", "11:00")]),
+                ]),
+            ),
+            ("sewing".to_owned(), HashMap::from([("club".to_owned(), vec![time("10:00", "12:00")])])),
+        ]);
+        let now = DateTime::parse_from_rfc3339("2024-05-04T12:00:00+03:00").unwrap().with_timezone(&Local);
+        drop_past_reservations(&mut external, now);
+        assert_eq!(external.len(), 1);
+        assert_eq!(external["axwzr3i57yba"].keys().collect::<Vec<_>>(), ["club"]);
+        assert_eq!(external["axwzr3i57yba"]["club"], [time("14:00", "16:00")]);
+    }
+
+    #[test]
+    fn suppresses_times_announced_within_window() {
+        let time = |start: &str, end: &str| Timeslot {
+            start: start.to_owned(),
+            end: end.to_owned(),
+        };
+        let announced = time("2024-05-04T10:00:00+03:00", "2024-05-04T14:00:00+03:00");
+        let within = time("2024-05-04T11:00:00+03:00", "2024-05-04T12:00:00+03:00");
+        let overlapping = time("2024-05-04T13:00:00+03:00", "2024-05-04T15:00:00+03:00");
+        let hour = chrono::Duration::hours(1);
+
+        let mut state = State::default();
+        assert!(!state.was_notified("axwzr3i57yba", &within, hour));
+        state.remember_notified("axwzr3i57yba", std::slice::from_ref(&announced), hour);
+        assert!(state.was_notified("axwzr3i57yba", &announced, hour));
+        assert!(state.was_notified("axwzr3i57yba", &within, hour));
+        assert!(!state.was_notified("axwzr3i57yba", &overlapping, hour));
+        assert!(!state.was_notified("other", &within, hour));
+
+        // Announcements older than the window are forgotten.
+        state.notified_times.get_mut("axwzr3i57yba").unwrap()[0].notified_at -= chrono::Duration::hours(2);
+        assert!(!state.was_notified("axwzr3i57yba", &within, hour));
+        state.remember_notified("axwzr3i57yba", &[], hour);
+        assert!(state.notified_times["axwzr3i57yba"].is_empty());
+
+        // Without a window, nothing is remembered.
+        state.remember_notified("axwzr3i57yba", &[announced], chrono::Duration::zero());
+        assert!(state.notified_times["axwzr3i57yba"].is_empty());
+    }
+}
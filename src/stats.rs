@@ -0,0 +1,309 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, Timelike, Weekday};
+use chrono_tz::Tz;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use crate::changes::{Change, ChangeKind};
+use crate::history::{Day, Observation};
+use crate::timeslot::Timeslot;
+use crate::timezone;
+
+/// Fewest booked times of a weekday and hour needed for a median time to book.
+const MIN_SAMPLES: usize = 3;
+
+/// Times starting at this hour or later count as evening times.
+const EVENING_HOUR: u32 = 17;
+
+/// Statistics from the availability history and the change log, for planning when to look for cancellations.
+#[derive(Serialize)]
+pub(crate) struct Report {
+    /// Observations in the history.
+    pub(crate) observations: usize,
+    /// Days with opening hours seen on or before the day.
+    pub(crate) days: usize,
+    pub(crate) average_daily_free_hours: Option<f64>,
+    /// Weekdays by the share of opening hours booked, busiest first.
+    pub(crate) busiest_weekdays: Vec<WeekdayUsage>,
+    /// Median minutes evening times stayed available before they were booked.
+    pub(crate) evening_time_to_book_minutes: Option<i64>,
+    /// See time_to_book_summary.
+    pub(crate) time_to_book: serde_json::Value,
+}
+
+#[derive(Serialize)]
+pub(crate) struct WeekdayUsage {
+    pub(crate) weekday: String,
+    pub(crate) booked_percent: f64,
+    pub(crate) days: usize,
+}
+
+/// Compile statistics from the history and the change log.
+///
+/// Each day counts with its availability as last seen on or before the day, so times booked
+/// in advance count as booked, but times freed and rebooked on earlier days don't.
+pub(crate) fn report(history: &[Observation], changes: &[Change]) -> Report {
+    let mut days: BTreeMap<(&str, NaiveDate), Day> = BTreeMap::new();
+    for observation in history {
+        let observed_on = timezone::zoned(&observation.observed_at).date_naive();
+        for (date, day) in observation.days.range(observed_on..) {
+            if day.open_minutes > 0 {
+                days.insert((&observation.resource, *date), *day);
+            }
+        }
+    }
+
+    let average_daily_free_hours = (!days.is_empty())
+        .then(|| days.values().map(|day| day.free_minutes as f64 / 60.0).sum::<f64>() / days.len() as f64);
+
+    let mut weekdays: BTreeMap<u32, (Day, usize)> = BTreeMap::new();
+    for ((_, date), day) in &days {
+        let (total, count) = weekdays.entry(date.weekday().num_days_from_monday()).or_default();
+        total.open_minutes += day.open_minutes;
+        total.free_minutes += day.free_minutes;
+        *count += 1;
+    }
+    let mut busiest_weekdays = weekdays
+        .iter()
+        .filter_map(|(weekday, (total, count))| {
+            Some(WeekdayUsage {
+                weekday: Weekday::try_from(*weekday as u8).ok()?.to_string(),
+                booked_percent: 100.0 * (total.open_minutes - total.free_minutes) as f64 / total.open_minutes as f64,
+                days: *count,
+            })
+        })
+        .collect::<Vec<WeekdayUsage>>();
+    busiest_weekdays.sort_by(|a, b| b.booked_percent.total_cmp(&a.booked_percent));
+
+    let time_to_book = time_to_book(changes);
+    let evening = time_to_book
+        .iter()
+        .filter(|((_, hour), _)| *hour >= EVENING_HOUR)
+        .flat_map(|(_, minutes)| minutes.iter().copied())
+        .collect::<Vec<i64>>();
+
+    Report {
+        observations: history.len(),
+        days: days.len(),
+        average_daily_free_hours,
+        busiest_weekdays,
+        evening_time_to_book_minutes: median(&evening),
+        time_to_book: time_to_book_summary(changes),
+    }
+}
+
+/// Minutes that times stayed available before they were booked, by the weekday (from Monday)
+/// and hour they start.
+pub(crate) type TimeToBook = BTreeMap<(u32, u32), Vec<i64>>;
+
+/// A time that became available and was booked later.
+struct Booking {
+    start: DateTime<Tz>,
+    available_at: DateTime<Local>,
+    booked_at: DateTime<Local>,
+}
+
+impl Booking {
+    /// Weekday (from Monday) and hour the time starts.
+    fn key(&self) -> (u32, u32) {
+        (self.start.weekday().num_days_from_monday(), self.start.hour())
+    }
+}
+
+/// Find the booked times in a change log.
+///
+/// A time counts as booked when it is no longer available before it starts. Times that are
+/// still available, or were available until they started, are left out. So are times whose
+/// removal is explained by a block added in the same check over the same hours, since the time
+/// was then merged with a neighbouring one or split by a shorter booking rather than booked whole.
+fn bookings(changes: &[Change]) -> Vec<Booking> {
+    let mut bookings = Vec::new();
+    for (index, added) in changes.iter().enumerate().filter(|(_, change)| change.kind == ChangeKind::Added) {
+        let removed = changes[index + 1..].iter().find(|change| {
+            change.kind == ChangeKind::Removed
+                && change.resource == added.resource
+                && change.start == added.start
+                && change.end == added.end
+        });
+        let Some(removed) = removed else {
+            continue;
+        };
+        if is_reshaped(changes, removed) {
+            continue;
+        }
+        let start = slot(added).start_time();
+        if removed.detected_at < start {
+            bookings.push(Booking {
+                start,
+                available_at: added.detected_at,
+                booked_at: removed.detected_at,
+            });
+        }
+    }
+    bookings
+}
+
+/// Whether a removed time overlaps a time added in the same check, i.e. its block changed shape.
+fn is_reshaped(changes: &[Change], removed: &Change) -> bool {
+    let removed_slot = slot(removed);
+    changes.iter().any(|change| {
+        let added = slot(change);
+        change.kind == ChangeKind::Added
+            && change.resource == removed.resource
+            && change.detected_at == removed.detected_at
+            && added.start_time() < removed_slot.end_time()
+            && removed_slot.start_time() < added.end_time()
+    })
+}
+
+/// Collect the time to book of the times in a change log.
+pub(crate) fn time_to_book(changes: &[Change]) -> TimeToBook {
+    let mut minutes = TimeToBook::new();
+    for booking in bookings(changes) {
+        minutes.entry(booking.key()).or_default().push((booking.booked_at - booking.available_at).num_minutes());
+    }
+    minutes
+}
+
+/// How far in advance times starting at a weekday and hour get booked.
+#[derive(Serialize)]
+pub(crate) struct LeadTime {
+    pub(crate) weekday: Weekday,
+    pub(crate) hour: u32,
+    /// Median minutes from the time becoming available to it being booked.
+    pub(crate) available_minutes: i64,
+    /// Median hours from the booking to the start of the time.
+    pub(crate) booked_hours_ahead: i64,
+    /// Booked times the medians are based on.
+    pub(crate) samples: usize,
+}
+
+/// Lead times by weekday and hour, for those with enough booked times.
+pub(crate) fn lead_times(changes: &[Change]) -> Vec<LeadTime> {
+    let mut by_hour: BTreeMap<(u32, u32), Vec<Booking>> = BTreeMap::new();
+    for booking in bookings(changes) {
+        by_hour.entry(booking.key()).or_default().push(booking);
+    }
+
+    by_hour
+        .iter()
+        .filter_map(|((weekday, hour), bookings)| {
+            let available = bookings.iter().map(|booking| (booking.booked_at - booking.available_at).num_minutes());
+            let ahead = bookings.iter().map(|booking| booking.start.signed_duration_since(booking.booked_at).num_hours());
+            Some(LeadTime {
+                weekday: Weekday::try_from(*weekday as u8).ok()?,
+                hour: *hour,
+                available_minutes: median(&available.collect::<Vec<i64>>())?,
+                booked_hours_ahead: median(&ahead.collect::<Vec<i64>>())?,
+                samples: bookings.len(),
+            })
+        })
+        .collect()
+}
+
+/// Median minutes that times starting at the same weekday and hour as `time` stayed available,
+/// if enough of them have been booked.
+pub(crate) fn median_time_to_book(time_to_book: &TimeToBook, time: &Timeslot) -> Option<i64> {
+    let start = time.start_time();
+    let key = (start.weekday().num_days_from_monday(), start.hour());
+    time_to_book.get(&key).and_then(|minutes| median(minutes))
+}
+
+/// Median time to book by weekday and hour, e.g.
+/// `[{"weekday": "Sat", "hour": 10, "median_minutes": 40, "samples": 5}]`.
+pub(crate) fn time_to_book_summary(changes: &[Change]) -> serde_json::Value {
+    let summary = time_to_book(changes)
+        .iter()
+        .filter_map(|((weekday, hour), minutes)| {
+            Some(json!({
+                "weekday": Weekday::try_from(*weekday as u8).ok()?.to_string(),
+                "hour": hour,
+                "median_minutes": median(minutes)?,
+                "samples": minutes.len(),
+            }))
+        })
+        .collect::<Vec<serde_json::Value>>();
+    serde_json::Value::Array(summary)
+}
+
+fn median(values: &[i64]) -> Option<i64> {
+    if values.len() < MIN_SAMPLES {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let middle = sorted.len() / 2;
+    Some(if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2
+    } else {
+        sorted[middle]
+    })
+}
+
+fn slot(change: &Change) -> Timeslot {
+    Timeslot {
+        start: change.start.clone(),
+        end: change.end.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Language;
+    use chrono::TimeZone;
+
+    fn change(minute: u32, kind: ChangeKind, start: &str, end: &str) -> Change {
+        Change {
+            detected_at: Local.with_ymd_and_hms(2024, 5, 1, 8, minute, 0).unwrap(),
+            resource: "axwzr3i57yba".to_owned(),
+            kind,
+            start: format!("2024-05-04T{}:00+03:00", start),
+            end: format!("2024-05-04T{}:00+03:00", end),
+        }
+    }
+
+    #[test]
+    fn counts_only_times_removed_whole() {
+        let changes = [
+            // Booked 20 minutes after becoming available.
+            change(0, ChangeKind::Added, "10:00", "12:00"),
+            change(20, ChangeKind::Removed, "10:00", "12:00"),
+            // Merged with a cancelled neighbour into 14:00-18:00, not booked.
+            change(0, ChangeKind::Added, "14:00", "16:00"),
+            change(30, ChangeKind::Removed, "14:00", "16:00"),
+            change(30, ChangeKind::Added, "14:00", "18:00"),
+            // Split by a booking of 19:00-20:00, which is not the whole time.
+            change(0, ChangeKind::Added, "18:00", "21:00"),
+            change(40, ChangeKind::Removed, "18:00", "21:00"),
+            change(40, ChangeKind::Added, "18:00", "19:00"),
+            change(40, ChangeKind::Added, "20:00", "21:00"),
+        ];
+        let bookings = bookings(&changes);
+        assert_eq!(bookings.len(), 1);
+        assert_eq!(bookings[0].start.hour(), 10);
+        assert_eq!((bookings[0].booked_at - bookings[0].available_at).num_minutes(), 20);
+    }
+
+    #[test]
+    fn takes_medians_of_enough_samples() {
+        assert_eq!(median(&[5, 1]), None);
+        assert_eq!(median(&[30, 10, 20]), Some(20));
+        assert_eq!(median(&[40, 10, 20, 30]), Some(25));
+    }
+
+    #[test]
+    fn formats_lead_times_in_the_language() {
+        let lead_times = [LeadTime {
+            weekday: Weekday::Sat,
+            hour: 10,
+            available_minutes: 40,
+            booked_hours_ahead: 52,
+            samples: 5,
+        }];
+        let text = |language| crate::output::lead_times_text(&lead_times, language);
+        assert_eq!(text(Language::En), "Sat 10:00 available 40 min, booked 52 h ahead (5 times)\n");
+        assert_eq!(text(Language::Fi), "la 10:00 vapaana 40 min, varattu 52 h etukäteen (5 aikaa)\n");
+        assert_eq!(serde_json::to_value(&lead_times[0]).unwrap()["weekday"], "Sat");
+    }
+}
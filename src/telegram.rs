@@ -0,0 +1,716 @@
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::blackout::{self, Blackout};
+use crate::client;
+use crate::digest::UnchangedDigest;
+use crate::message;
+use crate::priority::{self, PriorityWindow};
+use crate::respa::Unit;
+use crate::state::State;
+use crate::timeslot::Timeslot;
+use crate::watch::Watch;
+use crate::CONFIG;
+
+/// A text message received by the bot, or a tap on an inline button.
+pub(crate) struct Message {
+    pub(crate) chat_id: String,
+    /// Group title, or the name of the user in a private chat.
+    pub(crate) chat_title: String,
+    /// ID of the user who sent the message or tapped the button.
+    pub(crate) user_id: Option<i64>,
+    /// Text of the message, or the callback data of the button.
+    pub(crate) text: String,
+    /// Set if an inline button was tapped.
+    pub(crate) callback: Option<Callback>,
+}
+
+/// A tap on an inline button of a message sent by the bot.
+pub(crate) struct Callback {
+    /// ID of the callback query, for answering it.
+    pub(crate) id: String,
+    /// ID of the message with the button.
+    pub(crate) message_id: i64,
+}
+
+/// A chat that receives notifications.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Subscriber {
+    pub(crate) chat_id: String,
+
+    /// Previous ID of the chat, if it has been migrated to a supergroup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) migrated_from: Option<String>,
+
+    /// Consecutive deliveries refused with 403, e.g. because the bot was blocked.
+    #[serde(default)]
+    pub(crate) failures: u32,
+
+    /// Set when the chat refused too many deliveries in a row. Inactive chats are skipped.
+    #[serde(default)]
+    pub(crate) inactive: bool,
+
+    /// Text of the last daily digest sent to the chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_digest: Option<String>,
+
+    /// What to send when the digest hasn't changed, chosen with /digest.
+    #[serde(default)]
+    pub(crate) unchanged_digest: UnchangedDigest,
+
+    /// Time windows the chat is notified about as soon as any part of them is free, set with /watch.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) watches: Vec<Watch>,
+
+    /// Nothing is sent to the chat until this time, set with /pause and cleared with /resume.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) alerts_paused_until: Option<DateTime<Local>>,
+
+    /// Dates the chat isn't notified about, set with /blackout.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) blackouts: Vec<Blackout>,
+
+    /// Send the chat at most one message per this many minutes, set with /throttle. Messages in
+    /// between are queued in the outbox and sent together once the time has passed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) throttle_minutes: Option<i64>,
+
+    /// When a message was last delivered to the chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_sent_at: Option<DateTime<Local>>,
+
+    /// Times the chat is notified about with sound, set with /priority. If there are any,
+    /// notifications about other times arrive silently.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) priority_windows: Vec<PriorityWindow>,
+
+    /// Day the location of each venue was last sent to the chat, by unit ID.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) venue_sent_on: HashMap<String, NaiveDate>,
+}
+
+impl Subscriber {
+    /// Return true if the chat has paused its notifications.
+    pub(crate) fn alerts_paused(&self) -> bool {
+        self.alerts_paused_until.is_some_and(|until| Local::now() < until)
+    }
+
+    /// Return true if the chat got a message too recently for its throttle to allow another.
+    pub(crate) fn throttled(&self) -> bool {
+        match (self.throttle_minutes, self.last_sent_at) {
+            // A throttle too long to add up isn't applied, rather than overflowing.
+            (Some(minutes), Some(sent_at)) => chrono::Duration::try_minutes(minutes)
+                .and_then(|throttle| sent_at.checked_add_signed(throttle))
+                .is_some_and(|until| Local::now() < until),
+            _ => false,
+        }
+    }
+}
+
+/// A message to send to a chat.
+#[derive(Clone)]
+pub(crate) struct OutgoingMessage {
+    pub(crate) text: String,
+    /// Inline keyboard of the message as JSON, if it has buttons.
+    pub(crate) reply_markup: Option<String>,
+    /// Set if the message should arrive without sound.
+    pub(crate) silent: bool,
+}
+
+impl OutgoingMessage {
+    /// A message with only text, arriving with sound.
+    pub(crate) fn text(text: String) -> OutgoingMessage {
+        OutgoingMessage {
+            text,
+            reply_markup: None,
+            silent: false,
+        }
+    }
+}
+
+/// A message that could not be delivered yet, kept in the state until Telegram accepts it.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct QueuedMessage {
+    pub(crate) chat_id: String,
+    pub(crate) text: String,
+    /// Set if the text is formatted with MarkdownV2.
+    #[serde(default)]
+    pub(crate) markdown: bool,
+    /// Inline keyboard of the message as JSON, if it has buttons.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) reply_markup: Option<String>,
+    /// Set if the message should arrive without sound.
+    #[serde(default)]
+    pub(crate) silent: bool,
+    /// Forum topic the message is posted in, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) message_thread_id: Option<i64>,
+    pub(crate) queued_at: DateTime<Local>,
+}
+
+/// Reasons a message could not be delivered.
+#[derive(Debug)]
+pub(crate) enum SendError {
+    /// The group was upgraded to a supergroup and now has a new chat ID.
+    Migrated(String),
+    /// Too many requests. Sending may be retried after the given time.
+    RateLimited(Duration),
+    /// Telegram rejected the request.
+    Api { code: i64, description: String },
+    /// Telegram could not be reached.
+    Network(reqwest::Error),
+}
+
+impl SendError {
+    /// Return true if sending may succeed later, e.g. when Telegram is unreachable or overloaded.
+    pub(crate) fn is_temporary(&self) -> bool {
+        match self {
+            SendError::Network(_) => true,
+            SendError::Api { code, .. } => *code >= 500,
+            SendError::RateLimited(_) => true,
+            SendError::Migrated(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Migrated(chat_id) => write!(f, "chat migrated to {}", chat_id),
+            SendError::RateLimited(retry_after) => write!(f, "rate limited, retry after {} s", retry_after.as_secs()),
+            SendError::Api { code, description } => write!(f, "{} {}", code, description),
+            SendError::Network(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Number of times a rate limited message is retried before it is queued for the next cycle.
+const MAX_RATE_LIMIT_RETRIES: usize = 3;
+
+fn api_url(method: &str) -> String {
+    format!("{}/bot{}/{}", CONFIG.telegram_api_url.trim_end_matches('/'), CONFIG.telegram_bot_token, method)
+}
+
+/// Send a text message to a chat.
+pub(crate) fn send_message(chat_id: &str, text: &str) -> Result<(), SendError> {
+    send_text(chat_id, text, false, None, false, None)
+}
+
+/// Send a text message with inline buttons to a chat. `reply_markup` is the keyboard as JSON.
+pub(crate) fn send_with_keyboard(chat_id: &str, text: &str, reply_markup: &str) -> Result<(), SendError> {
+    send_text(chat_id, text, false, Some(reply_markup), false, None)
+}
+
+/// Send a text message to a chat, optionally formatted with MarkdownV2, with inline buttons,
+/// without sound and in a forum topic.
+fn send_text(
+    chat_id: &str,
+    text: &str,
+    markdown: bool,
+    reply_markup: Option<&str>,
+    silent: bool,
+    message_thread_id: Option<i64>,
+) -> Result<(), SendError> {
+    let message_thread_id = message_thread_id.map(|id| id.to_string());
+    let mut parameters = vec![("chat_id", chat_id), ("text", text)];
+    if let Some(message_thread_id) = &message_thread_id {
+        parameters.push(("message_thread_id", message_thread_id));
+    }
+    if markdown {
+        parameters.push(("parse_mode", "MarkdownV2"));
+    }
+    if silent {
+        parameters.push(("disable_notification", "true"));
+    }
+    if let Some(reply_markup) = reply_markup {
+        parameters.push(("reply_markup", reply_markup));
+    }
+    call("sendMessage", &parameters)
+}
+
+/// Replace the text of a message sent by the bot, removing its buttons.
+pub(crate) fn edit_message(chat_id: &str, message_id: i64, text: &str) -> Result<(), SendError> {
+    call(
+        "editMessageText",
+        &[("chat_id", chat_id), ("message_id", &message_id.to_string()), ("text", text)],
+    )
+}
+
+/// Acknowledge a tap on an inline button, so the client stops showing it as loading.
+pub(crate) fn answer_callback(callback_id: &str) -> Result<(), SendError> {
+    call("answerCallbackQuery", &[("callback_query_id", callback_id)])
+}
+
+/// Send the location of a venue to a chat, shown with a map that opens navigation, optionally in
+/// a forum topic.
+pub(crate) fn send_venue(chat_id: &str, unit: &Unit, message_thread_id: Option<i64>) -> Result<(), SendError> {
+    let (latitude, longitude) = (unit.latitude.to_string(), unit.longitude.to_string());
+    let message_thread_id = message_thread_id.map(|id| id.to_string());
+    let mut parameters = vec![
+        ("chat_id", chat_id),
+        ("latitude", &latitude),
+        ("longitude", &longitude),
+        ("title", &unit.name),
+        ("address", &unit.address),
+    ];
+    if let Some(message_thread_id) = &message_thread_id {
+        parameters.push(("message_thread_id", message_thread_id));
+    }
+    call("sendVenue", &parameters)
+}
+
+/// Send a PNG image to a chat, with a caption, optionally in a forum topic.
+#[cfg(feature = "heatmap")]
+pub(crate) fn send_photo(chat_id: &str, png: Vec<u8>, caption: &str, message_thread_id: Option<i64>) -> Result<(), SendError> {
+    use reqwest::blocking::multipart::{Form, Part};
+
+    let photo = Part::bytes(png).file_name("heatmap.png").mime_str("image/png").map_err(SendError::Network)?;
+    let mut form = Form::new()
+        .text("chat_id", chat_id.to_owned())
+        .text("caption", caption.to_owned())
+        .part("photo", photo);
+    if let Some(message_thread_id) = message_thread_id {
+        form = form.text("message_thread_id", message_thread_id.to_string());
+    }
+    let response = client::client()
+        .post(api_url("sendPhoto"))
+        .multipart(form)
+        .send()
+        .and_then(|response| response.text())
+        .map_err(SendError::Network)?;
+    check_response(&response)
+}
+
+/// Check the bot token by fetching the bot's own user. Returns the username of the bot.
+pub(crate) fn get_me() -> Result<String, SendError> {
+    let response = client::client()
+        .get(api_url("getMe"))
+        .send()
+        .and_then(|response| response.text())
+        .map_err(SendError::Network)?;
+    check_response(&response)?;
+    let me: serde_json::Value = serde_json::from_str(&response).unwrap_or_default();
+    Ok(me["result"]["username"].as_str().unwrap_or_default().to_owned())
+}
+
+/// Call a Telegram Bot API method that sends something to a chat.
+fn call(method: &str, parameters: &[(&str, &str)]) -> Result<(), SendError> {
+    let response = client::client()
+        .get(api_url(method))
+        .query(parameters)
+        .send()
+        .and_then(|response| response.text())
+        .map_err(SendError::Network)?;
+    check_response(&response)
+}
+
+/// Interpret the response to a method that sends something to a chat.
+fn check_response(response: &str) -> Result<(), SendError> {
+    tracing::debug!(%response, "Telegram response");
+
+    let response: serde_json::Value = serde_json::from_str(response).unwrap_or_default();
+    if response["ok"].as_bool() == Some(true) {
+        return Ok(());
+    }
+
+    // Sending to a group that was upgraded to a supergroup fails with the new chat ID in the parameters.
+    if let Some(new_chat_id) = response["parameters"]["migrate_to_chat_id"].as_i64() {
+        return Err(SendError::Migrated(new_chat_id.to_string()));
+    }
+
+    // Too Many Requests responses tell how many seconds to wait before retrying.
+    if let Some(retry_after) = response["parameters"]["retry_after"].as_u64() {
+        return Err(SendError::RateLimited(Duration::from_secs(retry_after)));
+    }
+
+    Err(SendError::Api {
+        code: response["error_code"].as_i64().unwrap_or_default(),
+        description: response["description"].as_str().unwrap_or("Unknown error").to_owned(),
+    })
+}
+
+/// Send a text message about a resource to the active subscribers it is routed to, pausing
+/// `send_interval_millis` between chats to stay within Telegram's rate limits.
+/// Messages that can't be delivered because of a temporary error are queued in the outbox, as are
+/// messages to chats throttled with /throttle. With `markdown`, the text is formatted with MarkdownV2.
+pub(crate) fn broadcast(state: &mut State, resource_id: &str, text: &str, markdown: bool) {
+    broadcast_to(state, markdown, Some(resource_id), |subscriber| {
+        CONFIG.routes_to(resource_id, subscriber).then(|| OutgoingMessage::text(text.to_owned()))
+    });
+}
+
+/// Send the active subscribers a resource is routed to a message about the new times not on their
+/// blackout dates. `message_for` returns the message about some of the times, or None if there
+/// is nothing to send. The message arrives silently if none of the times is within the chat's
+/// priority windows. Inline buttons are only sent to the
+/// operator chat, since they book with the operator's account. See broadcast.
+pub(crate) fn broadcast_times(
+    state: &mut State,
+    resource_id: &str,
+    new_times: &[Timeslot],
+    markdown: bool,
+    message_for: impl Fn(&State, &[Timeslot]) -> Option<OutgoingMessage>,
+) {
+    // The messages are rendered first, since rendering may need the state.
+    let messages = state
+        .subscribers
+        .iter()
+        .filter(|subscriber| CONFIG.routes_to(resource_id, subscriber))
+        .filter_map(|subscriber| {
+            let times = blackout::outside(&subscriber.blackouts, new_times);
+            let mut message = if times.is_empty() { None } else { message_for(state, &times) }?;
+            message.reply_markup = message.reply_markup.filter(|_| subscriber.chat_id == CONFIG.operator_chat());
+            message.silent = priority::is_silent(&subscriber.priority_windows, &times);
+            Some((subscriber.chat_id.clone(), message))
+        })
+        .collect::<HashMap<String, OutgoingMessage>>();
+    broadcast_to(state, markdown, Some(resource_id), |subscriber| {
+        messages.get(&subscriber.chat_id).cloned()
+    });
+}
+
+/// Send each active subscriber the text `text_for` returns for it, if any. See broadcast.
+/// Chats that have paused their notifications are skipped.
+/// Returns the chats the text was delivered to.
+pub(crate) fn broadcast_each(
+    state: &mut State,
+    markdown: bool,
+    text_for: impl Fn(&Subscriber) -> Option<String>,
+) -> Vec<String> {
+    broadcast_to(state, markdown, None, |subscriber| text_for(subscriber).map(OutgoingMessage::text))
+}
+
+/// Like broadcast_each, for messages about a resource, which are posted in its forum topic in the
+/// chats its route has one for.
+pub(crate) fn broadcast_each_about(
+    state: &mut State,
+    resource_id: &str,
+    markdown: bool,
+    text_for: impl Fn(&Subscriber) -> Option<String>,
+) -> Vec<String> {
+    broadcast_to(state, markdown, Some(resource_id), |subscriber| {
+        text_for(subscriber).map(OutgoingMessage::text)
+    })
+}
+
+/// Send each active subscriber the message `message_for` returns for it, if any. Messages about a
+/// resource are posted in the chat's forum topic for it, if any. Returns the chats the message was delivered to.
+fn broadcast_to(
+    state: &mut State,
+    markdown: bool,
+    resource_id: Option<&str>,
+    message_for: impl Fn(&Subscriber) -> Option<OutgoingMessage>,
+) -> Vec<String> {
+    let mut delivered = Vec::new();
+    let subscribers = state.subscribers.iter_mut().filter(|subscriber| !subscriber.inactive && !subscriber.alerts_paused());
+    for subscriber in subscribers {
+        let Some(OutgoingMessage { text, reply_markup, silent }) = message_for(subscriber) else {
+            continue;
+        };
+        let message_thread_id = resource_id.and_then(|resource_id| CONFIG.message_thread_id(resource_id, &subscriber.chat_id));
+        // Throttled chats get the message later, together with the others queued for them.
+        if subscriber.throttled() {
+            tracing::info!(chat_id = %subscriber.chat_id, "Chat is throttled, queueing message");
+        } else {
+            std::thread::sleep(Duration::from_millis(CONFIG.send_interval_millis));
+            match deliver(subscriber, &text, markdown, reply_markup.as_deref(), silent, message_thread_id) {
+                Ok(()) => {
+                    delivered.push(subscriber.chat_id.clone());
+                    continue;
+                }
+                Err(error) if !error.is_temporary() => continue,
+                Err(_) => {}
+            }
+        }
+        state.outbox.push(QueuedMessage {
+            chat_id: subscriber.chat_id.clone(),
+            text,
+            markdown,
+            reply_markup,
+            silent,
+            message_thread_id,
+            queued_at: Local::now(),
+        });
+    }
+    delivered
+}
+
+/// Send the location of a resource's venue to the active subscribers the resource is routed to
+/// that `wants` it. Failures are only logged. Returns the chats it was delivered to.
+pub(crate) fn broadcast_venue(state: &State, resource_id: &str, unit: &Unit, wants: impl Fn(&Subscriber) -> bool) -> Vec<String> {
+    let mut delivered = Vec::new();
+    let subscribers = state.subscribers.iter().filter(|subscriber| !subscriber.inactive && !subscriber.alerts_paused());
+    for subscriber in subscribers.filter(|subscriber| CONFIG.routes_to(resource_id, subscriber) && wants(subscriber)) {
+        std::thread::sleep(Duration::from_millis(CONFIG.send_interval_millis));
+        match send_venue(&subscriber.chat_id, unit, CONFIG.message_thread_id(resource_id, &subscriber.chat_id)) {
+            Ok(()) => delivered.push(subscriber.chat_id.clone()),
+            Err(error) => tracing::warn!(chat_id = %subscriber.chat_id, %error, "Failed to send venue"),
+        }
+    }
+    delivered
+}
+
+/// Retry sending the messages in the outbox, chat by chat in the order they were queued.
+/// Stops at the first temporary error, since Telegram is then likely still unavailable.
+/// Messages to chats that are still throttled are kept for later.
+///
+/// A chat with a single queued message gets it as it was. A chat with several gets them together
+/// in one catch-up message, telling how many there are and since when. See catch_up.
+pub(crate) fn flush_outbox(state: &mut State) {
+    if state.outbox.is_empty() {
+        return;
+    }
+    tracing::info!(queued = state.outbox.len(), "Retrying queued messages");
+
+    let mut chats: Vec<Vec<QueuedMessage>> = Vec::new();
+    for message in std::mem::take(&mut state.outbox) {
+        match chats.iter_mut().find(|messages| messages[0].chat_id == message.chat_id) {
+            Some(messages) => messages.push(message),
+            None => chats.push(vec![message]),
+        }
+    }
+
+    let mut chats = chats.into_iter();
+    'chats: while let Some(messages) = chats.next() {
+        // Drop messages to chats that have unsubscribed or stopped accepting messages.
+        let chat_id = &messages[0].chat_id;
+        let Some(subscriber) = state.subscribers.iter_mut().find(|subscriber| {
+            subscriber.chat_id == *chat_id || subscriber.migrated_from.as_deref() == Some(chat_id)
+        }) else {
+            continue;
+        };
+        if subscriber.inactive {
+            continue;
+        }
+        // Messages to throttled chats wait until the chat may get one again.
+        if subscriber.throttled() {
+            state.outbox.extend(messages);
+            continue;
+        }
+
+        // A catch-up message rings if any of the messages it replaces would have.
+        let silent = messages.iter().all(|message| message.silent);
+        // It is posted in a forum topic only if all of them were in the same one.
+        let message_thread_id = messages[0]
+            .message_thread_id
+            .filter(|id| messages.iter().all(|message| message.message_thread_id == Some(*id)));
+        let (parts, markdown, reply_markup) = match messages.as_slice() {
+            [message] => (vec![(0, message.text.clone())], message.markdown, message.reply_markup.clone()),
+            _ => {
+                let (parts, markdown) = catch_up(&messages, subscriber.throttle_minutes.is_some());
+                (parts, markdown, None)
+            }
+        };
+        for (first, text) in parts {
+            std::thread::sleep(Duration::from_millis(CONFIG.send_interval_millis));
+            match deliver(subscriber, &text, markdown, reply_markup.as_deref(), silent, message_thread_id) {
+                Err(error) if error.is_temporary() => {
+                    state.outbox.extend(messages.into_iter().skip(first));
+                    state.outbox.extend(chats.flatten());
+                    break 'chats;
+                }
+                Err(_) => continue 'chats,
+                Ok(()) => {}
+            }
+        }
+        if messages.len() > 1 {
+            tracing::info!(chat_id = %subscriber.chat_id, missed = messages.len(), "Sent catch-up message");
+        }
+    }
+}
+
+/// Longest text Telegram accepts in a message.
+const MAX_MESSAGE_LENGTH: usize = 4096;
+
+/// Messages replacing the queued messages of a chat: a heading telling how many there are and
+/// since when, followed by their texts. `throttled` tells whether the chat has a throttle, which
+/// likely held the messages back instead of Telegram.
+///
+/// Texts that don't fit in one message continue in the next. Returns the messages, each with
+/// the index of the first queued message in it, and whether they are formatted with MarkdownV2,
+/// which they are if any of the queued messages was.
+fn catch_up(messages: &[QueuedMessage], throttled: bool) -> (Vec<(usize, String)>, bool) {
+    let since = messages.iter().map(|message| message.queued_at).min().unwrap_or_else(Local::now);
+    let heading = if throttled {
+        CONFIG.language.throttled_catch_up(since, messages.len())
+    } else {
+        CONFIG.language.catch_up(since, messages.len())
+    };
+    let markdown = messages.iter().any(|message| message.markdown);
+    let as_formatted = |text: &str, is_markdown: bool| {
+        if markdown && !is_markdown {
+            message::escape_markdown(text)
+        } else {
+            text.to_owned()
+        }
+    };
+
+    let mut parts = vec![(0, as_formatted(&heading, false))];
+    for (index, message) in messages.iter().enumerate() {
+        let text = as_formatted(message.text.trim_end(), message.markdown);
+        let (_, part) = parts.last_mut().unwrap();
+        if part.chars().count() + text.chars().count() + 2 <= MAX_MESSAGE_LENGTH {
+            part.push_str("\n\n");
+            part.push_str(&text);
+        } else {
+            parts.push((index, text));
+        }
+    }
+    (parts, markdown)
+}
+
+/// Send a text message to a subscriber.
+///
+/// If Telegram asks to slow down, waits the requested time and tries again a few times,
+/// unless the wait would be longer than `max_retry_after_secs`.
+///
+/// Subscribers whose chat has been migrated to a supergroup are updated
+/// with the new chat ID and the message is sent again.
+///
+/// MarkdownV2 messages Telegram can't parse are sent again as plain text, without the escapes.
+///
+/// Subscribers that refuse `max_send_failures` messages in a row (e.g. they blocked the bot)
+/// are marked inactive, and the operator chat is told about it if one is configured.
+fn deliver(
+    subscriber: &mut Subscriber,
+    text: &str,
+    markdown: bool,
+    reply_markup: Option<&str>,
+    silent: bool,
+    message_thread_id: Option<i64>,
+) -> Result<(), SendError> {
+    let _span = tracing::info_span!("deliver", chat_id = %subscriber.chat_id).entered();
+    let mut result = send_text(&subscriber.chat_id, text, markdown, reply_markup, silent, message_thread_id);
+    for _ in 0..MAX_RATE_LIMIT_RETRIES {
+        let Err(SendError::RateLimited(retry_after)) = result else {
+            break;
+        };
+        if retry_after > Duration::from_secs(CONFIG.max_retry_after_secs) {
+            break;
+        }
+        tracing::info!(retry_after = retry_after.as_secs(), "Rate limited by Telegram, waiting");
+        std::thread::sleep(retry_after);
+        result = send_text(&subscriber.chat_id, text, markdown, reply_markup, silent, message_thread_id);
+    }
+
+    if let Err(SendError::Migrated(new_chat_id)) = &result {
+        tracing::info!(chat_id = %subscriber.chat_id, %new_chat_id, "Chat migrated to supergroup");
+        subscriber.migrated_from = Some(std::mem::replace(&mut subscriber.chat_id, new_chat_id.clone()));
+        result = send_text(&subscriber.chat_id, text, markdown, reply_markup, silent, message_thread_id);
+    }
+
+    if let Err(SendError::Api { code: 400, description }) = &result {
+        if markdown && description.contains("can't parse entities") {
+            tracing::warn!(%description, "Telegram couldn't parse the message, sending it as plain text");
+            let plain = message::unescape_markdown(text);
+            result = send_text(&subscriber.chat_id, &plain, false, reply_markup, silent, message_thread_id);
+        }
+    }
+
+    match &result {
+        Ok(()) => {
+            subscriber.failures = 0;
+            subscriber.last_sent_at = Some(Local::now());
+        }
+        Err(SendError::Api { code: 403, description }) => {
+            subscriber.failures += 1;
+            tracing::warn!(chat_id = %subscriber.chat_id, %description, "Chat refused message");
+            if subscriber.failures >= CONFIG.max_send_failures {
+                deactivate(subscriber, description);
+            }
+        }
+        Err(error) => tracing::warn!(chat_id = %subscriber.chat_id, %error, "Failed to send message"),
+    }
+    result
+}
+
+/// Stop sending to a subscriber and report it to the operator chat.
+fn deactivate(subscriber: &mut Subscriber, reason: &str) {
+    subscriber.inactive = true;
+    let report = format!(
+        "Stopped sending to chat {} after {} refused messages: {}",
+        subscriber.chat_id, subscriber.failures, reason
+    );
+    tracing::warn!("{}", report);
+
+    if let Some(operator_chat_id) = &CONFIG.operator_chat_id {
+        if let Err(error) = send_message(operator_chat_id, &report) {
+            tracing::warn!(%error, "Failed to report to operator chat");
+        }
+    }
+}
+
+/// Fetch text messages sent to the bot and inline button taps since `offset`.
+/// Returns the messages and the offset to use for the next call.
+///
+/// Returns no messages and the original offset if the request fails,
+/// so a Telegram outage doesn't stop availability monitoring.
+pub(crate) fn get_updates(offset: i64) -> (Vec<Message>, i64) {
+    let response = client::client()
+        .get(api_url("getUpdates"))
+        .query(&[("offset", offset)])
+        .send()
+        .and_then(|response| response.text());
+    let updates: serde_json::Value = match response {
+        Ok(text) => serde_json::from_str(&text).unwrap_or_default(),
+        Err(error) => {
+            tracing::warn!(%error, "Failed to fetch Telegram updates");
+            return (Vec::new(), offset);
+        }
+    };
+
+    let mut messages: Vec<Message> = Vec::new();
+    let mut next_offset = offset;
+    for update in updates["result"].as_array().unwrap_or(&Vec::new()) {
+        if let Some(update_id) = update["update_id"].as_i64() {
+            next_offset = next_offset.max(update_id + 1);
+        }
+
+        // Button taps carry the message with the button, and the button's callback data.
+        let callback = &update["callback_query"];
+        if let (Some(id), Some(data)) = (callback["id"].as_str(), callback["data"].as_str()) {
+            let message = &callback["message"];
+            let (Some(chat_id), Some(message_id)) = (message["chat"]["id"].as_i64(), message["message_id"].as_i64()) else {
+                continue;
+            };
+            messages.push(Message {
+                chat_id: chat_id.to_string(),
+                chat_title: chat_title(&message["chat"]).to_owned(),
+                user_id: callback["from"]["id"].as_i64(),
+                text: data.to_owned(),
+                callback: Some(Callback {
+                    id: id.to_owned(),
+                    message_id,
+                }),
+            });
+            continue;
+        }
+
+        // Skip updates that aren't text messages
+        let message = &update["message"];
+        let (Some(chat_id), Some(text)) = (message["chat"]["id"].as_i64(), message["text"].as_str()) else {
+            continue;
+        };
+
+        messages.push(Message {
+            chat_id: chat_id.to_string(),
+            chat_title: chat_title(&message["chat"]).to_owned(),
+            user_id: message["from"]["id"].as_i64(),
+            text: text.to_owned(),
+            callback: None,
+        });
+    }
+
+    (messages, next_offset)
+}
+
+/// Group title, or the name of the user in a private chat.
+fn chat_title(chat: &serde_json::Value) -> &str {
+    chat["title"]
+        .as_str()
+        .or(chat["username"].as_str())
+        .or(chat["first_name"].as_str())
+        .unwrap_or_default()
+}
@@ -1,30 +1,64 @@
 use chrono::DateTime;
-use chrono::Local;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+use crate::i18n::Language;
+use crate::timezone;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct Timeslot {
     pub(crate) start: String,
     pub(crate) end: String,
 }
 
 impl Timeslot {
+    /// Length of the time in whole hours, rounded down.
     pub(crate) fn duration(&self) -> i64 {
         let duration = self.end_time() - self.start_time();
         duration.num_hours()
     }
 
-    pub(crate) fn start_time(&self) -> DateTime<Local> {
-        let start_time = DateTime::parse_from_rfc3339(&self.start)
-            .unwrap()
-            .with_timezone(&Local);
-        start_time
+    /// Length of the time in whole minutes.
+    pub(crate) fn duration_minutes(&self) -> i64 {
+        (self.end_time() - self.start_time()).num_minutes()
+    }
+
+    pub(crate) fn start_time(&self) -> DateTime<Tz> {
+        timezone::zoned(&DateTime::parse_from_rfc3339(&self.start).unwrap())
+    }
+
+    /// Format the timeslot for display in the given language, e.g.
+    /// "2023-12-01 10:00 - 11:30 (1 h 30 min)" or "1.12.2023 10:00 - 11:00 (1 t)".
+    /// A time ending on a later day repeats the date on the end side, e.g.
+    /// "2023-12-01 22:00 - 2023-12-02 02:00 (4 h)".
+    pub(crate) fn localized(&self, language: Language) -> String {
+        format!("{} {}", self.start_time().format(language.date_format()), self.localized_hours(language))
     }
 
-    pub(crate) fn end_time(&self) -> DateTime<Local> {
-        let end_time = DateTime::parse_from_rfc3339(&self.end)
-            .unwrap()
-            .with_timezone(&Local);
-        end_time
+    /// Format the timeslot without its date, for listing under the date, e.g.
+    /// "10:00 - 11:30 (1 h 30 min)". The date is still shown on the end side if it's later.
+    pub(crate) fn localized_hours(&self, language: Language) -> String {
+        let (start, end) = (self.start_time(), self.end_time());
+        let end_format = if end.date_naive() == start.date_naive() {
+            "%H:%M".to_owned()
+        } else {
+            format!("{} %H:%M", language.date_format())
+        };
+        format!(
+            "{} - {} ({})",
+            start.format("%H:%M"),
+            end.format(&end_format),
+            language.duration(self.duration_minutes())
+        )
+    }
+
+    pub(crate) fn end_time(&self) -> DateTime<Tz> {
+        timezone::zoned(&DateTime::parse_from_rfc3339(&self.end).unwrap())
+    }
+
+    /// Return true if `other` is within this time, e.g. a part of it.
+    pub(crate) fn covers(&self, other: &Timeslot) -> bool {
+        self.start_time() <= other.start_time() && other.end_time() <= self.end_time()
     }
 }
 
@@ -37,110 +71,86 @@ impl std::cmp::PartialEq for Timeslot {
 impl std::fmt::Display for Timeslot {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Example output:
-        // "2023-12-01 10:00 - 11:00 (1 h)"
-        write!(
-            f,
-            "{} - {} ({} h)",
-            self.start_time().format("%Y-%m-%d %H:%M"),
-            self.end_time().format("%H:%M"),
-            self.duration()
-        )
+        // "2023-12-01 10:00 - 11:30 (1 h 30 min)"
+        write!(f, "{}", self.localized(Language::En))
     }
 }
 
-/// Get all remaining available times from opening times and reservations.
-/// Returns a vector of Timeslot structs.
-///
-/// # Example
-/// ```
-/// let opening_times = vec![
-///     Timeslot {
-///         start: "2021-09-01T08:00:00+03:00".to_owned(),
-///         end: "2021-09-01T16:00:00+03:00".to_owned(),
-///     },
-/// ];
-///
-/// let reservations = vec![
-///     Timeslot {
-///         start: "2021-09-01T10:00:00+03:00".to_owned(),
-///         end: "2021-09-01T11:00:00+03:00".to_owned(),
-///     }
-/// ];
-///
-/// let available_times = get_available_times(&opening_times, &reservations);
-///
-/// // Expected output:
-/// assert_eq!(available_times.len(), 2);
-/// assert_eq!(available_times[0].start, "2021-09-01T08:00:00+03:00");
-/// assert_eq!(available_times[0].end, "2021-09-01T10:00:00+03:00");
-/// assert_eq!(available_times[1].start, "2021-09-01T11:00:00+03:00");
-/// assert_eq!(available_times[1].end, "2021-09-01T16:00:00+03:00");
-/// ```
-pub(crate) fn get_available_times(
-    opening_times: &Vec<Timeslot>,
-    reservations: &Vec<Timeslot>,
-) -> Vec<Timeslot> {
-    // Iterate over each hour in opening times.
-    // If the hour is not in reservations, add it to available times.
-    let mut available_times: Vec<Timeslot> = Vec::new();
-    for opening_time in opening_times {
-        // Get start and end time of opening time
-        let start_time = opening_time.start_time();
-        let end_time = opening_time.end_time();
-
-        // Iterate over each hour in opening time
-        let mut current_time = start_time;
-        while current_time < end_time {
-            // Check if current time is in reservations
-            let mut is_reserved = false;
-            for reservation in reservations {
-                if current_time >= reservation.start_time() && current_time < reservation.end_time()
-                {
-                    is_reserved = true;
-                    break;
-                }
-            }
+/// A block of available time. Short reservations inside the block are listed in `gaps`.
+pub(crate) struct Block {
+    pub(crate) slot: Timeslot,
+    pub(crate) gaps: Vec<Timeslot>,
+}
 
-            // If current time is not in reservations, add it to available times
-            if !is_reserved {
-                let timeslot = Timeslot {
-                    start: current_time.to_rfc3339(),
-                    end: (current_time + chrono::Duration::hours(1)).to_rfc3339(),
-                };
-                available_times.push(timeslot);
-            }
+impl Block {
+    /// Format the block for display in the given language, e.g.
+    /// "2023-12-01 10:00 - 14:00 (4 h), reserved 12:00 - 12:15"
+    pub(crate) fn localized(&self, language: Language) -> String {
+        self.with_gaps(self.slot.localized(language), language)
+    }
+
+    /// Format the block without its date, e.g. "10:00 - 14:00 (4 h), reserved 12:00 - 12:15"
+    pub(crate) fn localized_hours(&self, language: Language) -> String {
+        self.with_gaps(self.slot.localized_hours(language), language)
+    }
 
-            // Increment current time by 1 hour
-            current_time = current_time + chrono::Duration::hours(1);
+    fn with_gaps(&self, mut text: String, language: Language) -> String {
+        for gap in &self.gaps {
+            text.push_str(&format!(
+                ", {} {} - {}",
+                language.reserved(),
+                gap.start_time().format("%H:%M"),
+                gap.end_time().format("%H:%M")
+            ));
         }
+        text
     }
+}
 
-    // Combine 1 hour timeslots into longer timeslots.
-    let mut combined_timeslots: Vec<Timeslot> = Vec::new();
-    let mut current_timeslot: Option<Timeslot> = None;
-
-    for timeslot in available_times {
-        if let Some(current) = current_timeslot {
-            if current.end_time() == timeslot.start_time() {
-                // Extend the current timeslot
-                current_timeslot = Some(Timeslot {
-                    start: current.start,
-                    end: timeslot.end,
+/// Combine available times separated by gaps of at most `max_gap` into single blocks.
+/// The times must be sorted by start time. With a zero `max_gap` each time becomes its own block.
+///
+/// This lets e.g. a 15 minute buffer booking between two free blocks be treated as one longer block.
+pub(crate) fn bridge_gaps(times: &[Timeslot], max_gap: chrono::Duration) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for time in times {
+        if let Some(block) = blocks.last_mut() {
+            let gap = time.start_time() - block.slot.end_time();
+            if gap > chrono::Duration::zero() && gap <= max_gap {
+                // Extend the current block over the gap
+                block.gaps.push(Timeslot {
+                    start: block.slot.end.clone(),
+                    end: time.start.clone(),
                 });
-            } else {
-                // Add the current timeslot to the combined timeslots
-                combined_timeslots.push(current);
-                current_timeslot = Some(timeslot);
+                block.slot.end = time.end.clone();
+                continue;
             }
-        } else {
-            current_timeslot = Some(timeslot);
         }
+
+        blocks.push(Block {
+            slot: time.clone(),
+            gaps: Vec::new(),
+        });
     }
 
-    // Add the last timeslot to the combined timeslots
-    if let Some(current) = current_timeslot {
-        combined_timeslots.push(current);
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn slot(start: (u32, u32, u32), end: (u32, u32, u32)) -> Timeslot {
+        let at = |(day, hour, minute)| timezone::zone().with_ymd_and_hms(2023, 12, day, hour, minute, 0).unwrap().to_rfc3339();
+        Timeslot { start: at(start), end: at(end) }
     }
 
-    return combined_timeslots;
+    #[test]
+    fn shows_minutes_and_the_end_date_of_multi_day_times() {
+        assert_eq!(slot((1, 10, 0), (1, 11, 30)).to_string(), "2023-12-01 10:00 - 11:30 (1 h 30 min)");
+        assert_eq!(slot((1, 10, 0), (1, 10, 45)).to_string(), "2023-12-01 10:00 - 10:45 (45 min)");
+        assert_eq!(slot((1, 22, 0), (2, 2, 0)).to_string(), "2023-12-01 22:00 - 2023-12-02 02:00 (4 h)");
+        assert_eq!(slot((1, 10, 0), (1, 12, 15)).localized(Language::Fi), "1.12.2023 10:00 - 12:15 (2 t 15 min)");
+    }
 }
@@ -0,0 +1,64 @@
+use chrono::{DateTime, LocalResult, NaiveDate, NaiveTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::CONFIG;
+
+/// Time zone used when none is configured.
+pub(crate) const DEFAULT: Tz = chrono_tz::Europe::Helsinki;
+
+/// The configured time zone. Unit tests use the default, so they don't need a configuration.
+pub(crate) fn zone() -> Tz {
+    if cfg!(test) {
+        DEFAULT
+    } else {
+        CONFIG.timezone
+    }
+}
+
+/// The current time in the configured time zone.
+pub(crate) fn now() -> DateTime<Tz> {
+    Utc::now().with_timezone(&zone())
+}
+
+/// The same time in the configured time zone, for showing it or taking its date.
+pub(crate) fn zoned<T: TimeZone>(time: &DateTime<T>) -> DateTime<Tz> {
+    time.with_timezone(&zone())
+}
+
+/// The time on `date` at the wall-clock time `time` in the configured time zone.
+///
+/// On the day clocks are turned forward, a time in the skipped hour is taken as if the clocks
+/// hadn't been turned yet, so 03:30 becomes 04:30. On the day they are turned back, a time that
+/// occurs twice is the first one.
+pub(crate) fn at(date: NaiveDate, time: NaiveTime) -> DateTime<Tz> {
+    let local = date.and_time(time);
+    let zone = zone();
+    match zone.from_local_datetime(&local) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => time,
+        LocalResult::None => {
+            let offset_before = zone.offset_from_utc_datetime(&(local - chrono::Days::new(1))).fix();
+            zone.from_utc_datetime(&(local - offset_before))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(date: &str, time: &str) -> String {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        at(date, NaiveTime::parse_from_str(time, "%H:%M").unwrap()).to_rfc3339()
+    }
+
+    #[test]
+    fn handles_times_skipped_or_repeated_on_dst_changes() {
+        // Clocks are turned from 03:00 to 04:00.
+        assert_eq!(local("2024-03-31", "02:30"), "2024-03-31T02:30:00+02:00");
+        assert_eq!(local("2024-03-31", "03:30"), "2024-03-31T04:30:00+03:00");
+        assert_eq!(local("2024-03-31", "04:30"), "2024-03-31T04:30:00+03:00");
+        // Clocks are turned from 04:00 back to 03:00.
+        assert_eq!(local("2024-10-27", "03:30"), "2024-10-27T03:30:00+03:00");
+        assert_eq!(local("2024-10-27", "04:30"), "2024-10-27T04:30:00+02:00");
+    }
+}
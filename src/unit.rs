@@ -0,0 +1,129 @@
+use chrono::Local;
+use serde::Deserialize;
+
+use crate::respa::{self, FoundResource};
+use crate::state::State;
+use crate::CONFIG;
+
+/// How often the resources of the monitored unit are listed again, to pick up new ones.
+const DISCOVERY_INTERVAL_HOURS: i64 = 1;
+
+/// A unit, e.g. Oodi's urban workshop, whose reservable resources are all monitored along with
+/// `resource_id`:
+///
+/// ```toml
+/// [unit]
+/// id = "tprek:51342"
+/// equipment = ["laser"]
+/// ```
+///
+/// With `equipment` or `types`, only resources with equipment or a type whose name contains
+/// one of the words are monitored.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct UnitConfig {
+    pub(crate) id: String,
+    pub(crate) equipment: Vec<String>,
+    pub(crate) types: Vec<String>,
+}
+
+impl UnitConfig {
+    fn matches(&self, resource: &FoundResource) -> bool {
+        let contains_any = |words: &[String], name: &str| {
+            let name = name.to_lowercase();
+            words.iter().any(|word| name.contains(&word.to_lowercase()))
+        };
+        let equipment = self.equipment.is_empty() || resource.equipment.iter().any(|name| contains_any(&self.equipment, name));
+        let types = self.types.is_empty() || resource.resource_type.as_deref().is_some_and(|name| contains_any(&self.types, name));
+        equipment && types
+    }
+}
+
+/// IDs of the resources to monitor: `resource_id` followed by the resources of the unit, if
+/// one is configured. The unit is listed again every DISCOVERY_INTERVAL_HOURS, and if listing
+/// fails, the previously found resources are used.
+pub(crate) fn monitored_resources(state: &mut State) -> Vec<String> {
+    let Some(unit) = &CONFIG.unit else {
+        return vec![CONFIG.resource_id.clone()];
+    };
+
+    let now = Local::now();
+    let due = state
+        .unit_discovered_at
+        .is_none_or(|discovered_at| now - discovered_at >= chrono::Duration::hours(DISCOVERY_INTERVAL_HOURS));
+    if due {
+        match respa::unit_resources(&unit.id) {
+            Ok(resources) => {
+                let found = resources
+                    .iter()
+                    .filter(|resource| unit.matches(resource))
+                    .map(|resource| resource.id.clone())
+                    .collect::<Vec<String>>();
+                for resource_id in found.iter().filter(|id| !state.unit_resources.contains(id)) {
+                    tracing::info!(unit = %unit.id, %resource_id, "Found resource in unit");
+                }
+                state.unit_available_times.retain(|resource_id, _| found.contains(resource_id));
+                state.unit_resources = found;
+                state.unit_discovered_at = Some(now);
+            }
+            Err(error) => tracing::warn!(unit = %unit.id, %error, "Failed to list the resources of the unit"),
+        }
+    }
+
+    resource_ids(state)
+}
+
+/// IDs of the monitored resources as last discovered: `resource_id` followed by the resources of
+/// the unit.
+pub(crate) fn resource_ids(state: &State) -> Vec<String> {
+    let mut resource_ids = vec![CONFIG.resource_id.clone()];
+    for resource_id in &state.unit_resources {
+        if !resource_ids.contains(resource_id) {
+            resource_ids.push(resource_id.clone());
+        }
+    }
+    resource_ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(resource_type: Option<&str>, equipment: &[&str]) -> FoundResource {
+        FoundResource {
+            id: "axwzr3i57yba".to_owned(),
+            name: "Laserleikkuri".to_owned(),
+            unit: None,
+            resource_type: resource_type.map(str::to_owned),
+            equipment: equipment.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn matches_equipment_and_types_by_words() {
+        let laser = resource(Some("Työpiste"), &["Epilog Laser Fusion", "Tietokone"]);
+        let printer = resource(Some("Työpiste"), &["3D-tulostin"]);
+        let room = resource(None, &[]);
+
+        let config = |equipment: &[&str], types: &[&str]| UnitConfig {
+            id: "tprek:51342".to_owned(),
+            equipment: equipment.iter().map(|word| word.to_string()).collect(),
+            types: types.iter().map(|word| word.to_string()).collect(),
+        };
+        let everything = config(&[], &[]);
+        assert!(everything.matches(&laser) && everything.matches(&room));
+
+        let lasers = config(&["laser"], &[]);
+        assert!(lasers.matches(&laser));
+        assert!(!lasers.matches(&printer));
+        assert!(!lasers.matches(&room));
+
+        let workstations = config(&[], &["TYÖPISTE"]);
+        assert!(workstations.matches(&printer));
+        assert!(!workstations.matches(&room));
+
+        // Both have to match.
+        assert!(config(&["laser"], &["työpiste"]).matches(&laser));
+        assert!(!config(&["laser"], &["kokoustila"]).matches(&laser));
+    }
+}
@@ -0,0 +1,96 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Notifier;
+use crate::i18n::Language;
+use crate::message::Resource;
+use crate::state::State;
+use crate::telegram;
+use crate::timeslot::Timeslot;
+use crate::timezone;
+use crate::CONFIG;
+
+/// A time window a chat would like to book, set with "/watch 2024-03-15 17:00-20:00".
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct Watch {
+    pub(crate) date: NaiveDate,
+    pub(crate) from: NaiveTime,
+    pub(crate) to: NaiveTime,
+}
+
+impl Watch {
+    /// Parse the arguments of /watch, a date and a time range, e.g. `["2024-03-15", "17:00-20:00"]`.
+    pub(crate) fn parse(arguments: &[&str]) -> Option<Watch> {
+        let [date, range] = arguments else {
+            return None;
+        };
+        let (from, to) = range.split_once('-')?;
+        let watch = Watch {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?,
+            from: NaiveTime::parse_from_str(from, "%H:%M").ok()?,
+            to: NaiveTime::parse_from_str(to, "%H:%M").ok()?,
+        };
+        (watch.from < watch.to).then_some(watch)
+    }
+
+    fn start(&self) -> NaiveDateTime {
+        self.date.and_time(self.from)
+    }
+
+    fn end(&self) -> NaiveDateTime {
+        self.date.and_time(self.to)
+    }
+
+    /// Return true if any part of `time` is within the window.
+    pub(crate) fn overlaps(&self, time: &Timeslot) -> bool {
+        time.start_time().naive_local() < self.end() && self.start() < time.end_time().naive_local()
+    }
+
+    /// Return true if the window has ended.
+    pub(crate) fn has_passed(&self, now: NaiveDateTime) -> bool {
+        self.end() <= now
+    }
+
+    /// Format the window for display, e.g. "2024-03-15 17:00 - 20:00".
+    pub(crate) fn localized(&self, language: Language) -> String {
+        format!(
+            "{} {} - {}",
+            self.date.format(language.date_format()),
+            self.from.format("%H:%M"),
+            self.to.format("%H:%M")
+        )
+    }
+}
+
+/// Tell chats about new times within the windows they watch, if the resource is routed to them,
+/// and forget windows that have ended.
+pub(crate) fn notify(state: &mut State, resource: &Resource, new_times: &[Timeslot]) {
+    let now = timezone::now().naive_local();
+    for subscriber in &mut state.subscribers {
+        subscriber.watches.retain(|watch| !watch.has_passed(now));
+    }
+    if new_times.is_empty() || !CONFIG.notifiers_for(&resource.id).contains(&Notifier::Telegram) {
+        return;
+    }
+
+    telegram::broadcast_each_about(state, &resource.id, false, |subscriber| {
+        if !CONFIG.routes_to(&resource.id, subscriber) {
+            return None;
+        }
+        let mut text = String::new();
+        for watch in &subscriber.watches {
+            let times = new_times.iter().filter(|time| watch.overlaps(time)).collect::<Vec<&Timeslot>>();
+            if times.is_empty() {
+                continue;
+            }
+            tracing::info!(chat_id = %subscriber.chat_id, watch = %watch.localized(CONFIG.language), "Watched time is free");
+            text.push_str(&CONFIG.language.watched_time_free(&watch.localized(CONFIG.language), &resource.name));
+            text.push('\n');
+            for time in times {
+                text.push_str(&time.localized(CONFIG.language));
+                text.push('\n');
+            }
+        }
+        (!text.is_empty()).then_some(text)
+    });
+}
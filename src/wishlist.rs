@@ -0,0 +1,111 @@
+use chrono::{DateTime, NaiveTime, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::availability::IntervalSet;
+use crate::holidays;
+use crate::scheduler::deserialize_optional_time;
+use crate::timeslot::Timeslot;
+use crate::timezone;
+use crate::CONFIG;
+
+/// A kind of time worth its own alert, e.g. any weekday evening with at least two consecutive
+/// free hours in the next two weeks:
+///
+/// ```toml
+/// [[wishlist]]
+/// name = "Weekday evening"
+/// weekdays = ["mon", "tue", "wed", "thu", "fri"]
+/// from = "17:00"
+/// to = "21:00"
+/// min_hours = 2
+/// within_days = 14
+/// ```
+///
+/// Without `weekdays` every day matches, and without `from` and `to` the whole day.
+#[derive(Deserialize)]
+pub(crate) struct WishlistEntry {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) weekdays: Vec<Weekday>,
+    #[serde(default, deserialize_with = "deserialize_optional_time")]
+    pub(crate) from: Option<NaiveTime>,
+    #[serde(default, deserialize_with = "deserialize_optional_time")]
+    pub(crate) to: Option<NaiveTime>,
+    /// Consecutive free hours needed within the window.
+    #[serde(default = "default_min_hours")]
+    pub(crate) min_hours: i64,
+    /// Only times starting within this many days from now match.
+    pub(crate) within_days: Option<u64>,
+}
+
+fn default_min_hours() -> i64 {
+    1
+}
+
+impl WishlistEntry {
+    /// The part of an available time within the entry's window, if it is long enough.
+    fn matching_part(&self, time: &Timeslot, now: DateTime<Tz>) -> Option<Timeslot> {
+        let date = time.start_time().date_naive();
+        if !holidays::matches(&self.weekdays, date) {
+            return None;
+        }
+        if self.within_days.is_some_and(|days| time.start_time() > now + chrono::Days::new(days)) {
+            return None;
+        }
+
+        let window_start = match self.from {
+            Some(from) => timezone::at(date, from),
+            None => time.start_time(),
+        };
+        let window_end = match self.to {
+            Some(to) => timezone::at(date, to),
+            None => time.end_time(),
+        };
+        let part = IntervalSet::between(time.start_time(), time.end_time())
+            .intersection(&IntervalSet::between(window_start, window_end))
+            .to_timeslots()
+            .pop()?;
+        if (part.end_time() - part.start_time()).num_minutes() < self.min_hours * 60 {
+            return None;
+        }
+        Some(part)
+    }
+}
+
+/// Messages for the wishlist entries matched by new times, one per entry. See alert.
+pub(crate) fn alerts(resource_name: &str, new_times: &[Timeslot], available_times: &[Timeslot]) -> Vec<String> {
+    CONFIG
+        .wishlist
+        .iter()
+        .filter_map(|entry| alert(entry, resource_name, new_times, available_times))
+        .collect()
+}
+
+/// Message about the times matching a wishlist entry, if new times made any match.
+///
+/// Entries are matched against the whole available block each new time belongs to, so a block
+/// that grows into two consecutive hours matches an entry needing two hours.
+pub(crate) fn alert(
+    entry: &WishlistEntry,
+    resource_name: &str,
+    new_times: &[Timeslot],
+    available_times: &[Timeslot],
+) -> Option<String> {
+    let now = timezone::now();
+    let parts = available_times
+        .iter()
+        .filter(|block| new_times.iter().any(|time| block.covers(time)))
+        .filter_map(|block| entry.matching_part(block, now))
+        .collect::<Vec<Timeslot>>();
+    if parts.is_empty() {
+        return None;
+    }
+    tracing::info!(entry = %entry.name, count = parts.len(), "Wishlist matched");
+    let mut alert = CONFIG.language.wishlist_matched(&entry.name, resource_name);
+    for part in parts {
+        alert.push('\n');
+        alert.push_str(&part.localized(CONFIG.language));
+    }
+    Some(alert)
+}
@@ -0,0 +1,276 @@
+//! Runs the binary against an embedded mock of the respa and Telegram APIs, and checks which
+//! Telegram messages a check sends.
+
+use chrono::{DateTime, Days, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+const RESOURCE_ID: &str = "axwzr3i57yba";
+const BOT_TOKEN: &str = "test-token";
+const CHAT_ID: &str = "42";
+/// Time zone of the bot, different from the UTC most test machines run in.
+const TIMEZONE: Tz = chrono_tz::America::New_York;
+
+/// A mock respa and Telegram API on a local port. Serves `resource` for the resource, accepts any
+/// message and records each sent message.
+struct MockServer {
+    url: String,
+    resource: Arc<Mutex<serde_json::Value>>,
+    sent: Arc<Mutex<Vec<Sent>>>,
+}
+
+/// A message sent to the mock Telegram API.
+#[derive(Debug, PartialEq)]
+struct Sent {
+    chat_id: String,
+    message_thread_id: Option<String>,
+    text: String,
+}
+
+impl MockServer {
+    fn start() -> MockServer {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let resource = Arc::new(Mutex::new(json!({})));
+        let sent = Arc::new(Mutex::new(Vec::new()));
+
+        let (served, recorded) = (resource.clone(), sent.clone());
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let url = reqwest::Url::parse(&format!("http://mock{}", request.url())).unwrap();
+                let query = |name: &str| url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned());
+                let body = match url.path() {
+                    path if path == format!("/respa/v1/resource/{}/", RESOURCE_ID) => served.lock().unwrap().to_string(),
+                    path if path == format!("/bot{}/getUpdates", BOT_TOKEN) => json!({ "ok": true, "result": [] }).to_string(),
+                    path if path == format!("/bot{}/sendMessage", BOT_TOKEN) => {
+                        recorded.lock().unwrap().push(Sent {
+                            chat_id: query("chat_id").unwrap_or_default(),
+                            message_thread_id: query("message_thread_id"),
+                            text: query("text").unwrap_or_default(),
+                        });
+                        json!({ "ok": true, "result": {} }).to_string()
+                    }
+                    _ => {
+                        request.respond(tiny_http::Response::from_string("Not found").with_status_code(404)).ok();
+                        continue;
+                    }
+                };
+                request.respond(tiny_http::Response::from_string(body)).ok();
+            }
+        });
+        MockServer { url, resource, sent }
+    }
+
+    /// Serve the resource open from 10 to 18 tomorrow, with the given reservations.
+    fn serve_resource(&self, reservations: &[(u32, u32)]) {
+        let reservations = reservations
+            .iter()
+            .map(|(from, to)| json!({ "begin": tomorrow_at(*from).to_rfc3339(), "end": tomorrow_at(*to).to_rfc3339() }))
+            .collect::<Vec<serde_json::Value>>();
+        *self.resource.lock().unwrap() = json!({
+            "id": RESOURCE_ID,
+            "name": { "en": "Laser cutter" },
+            "opening_hours": [{
+                "date": tomorrow_at(0).format("%Y-%m-%d").to_string(),
+                "opens": tomorrow_at(10).to_rfc3339(),
+                "closes": tomorrow_at(18).to_rfc3339(),
+            }],
+            "reservations": reservations,
+        });
+    }
+
+    /// Texts of the messages sent since the previous call, all of which went to CHAT_ID.
+    fn take_sent(&self) -> Vec<String> {
+        self.take_sent_to()
+            .into_iter()
+            .map(|sent| {
+                assert_eq!(sent.chat_id, CHAT_ID);
+                assert_eq!(sent.message_thread_id, None);
+                sent.text
+            })
+            .collect()
+    }
+
+    /// Messages sent to any chat since the previous call.
+    fn take_sent_to(&self) -> Vec<Sent> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+}
+
+fn tomorrow_at(hour: u32) -> DateTime<Tz> {
+    let date = Utc::now().with_timezone(&TIMEZONE).date_naive() + Days::new(1);
+    TIMEZONE.from_local_datetime(&date.and_time(NaiveTime::from_hms_opt(hour, 0, 0).unwrap())).earliest().unwrap()
+}
+
+/// The notification about new available times tomorrow, listed under the day's heading.
+fn notification(times: &[(u32, u32)]) -> String {
+    let total = times.iter().map(|(from, to)| to - from).sum::<u32>();
+    let count = match times.len() {
+        1 => "1 new slot".to_owned(),
+        count => format!("{} new slots", count),
+    };
+    let mut text = format!("New available times:\n{}, total {} h\n\n{}\n", count, total, tomorrow_at(0).format("%A %-d.%-m."));
+    for (from, to) in times {
+        text.push_str(&format!("{:02}:00 - {:02}:00 ({} h)\n", from, to, to - from));
+    }
+    text
+}
+
+/// A fresh working directory with a config pointing at the mock server, followed by `extra_config`.
+fn work_dir(name: &str, server: &MockServer, extra_config: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("laser-rust-{}-{}", name, std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = format!(
+        "resource_id = \"{}\"\nrespa_url = \"{}/respa/v1\"\ntelegram_api_url = \"{}\"\nsend_interval_millis = 0\ntimezone = \"{}\"\n{}",
+        RESOURCE_ID, server.url, server.url, TIMEZONE, extra_config
+    );
+    std::fs::write(dir.join("laser.toml"), config).unwrap();
+    dir
+}
+
+/// Run one check with `--once` and return its exit code.
+fn run_once(dir: &Path) -> i32 {
+    let output = Command::new(env!("CARGO_BIN_EXE_laser-rust"))
+        .arg("--once")
+        .arg("--state-dir")
+        .arg(dir)
+        .current_dir(dir)
+        .env("LASER_CONFIG", dir.join("laser.toml"))
+        .env("TELEGRAM_BOT_TOKEN", BOT_TOKEN)
+        .env("TELEGRAM_CHAT_ID", CHAT_ID)
+        .env_remove("LASER_FAULTS")
+        .env_remove("RESPA_API_TOKEN")
+        .output()
+        .unwrap();
+    output.status.code().unwrap()
+}
+
+#[test]
+fn announces_grown_block_in_full_with_exact_diff() {
+    let server = MockServer::start();
+    let dir = work_dir("exact", &server, "");
+
+    // Without a saved state, everything available is new.
+    server.serve_resource(&[(12, 14), (15, 16)]);
+    assert_eq!(run_once(&dir), 2);
+    assert_eq!(server.take_sent(), vec![notification(&[(10, 12), (14, 15), (16, 18)])]);
+
+    // Nothing changed.
+    assert_eq!(run_once(&dir), 0);
+    assert_eq!(server.take_sent(), Vec::<String>::new());
+
+    // The reservation from 12 to 14 is cancelled, joining two blocks into a new one.
+    server.serve_resource(&[(15, 16)]);
+    assert_eq!(run_once(&dir), 2);
+    assert_eq!(server.take_sent(), vec![notification(&[(10, 15)])]);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn announces_only_freed_time_with_interval_diff() {
+    let server = MockServer::start();
+    let dir = work_dir("interval", &server, "\n[diff]\nstrategy = \"interval\"\n");
+
+    server.serve_resource(&[(12, 14)]);
+    assert_eq!(run_once(&dir), 2);
+    assert_eq!(server.take_sent(), vec![notification(&[(10, 12), (14, 18)])]);
+
+    // The reservation from 12 to 14 is cancelled.
+    server.serve_resource(&[]);
+    assert_eq!(run_once(&dir), 2);
+    assert_eq!(server.take_sent(), vec![notification(&[(12, 14)])]);
+
+    // Time is booked, which adds nothing.
+    server.serve_resource(&[(10, 11)]);
+    assert_eq!(run_once(&dir), 0);
+    assert_eq!(server.take_sent(), Vec::<String>::new());
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn announces_new_times_only_when_confirmed_by_next_check() {
+    let server = MockServer::start();
+    // The exact diff announces the blocks left after the reservation is back as new, so the times
+    // are only kept from being announced twice by dedup_minutes.
+    let dir = work_dir("confirm", &server, "confirm_new_times = true\ndedup_minutes = 60\n");
+
+    server.serve_resource(&[(12, 14)]);
+    assert_eq!(run_once(&dir), 0);
+    assert_eq!(server.take_sent(), Vec::<String>::new());
+
+    // Still available, so the times are announced.
+    assert_eq!(run_once(&dir), 2);
+    assert_eq!(server.take_sent(), vec![notification(&[(10, 12), (14, 18)])]);
+
+    // The reservation from 12 to 14 disappears for one check only.
+    server.serve_resource(&[]);
+    assert_eq!(run_once(&dir), 0);
+    server.serve_resource(&[(12, 14)]);
+    assert_eq!(run_once(&dir), 0);
+    assert_eq!(server.take_sent(), Vec::<String>::new());
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn batches_notifications_to_throttled_chat() {
+    let server = MockServer::start();
+    let dir = work_dir("throttle", &server, "");
+
+    server.serve_resource(&[(11, 12), (14, 16)]);
+    assert_eq!(run_once(&dir), 2);
+    assert_eq!(server.take_sent(), vec![notification(&[(10, 11), (12, 14), (16, 18)])]);
+
+    // The chat sends /throttle 1h, so it got its latest message too recently for another one.
+    let set_subscriber = |key: &str, value: serde_json::Value| {
+        let path = dir.join("state.json");
+        let mut state: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        state["subscribers"][0][key] = value;
+        std::fs::write(&path, state.to_string()).unwrap();
+    };
+    set_subscriber("throttle_minutes", json!(60));
+    server.serve_resource(&[(14, 16)]);
+    assert_eq!(run_once(&dir), 2);
+    server.serve_resource(&[]);
+    assert_eq!(run_once(&dir), 2);
+    assert_eq!(server.take_sent(), Vec::<String>::new());
+
+    // An hour later, both notifications are sent as one.
+    set_subscriber("last_sent_at", json!((Utc::now() - chrono::Duration::hours(2)).to_rfc3339()));
+    assert_eq!(run_once(&dir), 0);
+    let sent = server.take_sent();
+    assert_eq!(sent.len(), 1);
+    let (heading, notifications) = sent[0].split_once("\n\n").unwrap();
+    assert!(heading.starts_with("2 notifications held back since"), "{}", heading);
+    let expected = format!("{}\n\n{}", notification(&[(10, 14)]).trim_end(), notification(&[(10, 18)]).trim_end());
+    assert_eq!(notifications, expected);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn posts_in_forum_topic_only_in_chats_with_one() {
+    let server = MockServer::start();
+    let dir = work_dir(
+        "topics",
+        &server,
+        &format!("\n[routes.{}]\nchats = [\"{}\", \"-1001\"]\ntopics = {{ \"-1001\" = 7 }}\n", RESOURCE_ID, CHAT_ID),
+    );
+
+    server.serve_resource(&[(12, 14)]);
+    assert_eq!(run_once(&dir), 2);
+    let sent = |chat_id: &str, message_thread_id: Option<&str>| Sent {
+        chat_id: chat_id.to_owned(),
+        message_thread_id: message_thread_id.map(str::to_owned),
+        text: notification(&[(10, 12), (14, 18)]),
+    };
+    assert_eq!(server.take_sent_to(), vec![sent(CHAT_ID, None), sent("-1001", Some("7"))]);
+
+    std::fs::remove_dir_all(&dir).ok();
+}